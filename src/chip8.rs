@@ -1,22 +1,44 @@
+pub mod analysis;
+pub mod patch;
+pub mod romdb;
+
 use anyhow::Result;
 use rand::{Rng, SeedableRng, rngs::StdRng};
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     fs,
+    io::{BufWriter, Write},
+    path::Path,
     time::{SystemTime, UNIX_EPOCH},
 };
 
+// XO-CHIP's 64KB. Classic ROMs only ever touch the first 4KB.
 #[allow(dead_code)]
-const MEMORY_SIZE: usize = 4096;
+const MEMORY_SIZE: usize = 0x10000;
 const REGISTER_COUNT: usize = 16;
 const STACK_SIZE: usize = 16;
 const KEY_COUNT: usize = 16;
 const VIDEO_WIDTH: usize = 64;
 const VIDEO_HEIGHT: usize = 32;
 const VIDEO_SIZE: usize = VIDEO_WIDTH * VIDEO_HEIGHT;
+// SUPER-CHIP high-resolution mode
+const HIRES_VIDEO_WIDTH: usize = 128;
+const HIRES_VIDEO_HEIGHT: usize = 64;
+const HIRES_VIDEO_SIZE: usize = HIRES_VIDEO_WIDTH * HIRES_VIDEO_HEIGHT;
+
+// Display values for XO-CHIP's extra colors, see copy_display_to_buffer. Small so that
+// DisplayMode::Palette's `value & 7` picks entries 2 and 3.
+const PLANE2_PIXEL: u32 = 2;
+const BOTH_PLANES_PIXEL: u32 = 3;
 
 const START_ADDRESS: u16 = 0x200;
+const TRACE_FLUSH_INTERVAL: u64 = 256; // Instructions between trace file flushes
+const STEP_OVER_MAX_CYCLES: u64 = 1_000_000; // For subroutines that never return
+const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
 const FONTSET_SIZE: usize = 80;
 const FONTSET_START_ADDRESS: u16 = 0x50;
+const FONT_GLYPH_HEIGHT: usize = 5;
+const FONT_ATLAS_GLYPH_SIZE: usize = FONT_GLYPH_HEIGHT * 8; // 8 pixels per sprite row
 
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -37,1168 +59,5880 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
-#[allow(dead_code)]
-pub struct Chip8 {
-    registers: [u8; REGISTER_COUNT],
-    memory: [u8; MEMORY_SIZE],
-    index: u16,
-    pc: u16,
-    stack: [u16; STACK_SIZE],
-    sp: u8,
-    delay_timer: u8,
-    sound_timer: u8,
-    keypad: [bool; KEY_COUNT],
-    video: [u32; VIDEO_SIZE],
-    opcode: u16,
-    rng: StdRng,
-    debug: bool,
-}
-
-#[allow(dead_code)]
-impl Chip8 {
-    pub fn new() -> Self {
-        let mut chip8 = Self {
-            registers: [0; REGISTER_COUNT],
-            memory: [0; MEMORY_SIZE],
-            index: 0,
-            pc: START_ADDRESS,
-            stack: [0; STACK_SIZE],
-            sp: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            keypad: [false; KEY_COUNT],
-            video: [0; VIDEO_SIZE],
-            opcode: 0,
-            rng: StdRng::seed_from_u64(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_nanos() as u64,
-            ),
-            debug: true, // Enable debug output initially
-        };
-
-        chip8.load_fontset();
-        chip8
+// Compile-time checks on the fontset: 16 glyphs of FONT_GLYPH_HEIGHT rows, each row
+// 4 pixels wide (high nibble only), and no glyph starting or ending on a blank row
+const _: () = assert!(FONTSET.len() == FONTSET_SIZE, "Fontset has wrong size");
+const _: () = assert!(FONTSET_SIZE == 16 * FONT_GLYPH_HEIGHT, "Fontset must hold 16 glyphs");
+const _: () = assert!(fontset_is_well_formed(&FONTSET), "Fontset glyph data is corrupted");
+
+const fn fontset_is_well_formed(fontset: &[u8; FONTSET_SIZE]) -> bool {
+    let mut i = 0;
+    while i < FONTSET_SIZE {
+        if fontset[i] & 0x0F != 0 {
+            return false;
+        }
+        let row = i % FONT_GLYPH_HEIGHT;
+        if (row == 0 || row == FONT_GLYPH_HEIGHT - 1) && fontset[i] == 0 {
+            return false;
+        }
+        i += 1;
     }
+    true
+}
 
-    pub fn enable_debug(&mut self, enabled: bool) {
-        self.debug = enabled;
-    }
+// Operand slots used by the assembler table below
+#[derive(Clone, Copy)]
+enum Operand {
+    Vx,     // register, encoded in bits 8-11
+    Vy,     // register, encoded in bits 4-7
+    Byte,   // 8-bit immediate, bits 0-7
+    Addr,   // 12-bit address, bits 0-11
+    Nibble, // 4-bit immediate, bits 0-3
+    Planes, // XO-CHIP plane mask, bits 8-9
+}
 
-    fn debug_print(&self, message: &str) {
-        if self.debug {
-            println!("DEBUG: {}", message);
+impl Operand {
+    fn max_and_shift(self) -> (u16, u16) {
+        match self {
+            Operand::Vx => (0xF, 8),
+            Operand::Vy => (0xF, 4),
+            Operand::Byte => (0xFF, 0),
+            Operand::Addr => (0xFFF, 0),
+            Operand::Nibble => (0xF, 0),
+            Operand::Planes => (0x3, 8),
         }
     }
 
-    fn load_fontset(&mut self) {
-        let start = FONTSET_START_ADDRESS as usize;
-        for (i, &byte) in FONTSET.iter().enumerate() {
-            self.memory[start + i] = byte;
+    // Placeholder this operand replaces in a form string
+    fn placeholder(self) -> &'static str {
+        match self {
+            Operand::Vx => "Vx",
+            Operand::Vy => "Vy",
+            Operand::Byte => "byte",
+            Operand::Addr => "addr",
+            Operand::Nibble => "nibble",
+            Operand::Planes => "mask",
         }
-        self.debug_print(&format!("Loaded fontset at 0x{:03X}", start));
     }
+}
 
-    pub fn load_rom(&mut self, filename: &str) -> Result<()> {
-        let rom_data = fs::read(filename)?;
+// (form, opcode base, fixed-bit mask, operands). Forms use the same notation as the
+// instruction comments. The mask selects the bits that identify the instruction when
+// disassembling. When assembling a bare mnemonic, the first form with that name and operand
+// count wins.
+const INSTRUCTION_FORMS: &[(&str, u16, u16, &[Operand])] = &[
+    ("CLS", 0x00E0, 0xFFFF, &[]),
+    ("RET", 0x00EE, 0xFFFF, &[]),
+    ("JP addr", 0x1000, 0xF000, &[Operand::Addr]),
+    ("CALL addr", 0x2000, 0xF000, &[Operand::Addr]),
+    ("SE Vx, byte", 0x3000, 0xF000, &[Operand::Vx, Operand::Byte]),
+    ("SNE Vx, byte", 0x4000, 0xF000, &[Operand::Vx, Operand::Byte]),
+    ("SE Vx, Vy", 0x5000, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("LD Vx, byte", 0x6000, 0xF000, &[Operand::Vx, Operand::Byte]),
+    ("ADD Vx, byte", 0x7000, 0xF000, &[Operand::Vx, Operand::Byte]),
+    ("LD Vx, Vy", 0x8000, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("OR Vx, Vy", 0x8001, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("AND Vx, Vy", 0x8002, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("XOR Vx, Vy", 0x8003, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("ADD Vx, Vy", 0x8004, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("SUB Vx, Vy", 0x8005, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("SHR Vx", 0x8006, 0xF00F, &[Operand::Vx]),
+    ("SUBN Vx, Vy", 0x8007, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("SHL Vx", 0x800E, 0xF00F, &[Operand::Vx]),
+    ("SNE Vx, Vy", 0x9000, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("LD I, addr", 0xA000, 0xF000, &[Operand::Addr]),
+    ("JP V0, addr", 0xB000, 0xF000, &[Operand::Addr]),
+    ("RND Vx, byte", 0xC000, 0xF000, &[Operand::Vx, Operand::Byte]),
+    ("DRW Vx, Vy, nibble", 0xD000, 0xF000, &[Operand::Vx, Operand::Vy, Operand::Nibble]),
+    ("SKP Vx", 0xE09E, 0xF0FF, &[Operand::Vx]),
+    ("SKNP Vx", 0xE0A1, 0xF0FF, &[Operand::Vx]),
+    ("LD Vx, DT", 0xF007, 0xF0FF, &[Operand::Vx]),
+    ("LD Vx, K", 0xF00A, 0xF0FF, &[Operand::Vx]),
+    ("LD DT, Vx", 0xF015, 0xF0FF, &[Operand::Vx]),
+    ("LD ST, Vx", 0xF018, 0xF0FF, &[Operand::Vx]),
+    ("ADD I, Vx", 0xF01E, 0xF0FF, &[Operand::Vx]),
+    ("LD F, Vx", 0xF029, 0xF0FF, &[Operand::Vx]),
+    ("LD B, Vx", 0xF033, 0xF0FF, &[Operand::Vx]),
+    ("LD [I], Vx", 0xF055, 0xF0FF, &[Operand::Vx]),
+    ("LD Vx, [I]", 0xF065, 0xF0FF, &[Operand::Vx]),
+    // XO-CHIP, after the CHIP-8 forms so bare "LD" keeps its usual meaning
+    ("LD [I], Vx-Vy", 0x5002, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("LD Vx-Vy, [I]", 0x5003, 0xF00F, &[Operand::Vx, Operand::Vy]),
+    ("LD I, long", 0xF000, 0xFFFF, &[]), // The address is the next word
+    ("PLANE mask", 0xF001, 0xFCFF, &[Operand::Planes]),
+    // SUPER-CHIP
+    ("SCD nibble", 0x00C0, 0xFFF0, &[Operand::Nibble]),
+    ("SCR", 0x00FB, 0xFFFF, &[]),
+    ("SCL", 0x00FC, 0xFFFF, &[]),
+    ("EXIT", 0x00FD, 0xFFFF, &[]),
+    ("LOW", 0x00FE, 0xFFFF, &[]),
+    ("HIGH", 0x00FF, 0xFFFF, &[]),
+];
 
-        let start = START_ADDRESS as usize;
-        if rom_data.len() > (MEMORY_SIZE - start) {
-            return Err(anyhow::anyhow!("ROM too large to fit in memory"));
+const ROM_HEADER_MAGIC: [u8; 4] = *b"CH8\0";
+const ROM_HEADER_VERSION: u8 = 1;
+const ROM_HEADER_SIZE: usize = 4 + 1 + 2 + 2 + 32 + 32 + 128;
+
+// Optional header in front of a ROM carrying the configuration it needs.
+// Multi-byte fields are big-endian, text fields are NUL padded. quirks_flags uses the
+// QUIRK_FLAG_* bits, so 0 turns every quirk off, VIP defaults included; set
+// FriesRomHeader::DEFAULT_QUIRKS instead to keep QuirksConfig::default().
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+pub struct FriesRomHeader {
+    pub magic: [u8; 4],
+    pub version: u8,
+    pub quirks_flags: u16,
+    pub cpf: u16, // cycles per frame
+    pub name: [u8; 32],
+    pub author: [u8; 32],
+    pub description: [u8; 128],
+}
+
+#[allow(dead_code)]
+impl FriesRomHeader {
+    // With this bit set in quirks_flags the other bits are ignored
+    pub const DEFAULT_QUIRKS: u16 = 1 << 15;
+
+    // Starts out with DEFAULT_QUIRKS and the default cycles per frame
+    pub fn new(name: &str, author: &str, description: &str) -> Self {
+        Self {
+            magic: ROM_HEADER_MAGIC,
+            version: ROM_HEADER_VERSION,
+            quirks_flags: Self::DEFAULT_QUIRKS,
+            cpf: 0,
+            name: pad_text(name),
+            author: pad_text(author),
+            description: pad_text(description),
         }
+    }
 
-        for (i, &byte) in rom_data.iter().enumerate() {
-            self.memory[start + i] = byte;
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < ROM_HEADER_SIZE {
+            return Err(anyhow::anyhow!("ROM header truncated: {} bytes", bytes.len()));
+        }
+        if bytes[0..4] != ROM_HEADER_MAGIC {
+            return Err(anyhow::anyhow!("Missing ROM header magic"));
+        }
+        if bytes[4] != ROM_HEADER_VERSION {
+            return Err(anyhow::anyhow!("Unsupported ROM header version {}", bytes[4]));
         }
 
-        self.debug_print(&format!("Loaded ROM: {} bytes at 0x{:03X}", rom_data.len(), start));
-        Ok(())
+        Ok(Self {
+            magic: ROM_HEADER_MAGIC,
+            version: bytes[4],
+            quirks_flags: u16::from_be_bytes([bytes[5], bytes[6]]),
+            cpf: u16::from_be_bytes([bytes[7], bytes[8]]),
+            name: bytes[9..41].try_into().unwrap(),
+            author: bytes[41..73].try_into().unwrap(),
+            description: bytes[73..ROM_HEADER_SIZE].try_into().unwrap(),
+        })
     }
 
-    pub fn random_byte(&mut self) -> u8 {
-        self.rng.random::<u8>()
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ROM_HEADER_SIZE);
+        bytes.extend_from_slice(&self.magic);
+        bytes.push(self.version);
+        bytes.extend_from_slice(&self.quirks_flags.to_be_bytes());
+        bytes.extend_from_slice(&self.cpf.to_be_bytes());
+        bytes.extend_from_slice(&self.name);
+        bytes.extend_from_slice(&self.author);
+        bytes.extend_from_slice(&self.description);
+        bytes
     }
 
-    pub fn get_display(&self) -> &[u32] {
-        &self.video
+    pub fn quirks(&self) -> QuirksConfig {
+        if self.quirks_flags & Self::DEFAULT_QUIRKS != 0 {
+            QuirksConfig::default()
+        } else {
+            QuirksConfig::from_flags(self.quirks_flags)
+        }
     }
 
-    pub fn set_keys(&mut self, keys: &[bool; KEY_COUNT]) {
-        self.keypad = *keys;
+    pub fn name_str(&self) -> String {
+        text_from_padded(&self.name)
     }
 
-    // Fetch -> Decode -> Execute
-    pub fn cycle(&mut self) {
-        // Check if PC is in valid range
-        if (self.pc as usize) >= MEMORY_SIZE - 1 {
-            self.debug_print(&format!("PC out of bounds: 0x{:03X}", self.pc));
-            return;
-        }
+    pub fn author_str(&self) -> String {
+        text_from_padded(&self.author)
+    }
 
-        let high_byte = self.memory[self.pc as usize] as u16;
-        let low_byte = self.memory[(self.pc + 1) as usize] as u16;
-        self.opcode = (high_byte << 8) | low_byte;
+    pub fn description_str(&self) -> String {
+        text_from_padded(&self.description)
+    }
+}
 
-        self.debug_print(&format!("PC: 0x{:03X}, Opcode: 0x{:04X}", self.pc, self.opcode));
+// Draws from a Chip8's RNG; every value taken is one RND won't see
+pub struct Chip8RngStream<'a>(&'a mut Chip8);
 
-        self.pc += 2;
+impl Iterator for Chip8RngStream<'_> {
+    type Item = u8;
 
-        match (self.opcode & 0xF000) >> 12 {
-            0x0 => self.execute_0xxx(),
-            0x1 => self.op_1nnn(), // JP addr
-            0x2 => self.op_2nnn(), // CALL addr
-            0x3 => self.op_3xkk(), // SE Vx, byte
-            0x4 => self.op_4xkk(), // SNE Vx, byte
-            0x5 => self.op_5xy0(), // SE Vx, Vy
-            0x6 => self.op_6xkk(), // LD Vx, byte
-            0x7 => self.op_7xkk(), // ADD Vx, byte
-            0x8 => self.execute_8xxx(),
-            0x9 => self.op_9xy0(), // SNE Vx, Vy
-            0xA => self.op_annn(), // LD I, addr
-            0xB => self.op_bnnn(), // JP V0, addr
-            0xC => self.op_cxkk(), // RND Vx, byte
-            0xD => self.op_dxyn(), // DRW Vx, Vy, nibble
-            0xE => self.execute_exxx(),
-            0xF => self.execute_fxxx(),
-            _ => {
-                println!("Unknown opcode: 0x{:04X}", self.opcode);
-            }
-        }
+    fn next(&mut self) -> Option<u8> {
+        Some(self.0.random_byte())
+    }
+}
 
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
-        }
+fn seeded_rng(seed: u64) -> Box<dyn FnMut() -> u8> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Box::new(move || rng.random::<u8>())
+}
 
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
-        }
+// Bytes from a hex string, or from base64 if it isn't hex
+fn parse_overlay_text(text: &str) -> Result<Vec<u8>> {
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to paste"));
     }
 
-    fn execute_0xxx(&mut self) {
-        match self.opcode & 0x00FF {
-            0xE0 => self.op_00e0(), // CLS
-            0xEE => self.op_00ee(), // RET
-            _ => {
-                println!("Unknown 0xxx opcode: 0x{:04X}", self.opcode);
-            }
-        }
+    if compact.len().is_multiple_of(2) && compact.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok((0..compact.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&compact[i..i + 2], 16).unwrap())
+            .collect());
     }
 
-    fn execute_8xxx(&mut self) {
-        match self.opcode & 0x000F { // Fixed: should check last nibble, not last byte
-            0x0 => self.op_8xy0(), // LD Vx, Vy
-            0x1 => self.op_8xy1(), // OR Vx, Vy
-            0x2 => self.op_8xy2(), // AND Vx, Vy
-            0x3 => self.op_8xy3(), // XOR Vx, Vy
-            0x4 => self.op_8xy4(), // ADD Vx, Vy
-            0x5 => self.op_8xy5(), // SUB Vx, Vy
-            0x6 => self.op_8xy6(), // SHR Vx
-            0x7 => self.op_8xy7(), // SUBN Vx, Vy
-            0xE => self.op_8xye(), // SHL Vx
-            _ => {
-                println!("Unknown 8xxx opcode: 0x{:04X}", self.opcode);
-            }
-        }
-    }
+    decode_base64(&compact).ok_or_else(|| anyhow::anyhow!("Paste is neither hex nor base64"))
+}
 
-    fn execute_exxx(&mut self) {
-        match self.opcode & 0x00FF {
-            0x9E => self.op_ex9e(), // SKP Vx
-            0xA1 => self.op_exa1(), // SKNP Vx
-            _ => {
-                println!("Unknown Exxx opcode: 0x{:04X}", self.opcode);
-            }
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let digits = text.trim_end_matches('=');
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+
+    for c in digits.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
         }
     }
+    Some(bytes)
+}
 
-    fn execute_fxxx(&mut self) {
-        match self.opcode & 0x00FF {
-            0x07 => self.op_fx07(), // LD Vx, DT
-            0x0A => self.op_fx0a(), // LD Vx, K
-            0x15 => self.op_fx15(), // LD DT, Vx
-            0x18 => self.op_fx18(), // LD ST, Vx
-            0x1E => self.op_fx1e(), // ADD I, Vx
-            0x29 => self.op_fx29(), // LD F, Vx
-            0x33 => self.op_fx33(), // LD B, Vx
-            0x55 => self.op_fx55(), // LD [I], Vx
-            0x65 => self.op_fx65(), // LD Vx, [I]
-            _ => {
-                println!("Unknown Fxxx opcode: 0x{:04X}", self.opcode);
-            }
-        }
-    }
+// Copy text into a NUL padded field, truncating if needed
+fn pad_text<const N: usize>(text: &str) -> [u8; N] {
+    let mut field = [0u8; N];
+    let len = text.len().min(N);
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+    field
+}
 
-    // ===== INSTRUCTIONS =====
+fn text_from_padded(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
 
-    // 00E0: CLS Clear the display.
-    fn op_00e0(&mut self) {
-        self.video = [0; VIDEO_SIZE];
-        self.debug_print("Cleared display");
-    }
+// Owned copy of the display, for comparing frames
+#[allow(dead_code)]
+#[derive(Clone, PartialEq)]
+pub struct FrameBuffer {
+    pixels: Vec<u32>,
+    width: usize,
+}
 
-    // 00EE: RET Return from a subroutine.
-    fn op_00ee(&mut self) {
-        self.sp -= 1;
-        self.pc = self.stack[self.sp as usize];
-        self.debug_print(&format!("RET to 0x{:03X}", self.pc));
+#[allow(dead_code)]
+impl FrameBuffer {
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
     }
 
-    // 1nnn: JP addr Jump to location nnn.
-    fn op_1nnn(&mut self) {
-        let address = self.opcode & 0x0FFF;
-        self.debug_print(&format!("JP to 0x{:03X}", address));
-        self.pc = address;
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    // 2nnn: CALL addr Call subroutine at nnn.
-    fn op_2nnn(&mut self) {
-        let address = self.opcode & 0x0FFF;
-        self.stack[self.sp as usize] = self.pc;
-        self.sp += 1;
-        self.pc = address;
-        self.debug_print(&format!("CALL 0x{:03X}", address));
+    // (x, y) coordinates of every pixel that differs between the two frames, row by row.
+    // Frames of different resolutions are compared over the overlapping pixel indices.
+    pub fn diff(&self, other: &FrameBuffer) -> Vec<(usize, usize)> {
+        self.pixels
+            .iter()
+            .zip(other.pixels.iter())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(i, _)| (i % self.width, i / self.width))
+            .collect()
     }
+}
 
-    // 3xkk - SE Vx, byte Skip next instruction if Vx = kk.
-    fn op_3xkk(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let byte = (self.opcode & 0x00FF) as u8;
+// Interpreter-specific behaviors. Defaults match the original COSMAC VIP interpreter.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct QuirksConfig {
+    // CHIP-8E: 5xy1/5xy2/5xy3 are OR/AND/XOR Vx, Vy without touching VF
+    pub chip8e_mode: bool,
+    // 8xy6/8xyE copy Vy into Vx before shifting, instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65 leave I just past the last register transferred
+    pub load_store_increments_i: bool,
+    // Bnnn jumps to nnn + Vx, x being the top nibble of nnn (SUPER-CHIP's Bxnn)
+    pub jump_uses_vx: bool,
+    // 8xy1/8xy2/8xy3 clear VF
+    pub vf_reset_on_logic: bool,
+    // Fx1E sets VF when I + Vx goes past 0xFFF (Amiga interpreter; Spacefight 2091! needs it)
+    pub i_overflow_sets_vf: bool,
+    // Dxyn clips sprites at the screen edges; otherwise they wrap around to the other side
+    pub clip_sprites: bool,
+    // Dxyn waits for the next 60Hz timer tick (the VIP's vertical blank) after a draw, so at
+    // most one sprite is drawn per frame
+    pub display_wait: bool,
+}
 
-        if self.registers[vx] == byte {
-            self.pc += 2;
-            self.debug_print(&format!("SE V{:X}, 0x{:02X} - SKIP", vx, byte));
-        } else {
-            self.debug_print(&format!("SE V{:X}, 0x{:02X} - NO SKIP", vx, byte));
+impl Default for QuirksConfig {
+    fn default() -> Self {
+        Self {
+            chip8e_mode: false,
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset_on_logic: true,
+            i_overflow_sets_vf: false,
+            clip_sprites: true,
+            display_wait: true,
         }
     }
+}
 
-    // 4xkk - SNE Vx, byte Skip next instruction if Vx != kk.
-    fn op_4xkk(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let byte = (self.opcode & 0x00FF) as u8;
+// Bit positions used for QuirksConfig in FriesRomHeader::quirks_flags. A set bit turns the
+// quirk on, so flags of 0 disable them all. Clipping predates the flag, so its bit asks for
+// wrapping instead and 0 keeps clipping.
+const QUIRK_FLAG_CHIP8E: u16 = 1 << 0;
+const QUIRK_FLAG_SHIFT_USES_VY: u16 = 1 << 1;
+const QUIRK_FLAG_LOAD_STORE_INCREMENTS_I: u16 = 1 << 2;
+const QUIRK_FLAG_JUMP_USES_VX: u16 = 1 << 3;
+const QUIRK_FLAG_VF_RESET_ON_LOGIC: u16 = 1 << 4;
+const QUIRK_FLAG_I_OVERFLOW_SETS_VF: u16 = 1 << 5;
+const QUIRK_FLAG_WRAP_SPRITES: u16 = 1 << 6;
+const QUIRK_FLAG_DISPLAY_WAIT: u16 = 1 << 7;
+
+// User-facing quirk names for a flag that is set and one that is clear, in display order.
+// Display prints them and from_names (--quirks) reads them back.
+const QUIRK_NAMES: &[(u16, &str, &str)] = &[
+    (QUIRK_FLAG_CHIP8E, "chip8e", "no-chip8e"),
+    (QUIRK_FLAG_SHIFT_USES_VY, "shift-uses-vy", "shift-in-place"),
+    (QUIRK_FLAG_LOAD_STORE_INCREMENTS_I, "fx55-increment", "fx55-no-increment"),
+    (QUIRK_FLAG_JUMP_USES_VX, "jump-uses-vx", "jump-uses-v0"),
+    (QUIRK_FLAG_VF_RESET_ON_LOGIC, "vf-reset", "no-vf-reset"),
+    (QUIRK_FLAG_I_OVERFLOW_SETS_VF, "i-overflow-vf", "no-i-overflow-vf"),
+    (QUIRK_FLAG_WRAP_SPRITES, "wrap-sprites", "clip-sprites"),
+    (QUIRK_FLAG_DISPLAY_WAIT, "display-wait", "no-display-wait"),
+];
 
-        if self.registers[vx] != byte {
-            self.pc += 2;
-            self.debug_print(&format!("SNE V{:X}, 0x{:02X} - SKIP", vx, byte));
-        } else {
-            self.debug_print(&format!("SNE V{:X}, 0x{:02X} - NO SKIP", vx, byte));
+#[allow(dead_code)]
+impl QuirksConfig {
+    pub fn from_flags(flags: u16) -> Self {
+        Self {
+            chip8e_mode: flags & QUIRK_FLAG_CHIP8E != 0,
+            shift_uses_vy: flags & QUIRK_FLAG_SHIFT_USES_VY != 0,
+            load_store_increments_i: flags & QUIRK_FLAG_LOAD_STORE_INCREMENTS_I != 0,
+            jump_uses_vx: flags & QUIRK_FLAG_JUMP_USES_VX != 0,
+            vf_reset_on_logic: flags & QUIRK_FLAG_VF_RESET_ON_LOGIC != 0,
+            i_overflow_sets_vf: flags & QUIRK_FLAG_I_OVERFLOW_SETS_VF != 0,
+            clip_sprites: flags & QUIRK_FLAG_WRAP_SPRITES == 0,
+            display_wait: flags & QUIRK_FLAG_DISPLAY_WAIT != 0,
         }
     }
 
-    // 5xy0 - SE Vx, Vy Skip next instruction if Vx = Vy.
-    fn op_5xy0(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+    pub fn to_flags(self) -> u16 {
+        [
+            (self.chip8e_mode, QUIRK_FLAG_CHIP8E),
+            (self.shift_uses_vy, QUIRK_FLAG_SHIFT_USES_VY),
+            (self.load_store_increments_i, QUIRK_FLAG_LOAD_STORE_INCREMENTS_I),
+            (self.jump_uses_vx, QUIRK_FLAG_JUMP_USES_VX),
+            (self.vf_reset_on_logic, QUIRK_FLAG_VF_RESET_ON_LOGIC),
+            (self.i_overflow_sets_vf, QUIRK_FLAG_I_OVERFLOW_SETS_VF),
+            (!self.clip_sprites, QUIRK_FLAG_WRAP_SPRITES),
+            (self.display_wait, QUIRK_FLAG_DISPLAY_WAIT),
+        ]
+        .iter()
+        .filter(|&&(on, _)| on)
+        .fold(0, |flags, &(_, flag)| flags | flag)
+    }
 
-        if self.registers[vx] == self.registers[vy] {
-            self.pc += 2;
+    // The defaults with the named quirks applied, e.g. "vf-reset, wrap-sprites". Takes the
+    // Display output as is, so "Quirks: [no-vf-reset]" works too.
+    pub fn from_names(names: &str) -> Result<Self> {
+        let list = names.trim();
+        let list = list.strip_prefix("Quirks:").unwrap_or(list).trim();
+        let list = list.strip_prefix('[').and_then(|l| l.strip_suffix(']')).unwrap_or(list);
+
+        let mut flags = QuirksConfig::default().to_flags();
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let Some(&(flag, set, _)) = QUIRK_NAMES.iter()
+                .find(|&&(_, set, clear)| name == set || name == clear)
+            else {
+                return Err(anyhow::anyhow!("Unknown quirk: {}", name));
+            };
+            if name == set {
+                flags |= flag;
+            } else {
+                flags &= !flag;
+            }
         }
-        self.debug_print(&format!("SE V{:X}, V{:X}", vx, vy));
+        Ok(Self::from_flags(flags))
     }
+}
 
-    // 6xkk - LD Vx, byte, Set Vx = kk.
-    fn op_6xkk(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let byte = (self.opcode & 0x00FF) as u8;
-
-        self.registers[vx] = byte;
-        self.debug_print(&format!("LD V{:X}, 0x{:02X}", vx, byte));
+// Lists the quirks that differ from the defaults by the names --quirks takes, e.g.
+// "Quirks: [shift-in-place, jump-uses-vx]"
+impl std::fmt::Display for QuirksConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags = self.to_flags();
+        let changed = flags ^ QuirksConfig::default().to_flags();
+        let names: Vec<&str> = QUIRK_NAMES.iter()
+            .filter(|&&(flag, _, _)| changed & flag != 0)
+            .map(|&(flag, set, clear)| if flags & flag != 0 { set } else { clear })
+            .collect();
+        write!(f, "Quirks: [{}]", names.join(", "))
     }
+}
 
-    // 7xkk - ADD Vx, byte, Set Vx = Vx + kk.
-    fn op_7xkk(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let byte = (self.opcode & 0x00FF) as u8;
-
-        self.registers[vx] = self.registers[vx].wrapping_add(byte);
-        self.debug_print(&format!("ADD V{:X}, 0x{:02X}", vx, byte));
-    }
+// Memory traffic since power-on or the last reset. hottest_addr is the most written address (0 if none).
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MemoryAccessStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub hottest_addr: u16,
+}
 
-    // 8xy0 - LD Vx, Vy, Set Vx = Vy.
-    fn op_8xy0(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+// One entry of the call stack; depth 0 is the outermost call
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StackFrame {
+    pub return_addr: u16,
+    pub depth: u8,
+}
 
-        self.registers[vx] = self.registers[vy];
-        self.debug_print(&format!("LD V{:X}, V{:X}", vx, vy));
-    }
+// Opcode families by high nibble, as listed in OpcodeStats::breakdown
+const OPCODE_FAMILIES: [&str; 16] = [
+    "0nnn", "1nnn", "2nnn", "3xkk", "4xkk", "5xyn", "6xkk", "7xkk",
+    "8xyn", "9xy0", "Annn", "Bnnn", "Cxkk", "Dxyn", "Exkk", "Fxkk",
+];
 
-    // 8xy1 - OR Vx, Vy, Set Vx = Vx OR Vy.
-    fn op_8xy1(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+// Instructions executed since the last reset, by kind. 8xyN and Fxkk are also counted by
+// their last nibble and byte, since those pick the operation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OpcodeStats {
+    pub by_nibble: [u64; 16], // by_nibble[0xD] counts every Dxyn
+    pub alu: [u64; 16],       // 8xyN by N
+    pub misc: [u64; 256],     // Fxkk by kk
+}
 
-        self.registers[vx] |= self.registers[vy];
-        self.debug_print(&format!("OR V{:X}, V{:X}", vx, vy));
+impl Default for OpcodeStats {
+    fn default() -> Self {
+        Self { by_nibble: [0; 16], alu: [0; 16], misc: [0; 256] }
     }
+}
 
-    // 8xy2 - AND Vx, Vy, Set Vx = Vx AND Vy.
-    fn op_8xy2(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
-
-        self.registers[vx] &= self.registers[vy];
-        self.debug_print(&format!("AND V{:X}, V{:X}", vx, vy));
+impl OpcodeStats {
+    fn count(&mut self, opcode: u16) {
+        let nibble = (opcode >> 12) as usize;
+        self.by_nibble[nibble] += 1;
+        match nibble {
+            0x8 => self.alu[(opcode & 0x000F) as usize] += 1,
+            0xF => self.misc[(opcode & 0x00FF) as usize] += 1,
+            _ => {}
+        }
     }
 
-    // 8xy3 - XOR Vx, Vy, Set Vx = Vx XOR Vy.
-    fn op_8xy3(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
-
-        self.registers[vx] ^= self.registers[vy];
-        self.debug_print(&format!("XOR V{:X}, V{:X}", vx, vy));
+    // (label, count) for every kind that ran, most executed first. 8xyN and Fxkk are listed
+    // per operation, e.g. "8xy4" and "Fx1E".
+    pub fn breakdown(&self) -> Vec<(String, u64)> {
+        let mut rows: Vec<(String, u64)> = Vec::new();
+        for (nibble, &count) in self.by_nibble.iter().enumerate() {
+            match nibble {
+                0x8 => rows.extend(self.alu.iter().enumerate().map(|(n, &c)| (format!("8xy{:X}", n), c))),
+                0xF => rows.extend(self.misc.iter().enumerate().map(|(kk, &c)| (format!("Fx{:02X}", kk), c))),
+                _ => rows.push((OPCODE_FAMILIES[nibble].to_string(), count)),
+            }
+        }
+        rows.retain(|&(_, count)| count > 0);
+        rows.sort_by_key(|&(_, count)| std::cmp::Reverse(count)); // Stable, so ties stay in opcode order
+        rows
     }
+}
 
-    // 8xy4 - ADD Vx, Vy, Set Vx = Vx + Vy, set VF = carry.
-    fn op_8xy4(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+// What one cycle changed, for Chip8::step_back. The CPU state is copied whole; video only for
+// instructions that can draw, and memory as (address, old value) for each byte written.
+struct RewindFrame {
+    registers: [u8; REGISTER_COUNT],
+    index: u16,
+    pc: u16,
+    stack: [u16; STACK_SIZE],
+    sp: u8,
+    delay_timer: u8,
+    sound_timer: u8,
+    cycle_count: u64,
+    waiting_key: Option<u8>,
+    vblank: bool,
+    plane_mask: u8,
+    video: Option<(Vec<u32>, Vec<u32>, bool)>, // Both planes and hires
+    memory: Vec<(usize, u8)>,
+}
 
-        let sum = self.registers[vx] as u16 + self.registers[vy] as u16;
+// Snapshot of all machine state that ROMs can observe
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Chip8State {
+    pub registers: [u8; REGISTER_COUNT],
+    pub memory: Vec<u8>, // MEMORY_SIZE bytes
+    pub index: u16,
+    pub pc: u16,
+    pub stack: [u16; STACK_SIZE],
+    pub sp: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keypad: [bool; KEY_COUNT],
+    pub video: Vec<u32>, // Row-major at the current resolution
+    pub hires: bool,
+    pub video_plane2: Vec<u32>, // XO-CHIP's second plane, laid out like `video`
+    pub plane_mask: u8,         // The planes FN01 selected
+}
 
-        self.registers[0xF] = if sum > 255 { 1 } else { 0 };
-        self.registers[vx] = (sum & 0xFF) as u8;
-        self.debug_print(&format!("ADD V{:X}, V{:X}", vx, vy));
+// What changed between two snapshots. Entries are (where, old, new).
+#[allow(dead_code)]
+#[derive(Default, PartialEq, Debug)]
+pub struct StateDiff {
+    pub changed_registers: Vec<(usize, u8, u8)>,
+    pub changed_memory: Vec<(u16, u8, u8)>,
+    pub pc_changed: Option<(u16, u16)>,
+    pub index_changed: Option<(u16, u16)>,
+    pub changed_stack: Vec<(usize, u16, u16)>,
+    pub sp_changed: Option<(u8, u8)>,
+    pub delay_timer_changed: Option<(u8, u8)>,
+    pub sound_timer_changed: Option<(u8, u8)>,
+    pub changed_keys: Vec<(usize, bool, bool)>,
+    pub plane_mask_changed: Option<(u8, u8)>,
+    pub display_changed: bool, // Either plane, or the resolution
+}
+
+// One step of the message-passing interface, see Chip8::process
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Input {
+    pub keys: [bool; KEY_COUNT],
+    pub timer_tick: bool, // Decrement the timers once, after the cycles have run
+    pub cycles_to_run: u32,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Output {
+    DisplayUpdated(Vec<u32>), // New contents of the whole display, as copy_display_to_buffer
+    SoundToggle(bool),        // Buzzer turned on (true) or off (false)
+    Halted(HaltReason),
+    CycleComplete, // All requested cycles ran
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HaltReason {
+    PcOutOfBounds(u16),
+    InfiniteLoop(u16), // JP to its own address, the usual way ROMs stop
+    Exited(u16),       // SUPER-CHIP 00FD at this address
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum Chip8Error {
+    // Only `loaded` of the ROM's `expected` bytes made it into memory
+    PartialRom { loaded: usize, expected: usize },
+    // Reading a ROM file failed; the path is the one given to load_rom
+    RomNotFound(String),
+    RomPermissionDenied(String),
+    RomIsDirectory(String),
+    RomTooLarge { path: String, size: usize, max: usize, addr: u16 },
+    IoError(std::io::Error),
+}
+
+impl std::fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chip8Error::PartialRom { loaded, expected } => {
+                write!(f, "ROM only partially loaded: {} of {} bytes fit in memory", loaded, expected)
+            }
+            Chip8Error::RomNotFound(path) => write!(f, "ROM not found: {} (check the path)", path),
+            Chip8Error::RomPermissionDenied(path) => {
+                write!(f, "Permission denied reading ROM {} (check the file's permissions)", path)
+            }
+            Chip8Error::RomIsDirectory(path) => write!(f, "{} is a directory, not a ROM file", path),
+            Chip8Error::RomTooLarge { path, size, max, addr } => write!(
+                f,
+                "ROM {} is {} bytes, but only {} fit in memory from 0x{:03X}",
+                path, size, max, addr
+            ),
+            Chip8Error::IoError(e) => write!(f, "I/O error: {}", e),
+        }
     }
+}
 
-    // 8xy5 - SUB Vx, Vy, Set Vx = Vx - Vy, set VF = NOT borrow.
-    fn op_8xy5(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-        self.registers[0xF] = if self.registers[vx] > self.registers[vy] {
-            1
-        } else {
-            0
-        };
+impl From<std::io::Error> for Chip8Error {
+    fn from(e: std::io::Error) -> Self {
+        Chip8Error::IoError(e)
+    }
+}
 
-        self.registers[vx] = self.registers[vx].wrapping_sub(self.registers[vy]);
-        self.debug_print(&format!("SUB V{:X}, V{:X}", vx, vy));
+// Read a ROM, telling the common ways that fails apart
+fn read_rom_file(path: &str) -> Result<Vec<u8>, Chip8Error> {
+    fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Chip8Error::RomNotFound(path.to_string()),
+        std::io::ErrorKind::PermissionDenied => Chip8Error::RomPermissionDenied(path.to_string()),
+        std::io::ErrorKind::IsADirectory => Chip8Error::RomIsDirectory(path.to_string()),
+        _ => Chip8Error::IoError(e),
+    })
+}
+
+// The 8 standard terminal colors, for display_as_ansi
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AnsiColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl AnsiColor {
+    // SGR foreground code; background is this plus 10
+    fn fg_code(self) -> u8 {
+        30 + self as u8
     }
+}
 
-    // 8xy6 - SHR Vx, Set Vx = Vx SHR 1.
-    fn op_8xy6(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+// Summary of a Chip8::step_n run
+#[allow(dead_code)]
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct CycleStats {
+    pub instructions_run: u64,
+    pub display_updates: u64, // Cycles that changed at least one pixel
+    pub halted: bool,
+    pub halt_reason: Option<HaltReason>,
+}
 
-        self.registers[0xF] = self.registers[vx] & 0x1;
-        self.registers[vx] >>= 1;
-        self.debug_print(&format!("SHR V{:X}", vx));
+// A malformed ROM tried something impossible. The instruction at `pc` is skipped, or for
+// memory accesses wraps around to address 0, and execution carries on; see Chip8::last_error
+// and the Result from Chip8::cycle (which leaves out MemoryWrap, as XO-CHIP defines the
+// wrap). Only PcOutOfBounds stops the machine for good.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExecutionError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    PcOutOfBounds { pc: u16 }, // No whole instruction left to fetch at `pc`
+    StackOverflow { pc: u16 },  // CALL with all 16 stack entries in use
+    StackUnderflow { pc: u16 }, // RET with nothing on the stack
+    MemoryWrap { pc: u16, addr: u16 }, // Reading or writing from `addr` ran past the end of memory
+}
+
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode 0x{:04X} at 0x{:03X}", opcode, pc)
+            }
+            ExecutionError::PcOutOfBounds { pc } => write!(f, "PC ran off the end of memory at 0x{:04X}", pc),
+            ExecutionError::StackOverflow { pc } => write!(f, "stack overflow at 0x{:03X}", pc),
+            ExecutionError::StackUnderflow { pc } => write!(f, "stack underflow at 0x{:03X}", pc),
+            ExecutionError::MemoryWrap { pc, addr } => {
+                write!(f, "memory access from 0x{:04X} wrapped past the end at 0x{:03X}", addr, pc)
+            }
+        }
     }
+}
 
-    // 8xy7 - SUBN Vx, Vy, Set Vx = Vy - Vx, set VF = NOT borrow.
-    fn op_8xy7(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+impl std::error::Error for ExecutionError {}
 
-        self.registers[0xF] = if self.registers[vy] > self.registers[vx] {
-            1
-        } else {
-            0
-        };
+// A run_until_* helper gave up after `cycles` cycles
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeoutError {
+    pub cycles: u64,
+}
 
-        self.registers[vx] = self.registers[vy].wrapping_sub(self.registers[vx]);
-        self.debug_print(&format!("SUBN V{:X}, V{:X}", vx, vy));
+impl std::fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {} cycles", self.cycles)
     }
+}
 
-    // 8xyE - SHL Vx {, Vy}, Set Vx = Vx SHL 1.
-    fn op_8xye(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+impl std::error::Error for TimeoutError {}
 
-        self.registers[0xF] = (self.registers[vx] & 0x80) >> 7;
-        self.registers[vx] <<= 1;
-        self.debug_print(&format!("SHL V{:X}", vx));
+#[allow(dead_code)]
+impl Chip8State {
+    pub fn diff(a: &Chip8State, b: &Chip8State) -> StateDiff {
+        let changed_registers = a.registers.iter().zip(b.registers.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(i, (&old, &new))| (i, old, new))
+            .collect();
+
+        let changed_memory = a.memory.iter().zip(b.memory.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(addr, (&old, &new))| (addr as u16, old, new))
+            .collect();
+
+        let changed_stack = a.stack.iter().zip(b.stack.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(i, (&old, &new))| (i, old, new))
+            .collect();
+
+        let changed_keys = a.keypad.iter().zip(b.keypad.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(key, (&old, &new))| (key, old, new))
+            .collect();
+
+        StateDiff {
+            changed_registers,
+            changed_memory,
+            pc_changed: (a.pc != b.pc).then_some((a.pc, b.pc)),
+            index_changed: (a.index != b.index).then_some((a.index, b.index)),
+            changed_stack,
+            sp_changed: (a.sp != b.sp).then_some((a.sp, b.sp)),
+            delay_timer_changed: (a.delay_timer != b.delay_timer).then_some((a.delay_timer, b.delay_timer)),
+            sound_timer_changed: (a.sound_timer != b.sound_timer).then_some((a.sound_timer, b.sound_timer)),
+            changed_keys,
+            plane_mask_changed: (a.plane_mask != b.plane_mask).then_some((a.plane_mask, b.plane_mask)),
+            display_changed: a.video != b.video || a.video_plane2 != b.video_plane2 || a.hires != b.hires,
+        }
     }
+}
 
-    // 9xy0 - SNE Vx, Vy, Skip next instruction if Vx != Vy.
-    fn op_9xy0(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+const STATE_MAGIC: [u8; 4] = *b"F8ST";
+const STATE_VERSION: u8 = 3; // 2: 64KB of memory, 3: XO-CHIP planes
 
-        if self.registers[vx] != self.registers[vy] {
-            self.pc += 2;
+// 64-bit FNV-1a over the pixels top to bottom, each as its two plane bits (plane 1 is bit
+// 0), so a plane-1-only display hashes as plain on/off
+fn video_hash(video: &[u32], plane2: &[u32]) -> u64 {
+    video.iter().zip(plane2).fold(FNV_OFFSET_BASIS, |hash, (&p1, &p2)| {
+        fnv1a(hash, &[(p1 != 0) as u8 | ((p2 != 0) as u8) << 1])
+    })
+}
+
+// JSON for scripts diffing runs: the CPU state one field per line, with the live part of the
+// stack and the display as its hash. Memory and the keypad are left out.
+#[allow(dead_code)]
+impl Chip8State {
+    pub fn to_json(&self) -> String {
+        let list = |values: Vec<String>| format!("[{}]", values.join(", "));
+        let fields = [
+            ("registers", list(self.registers.iter().map(u8::to_string).collect())),
+            ("pc", self.pc.to_string()),
+            ("index", self.index.to_string()),
+            ("sp", self.sp.to_string()),
+            ("stack", list(self.stack[..self.sp as usize].iter().map(u16::to_string).collect())),
+            ("delay_timer", self.delay_timer.to_string()),
+            ("sound_timer", self.sound_timer.to_string()),
+            ("hires", self.hires.to_string()),
+            // A string, since JSON numbers can't hold every u64 exactly
+            ("video_hash", format!("\"{:016x}\"", video_hash(&self.video, &self.video_plane2))),
+        ];
+        let lines: Vec<String> = fields.iter()
+            .map(|(name, value)| format!("  \"{}\": {}", name, value))
+            .collect();
+        format!("{{\n{}\n}}", lines.join(",\n"))
+    }
+}
+
+// Save files: magic, version, then the fields in declaration order. Multi-byte fields are
+// big-endian, keys, the hires flag and the plane mask are one byte each, and both video
+// planes are one bit per pixel.
+#[allow(dead_code)]
+impl Chip8State {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + MEMORY_SIZE + HIRES_VIDEO_SIZE / 8 + 64);
+        bytes.extend_from_slice(&STATE_MAGIC);
+        bytes.push(STATE_VERSION);
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.pc.to_be_bytes());
+        for addr in &self.stack {
+            bytes.extend_from_slice(&addr.to_be_bytes());
         }
-        self.debug_print(&format!("SNE V{:X}, V{:X}", vx, vy));
+        bytes.extend_from_slice(&[self.sp, self.delay_timer, self.sound_timer]);
+        bytes.extend(self.keypad.iter().map(|&key| key as u8));
+        bytes.push(self.hires as u8);
+        let bits = |video: &[u32]| -> Vec<u8> {
+            video.chunks(8)
+                .map(|pixels| pixels.iter().enumerate().fold(0u8, |byte, (i, &p)| byte | ((p != 0) as u8) << (7 - i)))
+                .collect()
+        };
+        bytes.extend(bits(&self.video));
+        bytes.extend(bits(&self.video_plane2));
+        bytes.push(self.plane_mask);
+        bytes
     }
 
-    // Annn - LD I, addr, Set I = nnn.
-    fn op_annn(&mut self) {
-        let address = self.opcode & 0x0FFF;
-        self.index = address;
-        self.debug_print(&format!("LD I, 0x{:03X}", address));
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.get(..4) != Some(&STATE_MAGIC[..]) {
+            return Err(anyhow::anyhow!("Not a save state: missing magic"));
+        }
+        if bytes.get(4) != Some(&STATE_VERSION) {
+            return Err(anyhow::anyhow!("Unsupported save state version {:?}", bytes.get(4)));
+        }
+
+        let mut rest = &bytes[5..];
+        let mut take = |len: usize| -> Result<&[u8]> {
+            if rest.len() < len {
+                return Err(anyhow::anyhow!("Save state truncated"));
+            }
+            let (field, tail) = rest.split_at(len);
+            rest = tail;
+            Ok(field)
+        };
+        let be_u16 = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+
+        let registers = take(REGISTER_COUNT)?.try_into().unwrap();
+        let memory = take(MEMORY_SIZE)?.to_vec();
+        let index = be_u16(take(2)?);
+        let pc = be_u16(take(2)?);
+        let mut stack = [0; STACK_SIZE];
+        for (addr, b) in stack.iter_mut().zip(take(2 * STACK_SIZE)?.chunks_exact(2)) {
+            *addr = be_u16(b);
+        }
+        let counters = take(3)?;
+        let (sp, delay_timer, sound_timer) = (counters[0], counters[1], counters[2]);
+        let mut keypad = [false; KEY_COUNT];
+        for (key, &b) in keypad.iter_mut().zip(take(KEY_COUNT)?) {
+            *key = b != 0;
+        }
+        let hires = take(1)?[0] != 0;
+        let size = if hires { HIRES_VIDEO_SIZE } else { VIDEO_SIZE };
+        let pixels = |bits: &[u8]| -> Vec<u32> {
+            bits.iter()
+                .flat_map(|&byte| (0..8).map(move |i| if byte & (0x80 >> i) != 0 { 0xFFFFFFFF } else { 0 }))
+                .collect()
+        };
+        let video = pixels(take(size / 8)?);
+        let video_plane2 = pixels(take(size / 8)?);
+        let plane_mask = take(1)?[0];
+
+        if (sp as usize) > STACK_SIZE {
+            return Err(anyhow::anyhow!("Save state is corrupt: sp {}", sp));
+        }
+
+        Ok(Self {
+            registers, memory, index, pc, stack, sp, delay_timer, sound_timer, keypad, video, hires,
+            video_plane2, plane_mask,
+        })
     }
+}
 
-    // Bnnn - JP V0, addr, Jump to location nnn + V0.
-    fn op_bnnn(&mut self) {
-        let address = self.opcode & 0x0FFF;
-        self.pc = address + self.registers[0] as u16;
-        self.debug_print(&format!("JP V0, 0x{:03X}", address));
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+impl Chip8State {
+    // One FNV-1a pass over every field. Stable across builds, unlike DefaultHasher.
+    pub fn fnv_hash(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, &self.registers);
+        hash = fnv1a(hash, &self.memory);
+        hash = fnv1a(hash, &self.index.to_le_bytes());
+        hash = fnv1a(hash, &self.pc.to_le_bytes());
+        for addr in &self.stack {
+            hash = fnv1a(hash, &addr.to_le_bytes());
+        }
+        hash = fnv1a(hash, &[self.sp, self.delay_timer, self.sound_timer]);
+        for &key in &self.keypad {
+            hash = fnv1a(hash, &[key as u8]);
+        }
+        for pixel in &self.video {
+            hash = fnv1a(hash, &pixel.to_le_bytes());
+        }
+        hash = fnv1a(hash, &[self.hires as u8]);
+        for pixel in &self.video_plane2 {
+            hash = fnv1a(hash, &pixel.to_le_bytes());
+        }
+        fnv1a(hash, &[self.plane_mask])
     }
+}
 
-    // Cxkk - RND Vx, byte, Set Vx = random byte AND kk.
-    fn op_cxkk(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let byte = (self.opcode & 0x00FF) as u8;
+// Handed to the hasher as a single u64 so the 64KB of memory and the video don't go
+// through the hasher byte by byte
+impl std::hash::Hash for Chip8State {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(self.fnv_hash());
+    }
+}
 
-        self.registers[vx] = self.random_byte() & byte;
-        self.debug_print(&format!("RND V{:X}, 0x{:02X}", vx, byte));
+#[allow(dead_code)]
+pub struct Chip8 {
+    registers: [u8; REGISTER_COUNT],
+    memory: Vec<u8>, // MEMORY_SIZE bytes, on the heap since XO-CHIP made it 64KB
+    index: u16,
+    pc: u16,
+    stack: [u16; STACK_SIZE],
+    sp: u8,
+    delay_timer: u8,
+    sound_timer: u8,
+    keypad: [bool; KEY_COUNT],
+    video: Vec<u32>, // video_width() x video_height(), row-major
+    hires: bool,     // SUPER-CHIP 128x64 mode
+    opcode: u16,
+    // Where PC goes once the instruction at PC finishes: the next one unless it jumps, skips
+    // or has to run again
+    next_pc: u16,
+    start_address: u16, // Where the ROM was loaded, and where reset() starts again
+    rng: Box<dyn FnMut() -> u8>, // Source of RND values
+    debug: bool,
+    quirks: QuirksConfig,
+    hotspot_threshold: Option<u64>, // Execution counting is off until a threshold is set
+    hotspot_counts: HashMap<u16, u64>,
+    opcode_stats: OpcodeStats,
+    hotspots: HashSet<u16>,
+    // Pre-rendered font sprites so Dxyn can skip per-row memory reads for font digits.
+    // Invalidated if the ROM writes over the fontset.
+    font_atlas: [[u32; FONT_ATLAS_GLYPH_SIZE]; 16],
+    font_atlas_valid: bool,
+    memory_read_count: u64,
+    memory_write_count: u64,
+    memory_writes_by_addr: Vec<u64>,
+    display_dirty: bool, // Set whenever a pixel changes
+    data_regions: Vec<(u16, u16)>, // (start, len) ranges disassembled as bytes; the fontset is implicit
+    // Frame-rate governor: cycles_per_frame is measured against the 60Hz timer clock
+    cycles_per_frame: u32,
+    target_fps: f64,
+    cycle_budget: f64, // Fractional cycles carried over to the next frame
+    frame_cycles: u32, // Instructions cycle() has run since the timers last ticked
+    cycle_count: u64,  // Instructions executed since power-on
+    waiting_for_key: bool, // Last instruction was an Fx0A still waiting for a key
+    waiting_key: Option<u8>, // Key Fx0A saw go down; it completes once that key is released
+    vblank: bool, // The timers ticked since the last draw, see QuirksConfig::display_wait
+    // XO-CHIP's second bitplane, laid out like `video`, which is the first. FN01 sets
+    // plane_mask: bit 0 selects `video`, bit 1 this.
+    video_plane2: Vec<u32>,
+    plane_mask: u8,
+    last_error: Option<ExecutionError>,
+    cycle_error: Option<ExecutionError>, // The first error in the current cycle(), if any
+    halted: bool, // Stopped by 00FD; nothing runs until reset
+    breakpoints: HashSet<u16>,
+    hit_breakpoint: bool, // cycle() stopped at a breakpoint; nothing runs until it's taken
+    resume_at: Option<u16>, // Breakpoint just taken, so the next cycle() executes it
+    trace: Option<BufWriter<fs::File>>, // See enable_trace; flushed when dropped
+    // Most recent cycle last, at most rewind_depth of them. rewind_frame is the cycle running now.
+    rewind: VecDeque<RewindFrame>,
+    rewind_depth: usize,
+    rewind_frame: Option<RewindFrame>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    // Dxyn - DRW Vx, Vy, nibble
-    // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
-    fn op_dxyn(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
-        let height = (self.opcode & 0x000F) as usize;
+#[allow(dead_code)]
+impl Chip8 {
+    // Machine dimensions for embedders, e.g. `[u32; Chip8::VIDEO_SIZE]`
+    pub const MEMORY_SIZE: usize = MEMORY_SIZE;
+    pub const START_ADDRESS: u16 = START_ADDRESS;
+    pub const REGISTER_COUNT: usize = REGISTER_COUNT;
+    pub const STACK_SIZE: usize = STACK_SIZE;
+    pub const KEY_COUNT: usize = KEY_COUNT;
+    pub const VIDEO_WIDTH: usize = VIDEO_WIDTH;
+    pub const VIDEO_HEIGHT: usize = VIDEO_HEIGHT;
+    pub const VIDEO_SIZE: usize = VIDEO_SIZE;
+    pub const HIRES_VIDEO_WIDTH: usize = HIRES_VIDEO_WIDTH;
+    pub const HIRES_VIDEO_HEIGHT: usize = HIRES_VIDEO_HEIGHT;
+    pub const HIRES_VIDEO_SIZE: usize = HIRES_VIDEO_SIZE;
+    pub const PLANE2_PIXEL: u32 = PLANE2_PIXEL;
+    pub const BOTH_PLANES_PIXEL: u32 = BOTH_PLANES_PIXEL;
+    pub const FONTSET_SIZE: usize = FONTSET_SIZE;
 
-        let x_pos = self.registers[vx] as usize % VIDEO_WIDTH;
-        let y_pos = self.registers[vy] as usize % VIDEO_HEIGHT;
+    pub fn new() -> Self {
+        let mut chip8 = Self {
+            registers: [0; REGISTER_COUNT],
+            memory: vec![0; MEMORY_SIZE],
+            index: 0,
+            pc: START_ADDRESS,
+            stack: [0; STACK_SIZE],
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            keypad: [false; KEY_COUNT],
+            video: vec![0; VIDEO_SIZE],
+            hires: false,
+            opcode: 0,
+            next_pc: START_ADDRESS,
+            start_address: START_ADDRESS,
+            rng: seeded_rng(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos() as u64,
+            ),
+            debug: true, // Enable debug output initially
+            quirks: QuirksConfig::default(),
+            hotspot_threshold: None,
+            hotspot_counts: HashMap::new(),
+            opcode_stats: OpcodeStats::default(),
+            hotspots: HashSet::new(),
+            font_atlas: [[0; FONT_ATLAS_GLYPH_SIZE]; 16],
+            font_atlas_valid: false,
+            memory_read_count: 0,
+            memory_write_count: 0,
+            memory_writes_by_addr: vec![0; MEMORY_SIZE],
+            display_dirty: false,
+            data_regions: Vec::new(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            target_fps: 60.0,
+            cycle_budget: 0.0,
+            frame_cycles: 0,
+            cycle_count: 0,
+            waiting_for_key: false,
+            waiting_key: None,
+            vblank: true,
+            video_plane2: vec![0; VIDEO_SIZE],
+            plane_mask: 1,
+            last_error: None,
+            cycle_error: None,
+            halted: false,
+            breakpoints: HashSet::new(),
+            hit_breakpoint: false,
+            resume_at: None,
+            trace: None,
+            rewind: VecDeque::new(),
+            rewind_depth: 0,
+            rewind_frame: None,
+        };
 
-        self.debug_print(&format!("DRW V{:X}, V{:X}, {} at ({}, {})", vx, vy, height, x_pos, y_pos));
+        chip8.load_fontset();
+        chip8
+    }
 
-        self.registers[0xF] = 0; // Clear collision flag
+    pub fn enable_debug(&mut self, enabled: bool) {
+        self.debug = enabled;
+    }
+
+    pub fn set_quirks(&mut self, quirks: QuirksConfig) {
+        self.quirks = quirks;
+    }
+
+    pub fn get_quirks(&self) -> QuirksConfig {
+        self.quirks
+    }
+
+    // On stderr, so it never mixes into output meant for other programs (--dump-state)
+    fn debug_print(&self, message: &str) {
+        if self.debug {
+            eprintln!("DEBUG: {}", message);
+        }
+    }
+
+    // Back to power-on CPU and display state with the loaded ROM still in memory, so it runs
+    // again from the start. Configuration (quirks, speed, RNG, debug, hotspot threshold) is
+    // kept; the profiling counters start over.
+    pub fn reset(&mut self) {
+        self.registers = [0; REGISTER_COUNT];
+        self.video.clear();
+        self.video.resize(VIDEO_SIZE, 0);
+        self.video_plane2.clear();
+        self.video_plane2.resize(VIDEO_SIZE, 0);
+        self.plane_mask = 1;
+        self.hires = false;
+        self.stack = [0; STACK_SIZE];
+        self.keypad = [false; KEY_COUNT];
+        self.pc = self.start_address;
+        self.index = 0;
+        self.sp = 0;
+        self.opcode = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.waiting_for_key = false;
+        self.waiting_key = None;
+        self.vblank = true;
+        self.last_error = None;
+        self.cycle_error = None;
+        self.halted = false;
+        self.hit_breakpoint = false;
+        self.resume_at = None;
+        self.rewind.clear();
+        self.display_dirty = false;
+        self.cycle_budget = 0.0;
+        self.frame_cycles = 0;
+        self.cycle_count = 0;
+        self.opcode_stats = OpcodeStats::default();
+        self.hotspot_counts.clear();
+        self.hotspots.clear();
+        self.memory_read_count = 0;
+        self.memory_write_count = 0;
+        self.memory_writes_by_addr.fill(0);
+        self.load_fontset(); // Counted as writes, as at power-on
+    }
+
+    // reset(), and clear everything from START_ADDRESS up, ready for a different ROM
+    pub fn reset_and_reload(&mut self) {
+        self.start_address = START_ADDRESS;
+        self.reset();
+        self.memory[START_ADDRESS as usize..].fill(0);
+    }
+
+    fn load_fontset(&mut self) {
+        self.load_fontset_from_bytes(&FONTSET)
+            .expect("built-in fontset fits in memory");
+    }
 
-        for row in 0..height {
-            let sprite_byte = self.memory[(self.index + row as u16) as usize];
-            self.debug_print(&format!("  Row {}: 0b{:08b} (0x{:02X})", row, sprite_byte, sprite_byte));
+    // Replace the 16 hex digit glyphs (5 bytes each) that Fx29 points at
+    pub fn load_fontset_from_bytes(&mut self, bytes: &[u8; FONTSET_SIZE]) -> Result<()> {
+        let start = FONTSET_START_ADDRESS as usize;
+        if start + FONTSET_SIZE > MEMORY_SIZE {
+            return Err(anyhow::anyhow!("Fontset at 0x{:03X} does not fit in memory", start));
+        }
 
-            for col in 0..8 {
-                let sprite_pixel = sprite_byte & (0x80 >> col);
+        self.memory[start..start + FONTSET_SIZE].copy_from_slice(bytes);
+        self.debug_print(&format!("Loaded fontset at 0x{:03X}", start));
+        self.build_font_atlas();
+        Ok(())
+    }
 
-                if sprite_pixel == 0 { continue; }
-                if (x_pos + col) >= VIDEO_WIDTH { continue; }
-                if (y_pos + row) >= VIDEO_HEIGHT { continue; }
+    // The fontset as it currently sits in memory
+    pub fn get_fontset(&self) -> &[u8; FONTSET_SIZE] {
+        let start = FONTSET_START_ADDRESS as usize;
+        self.memory[start..start + FONTSET_SIZE].try_into().unwrap()
+    }
 
-                let screen_pixel_index = (y_pos + row) * VIDEO_WIDTH + (x_pos + col);
-                if self.video[screen_pixel_index] == 0xFFFFFFFF {
-                    self.registers[0xF] = 1;
+    fn build_font_atlas(&mut self) {
+        let start = FONTSET_START_ADDRESS as usize;
+        for (digit, glyph) in self.font_atlas.iter_mut().enumerate() {
+            for row in 0..FONT_GLYPH_HEIGHT {
+                let sprite_byte = self.memory[start + digit * FONT_GLYPH_HEIGHT + row];
+                for col in 0..8 {
+                    glyph[row * 8 + col] = if sprite_byte & (0x80 >> col) != 0 { 0xFFFFFFFF } else { 0 };
                 }
-                self.video[screen_pixel_index] ^= 0xFFFFFFFF;
             }
         }
+        self.font_atlas_valid = true;
+    }
 
-        // Count pixels that are on for debugging
-        let pixels_on = self.video.iter().filter(|&&p| p == 0xFFFFFFFF).count();
-        self.debug_print(&format!("  Pixels on after draw: {}", pixels_on));
+    // Called after writing memory[start..start + len]: updates access stats and drops the
+    // font atlas if the fontset was overwritten. The range wraps at the end of memory.
+    fn memory_written(&mut self, start: usize, len: usize) {
+        self.memory_write_count += len as u64;
+        let font = FONTSET_START_ADDRESS as usize..FONTSET_START_ADDRESS as usize + FONTSET_SIZE;
+        for addr in (start..start + len).map(|addr| addr % MEMORY_SIZE) {
+            self.memory_writes_by_addr[addr] += 1;
+            if font.contains(&addr) {
+                self.font_atlas_valid = false;
+            }
+        }
     }
 
-    // Ex9E - SKP Vx, Skip next instruction if key with the value of Vx is pressed.
-    fn op_ex9e(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let key = self.registers[vx] as usize;
+    fn record_error(&mut self, error: ExecutionError) {
+        self.last_error = Some(error);
+        self.cycle_error.get_or_insert(error);
+    }
 
-        if key < KEY_COUNT && self.keypad[key] {
-            self.pc += 2;
+    fn unknown_opcode(&mut self) {
+        self.record_error(ExecutionError::UnknownOpcode { pc: self.pc, opcode: self.opcode });
+        self.debug_print(&format!("Unknown opcode: 0x{:04X}, skipped", self.opcode));
+    }
+
+    // The address `offset` bytes past `base`, for an instruction reading or writing memory.
+    // Running off the end wraps to 0, as on XO-CHIP, and is recorded in last_error. That's
+    // defined behaviour rather than a broken ROM, so cycle() doesn't return it.
+    fn memory_addr(&mut self, base: u16, offset: usize) -> usize {
+        let addr = base as usize + offset;
+        if addr >= MEMORY_SIZE {
+            self.last_error = Some(ExecutionError::MemoryWrap { pc: self.pc, addr: base });
         }
-        self.debug_print(&format!("SKP V{:X}", vx));
+        addr % MEMORY_SIZE
     }
 
-    // ExA1 - SKNP Vx, Skip next instruction if key with the value of Vx is not pressed.
-    fn op_exa1(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let key = self.registers[vx] as usize;
+    pub fn total_memory_writes(&self) -> u64 {
+        self.memory_write_count
+    }
 
-        if key >= KEY_COUNT || !self.keypad[key] {
-            self.pc += 2;
+    pub fn memory_access_stats(&self) -> MemoryAccessStats {
+        let hottest_addr = self.memory_writes_by_addr.iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .max_by_key(|&(_, &count)| count)
+            .map_or(0, |(addr, _)| addr as u16);
+
+        MemoryAccessStats {
+            reads: self.memory_read_count,
+            writes: self.memory_write_count,
+            hottest_addr,
         }
-        self.debug_print(&format!("SKNP V{:X}", vx));
     }
 
-    // Fx07 - LD Vx, DT, Set Vx = delay timer value.
-    fn op_fx07(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.registers[vx] = self.delay_timer;
-        self.debug_print(&format!("LD V{:X}, DT", vx));
+    // The atlas glyph Dxyn is about to draw, if I points at the start of a font digit
+    fn font_glyph_at_index(&self, height: usize) -> Option<usize> {
+        let offset = (self.index as usize).checked_sub(FONTSET_START_ADDRESS as usize)?;
+        if !self.font_atlas_valid
+            || height > FONT_GLYPH_HEIGHT
+            || offset >= FONTSET_SIZE
+            || offset % FONT_GLYPH_HEIGHT != 0
+        {
+            return None;
+        }
+        Some(offset / FONT_GLYPH_HEIGHT)
+    }
+
+    pub fn load_rom(&mut self, filename: &str) -> Result<(), Chip8Error> {
+        self.load_rom_at(filename, START_ADDRESS)
+    }
+
+    // Load a ROM at `addr` instead of 0x200 (ETI-660 programs start at 0x600) and start
+    // running it there
+    pub fn load_rom_at(&mut self, filename: &str, addr: u16) -> Result<(), Chip8Error> {
+        let rom_data = read_rom_file(filename)?;
+        let start = addr as usize;
+        let max = MEMORY_SIZE - start;
+        if rom_data.len() > max {
+            return Err(Chip8Error::RomTooLarge { path: filename.to_string(), size: rom_data.len(), max, addr });
+        }
+
+        self.memory[start..start + rom_data.len()].copy_from_slice(&rom_data);
+        self.memory_written(start, rom_data.len());
+        self.debug_print(&format!("Loaded ROM: {} bytes at 0x{:03X}", rom_data.len(), start));
+        self.start_address = addr;
+        self.pc = addr;
+        Ok(())
+    }
+
+    // Load a ROM with an IPS patch applied on top, leaving both files untouched
+    pub fn load_rom_with_patch(&mut self, rom_path: &Path, patch_path: &Path) -> Result<()> {
+        let rom = fs::read(rom_path)?;
+        let patch = fs::read(patch_path)?;
+        let patched = patch::apply_ips(&rom, &patch)
+            .map_err(|e| anyhow::anyhow!("{}: {}", patch_path.display(), e))?;
+        self.load_rom_bytes(&patched, true)?;
+        Ok(())
+    }
+
+    // Load a ROM at 0x200. A ROM that doesn't fit is an error (Chip8Error::PartialRom) when
+    // `strict`, otherwise the bytes that fit are loaded and the rest dropped.
+    pub fn load_rom_bytes(&mut self, rom_data: &[u8], strict: bool) -> Result<(), Chip8Error> {
+        if strict && rom_data.len() > MEMORY_SIZE - START_ADDRESS as usize {
+            return Err(Chip8Error::PartialRom {
+                loaded: 0,
+                expected: rom_data.len(),
+            });
+        }
+
+        if let (_, Some(warning)) = self.load_rom_partial(rom_data) {
+            self.debug_print(&format!("Warning: {}", warning));
+        }
+        Ok(())
+    }
+
+    // Load as much of `rom_data` as fits in memory. Returns the number of bytes loaded, plus a
+    // PartialRom warning if some had to be dropped; the caller decides whether to run it.
+    pub fn load_rom_partial(&mut self, rom_data: &[u8]) -> (usize, Option<Chip8Error>) {
+        let start = START_ADDRESS as usize;
+        let loaded = rom_data.len().min(MEMORY_SIZE - start);
+
+        self.memory[start..start + loaded].copy_from_slice(&rom_data[..loaded]);
+        self.memory_written(start, loaded);
+        self.debug_print(&format!("Loaded ROM: {} bytes at 0x{:03X}", loaded, start));
+
+        let warning = (loaded < rom_data.len()).then_some(Chip8Error::PartialRom {
+            loaded,
+            expected: rom_data.len(),
+        });
+        (loaded, warning)
+    }
+
+    // Load a ROM that may start with a FriesRomHeader, applying its quirks. Returns the header
+    // if there was one; files without the magic bytes are loaded as plain ROMs.
+    pub fn load_rom_with_header(&mut self, filename: &str) -> Result<Option<FriesRomHeader>> {
+        let data = read_rom_file(filename)?;
+        if !data.starts_with(&ROM_HEADER_MAGIC) {
+            self.load_rom_bytes(&data, true)?;
+            return Ok(None);
+        }
+
+        let header = FriesRomHeader::parse(&data)?;
+        self.load_rom_bytes(&data[ROM_HEADER_SIZE..], true)?;
+        self.set_quirks(header.quirks());
+        if header.cpf != 0 {
+            self.set_cycles_per_frame(header.cpf as u32);
+        }
+        self.debug_print(&format!(
+            "ROM header: \"{}\" by {} (v{}, quirks 0x{:04X}, cpf {})",
+            header.name_str(),
+            header.author_str(),
+            header.version,
+            header.quirks_flags,
+            header.cpf
+        ));
+        Ok(Some(header))
+    }
+
+    pub fn save_rom_with_header(filename: &str, header: &FriesRomHeader, rom: &[u8]) -> Result<()> {
+        let mut data = header.to_bytes();
+        data.extend_from_slice(rom);
+        fs::write(filename, data)?;
+        Ok(())
+    }
+
+    // Replace the instruction at `offset` bytes into the loaded ROM
+    pub fn patch_rom(&mut self, offset: u16, opcode: u16) -> Result<()> {
+        let addr = START_ADDRESS as usize + offset as usize;
+        if addr + 1 >= MEMORY_SIZE {
+            return Err(anyhow::anyhow!("Patch offset 0x{:03X} is outside the ROM area", offset));
+        }
+
+        let old_opcode = ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16;
+        self.memory[addr] = (opcode >> 8) as u8;
+        self.memory[addr + 1] = (opcode & 0x00FF) as u8;
+        self.memory_written(addr, 2);
+
+        self.debug_print(&format!(
+            "RomPatched {{ offset: 0x{:03X}, old_opcode: 0x{:04X}, new_opcode: 0x{:04X} }}",
+            offset, old_opcode, opcode
+        ));
+        Ok(())
+    }
+
+    // Write pasted bytes over memory at PC. `text` is hex ("A2 2A 60 0C", whitespace ignored)
+    // or, failing that, base64. Returns the number of bytes written.
+    pub fn paste_at_pc(&mut self, text: &str) -> Result<usize> {
+        let bytes = parse_overlay_text(text)?;
+        let start = self.pc as usize;
+        if start + bytes.len() > MEMORY_SIZE {
+            return Err(anyhow::anyhow!(
+                "Pasted {} bytes at 0x{:03X} would run past the end of memory",
+                bytes.len(),
+                start
+            ));
+        }
+
+        self.memory[start..start + bytes.len()].copy_from_slice(&bytes);
+        self.memory_written(start, bytes.len());
+        self.debug_print(&format!("Pasted {} bytes at 0x{:03X}", bytes.len(), start));
+        Ok(bytes.len())
+    }
+
+    // Peek and poke RAM for tools and tests. Memory is 64KB, so every u16 is an address.
+    pub fn read_memory(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    pub fn write_memory(&mut self, addr: u16, value: u8) {
+        self.memory[addr as usize] = value;
+        self.memory_written(addr as usize, 1);
+    }
+
+    // Up to `len` bytes from `start`, cut short at the end of memory
+    pub fn memory_slice(&self, start: u16, len: usize) -> &[u8] {
+        let start = start as usize;
+        &self.memory[start..start.saturating_add(len).min(MEMORY_SIZE)]
+    }
+
+    // Take RND values from `rng` instead of the system-seeded generator
+    pub fn with_rng(mut self, rng: impl FnMut() -> u8 + 'static) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    // Reproducible RND values from a fixed seed
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = seeded_rng(seed);
+        self
+    }
+
+    // RND returns `values` in order, starting over after the last one. Panics if empty.
+    pub fn with_deterministic_sequence(self, values: &[u8]) -> Self {
+        assert!(!values.is_empty(), "deterministic sequence needs at least one value");
+        let values = values.to_vec();
+        let mut next = 0;
+        self.with_rng(move || {
+            let value = values[next];
+            next = (next + 1) % values.len();
+            value
+        })
+    }
+
+    pub fn random_byte(&mut self) -> u8 {
+        (self.rng)()
+    }
+
+    // Endless iterator over the values RND would use next
+    pub fn rng_stream(&mut self) -> Chip8RngStream<'_> {
+        Chip8RngStream(self)
+    }
+
+    // The display at the current resolution, video_width() pixels per row
+    pub fn get_display(&self) -> &[u32] {
+        &self.video
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn video_width(&self) -> usize {
+        if self.hires { HIRES_VIDEO_WIDTH } else { VIDEO_WIDTH }
+    }
+
+    pub fn video_height(&self) -> usize {
+        if self.hires { HIRES_VIDEO_HEIGHT } else { VIDEO_HEIGHT }
+    }
+
+    // Switch resolution. The display is cleared, since old pixels have no place in the new
+    // grid.
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        if self.video.iter().any(|&pixel| pixel != 0) {
+            self.display_dirty = true;
+        }
+        self.video = vec![0; self.video_width() * self.video_height()];
+        self.video_plane2 = vec![0; self.video.len()];
+    }
+
+    // The display as terminal rows of half blocks (16 in low-res): each '▀' shows an upper pixel in its
+    // foreground color and the lower one in its background color
+    pub fn display_as_ansi(&self, fg_color: AnsiColor, bg_color: AnsiColor) -> String {
+        let color = |pixel: u32| if pixel != 0 { fg_color } else { bg_color };
+        let mut out = String::new();
+
+        let width = self.video_width();
+        for y in (0..self.video_height()).step_by(2) {
+            let mut current = None;
+            for x in 0..width {
+                let upper = color(self.video[y * width + x]);
+                let lower = color(self.video[(y + 1) * width + x]);
+                // Only emit an escape when the colors change
+                if current != Some((upper, lower)) {
+                    out += &format!("\x1b[{};{}m", upper.fg_code(), lower.fg_code() + 10);
+                    current = Some((upper, lower));
+                }
+                out.push('▀');
+            }
+            out += "\x1b[0m\n";
+        }
+        out
+    }
+
+    // The display as plain text, for logs: one line per row, '█' for on pixels and ' ' for off
+    pub fn display_ascii(&self) -> String {
+        let mut out = String::with_capacity(self.video.len() + self.video_height());
+        for row in self.video_iter_rows() {
+            out.extend(row.iter().map(|&pixel| if pixel != 0 { '█' } else { ' ' }));
+            out.push('\n');
+        }
+        out
+    }
+
+    // The display top to bottom, one video_width() slice per row
+    pub fn video_iter_rows(&self) -> impl Iterator<Item = &[u32]> {
+        self.video.chunks_exact(self.video_width())
+    }
+
+    // 64-bit FNV-1a over both planes, top to bottom. Unlike DefaultHasher the value never
+    // changes between builds, so tests can pin a frame to it.
+    pub fn display_hash(&self) -> u64 {
+        video_hash(&self.video, &self.video_plane2)
+    }
+
+    // Copy the display into a caller-owned buffer, avoiding a per-frame allocation.
+    // Panics if `buf` is shorter than the display.
+    // Pixels lit only in the first plane are 0xFFFFFFFF as always; XO-CHIP's second plane adds
+    // PLANE2_PIXEL and BOTH_PLANES_PIXEL.
+    pub fn copy_display_to_buffer(&self, buf: &mut [u32]) {
+        let size = self.video.len();
+        assert!(buf.len() >= size, "display buffer too small: {} < {}", buf.len(), size);
+        for ((out, &plane1), &plane2) in buf.iter_mut().zip(&self.video).zip(&self.video_plane2) {
+            *out = match (plane1 != 0, plane2 != 0) {
+                (_, false) => plane1,
+                (false, true) => PLANE2_PIXEL,
+                (true, true) => BOTH_PLANES_PIXEL,
+            };
+        }
+    }
+
+    // Raw views for C embedders. Getting the pointers is safe; reading through them is only
+    // valid while this Chip8 is alive and not being mutated.
+    pub fn memory_as_ptr(&self) -> *const u8 {
+        self.memory.as_ptr()
+    }
+
+    pub fn memory_len(&self) -> usize {
+        MEMORY_SIZE
+    }
+
+    pub fn video_as_ptr(&self) -> *const u32 {
+        self.video.as_ptr()
+    }
+
+    pub fn video_len(&self) -> usize {
+        self.video.len()
+    }
+
+    pub fn get_frame_buffer(&self) -> FrameBuffer {
+        FrameBuffer { pixels: self.video.clone(), width: self.video_width() }
+    }
+
+    pub fn save_state(&self) -> Chip8State {
+        Chip8State {
+            registers: self.registers,
+            memory: self.memory.clone(),
+            index: self.index,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keypad: self.keypad,
+            video: self.video.clone(),
+            hires: self.hires,
+            video_plane2: self.video_plane2.clone(),
+            plane_mask: self.plane_mask,
+        }
+    }
+
+    // Restore a snapshot from save_state. The RNG isn't part of the snapshot, so it is reseeded
+    // from the state itself: loading the same snapshot always replays the same RND values.
+    pub fn load_state(&mut self, state: &Chip8State) {
+        self.registers = state.registers;
+        // A hand-built or 4KB-era state may hold less (or more) than 64KB: copy what fits and
+        // zero the rest
+        let len = state.memory.len().min(MEMORY_SIZE);
+        self.memory[..len].copy_from_slice(&state.memory[..len]);
+        self.memory[len..].fill(0);
+        self.index = state.index;
+        self.pc = state.pc;
+        self.stack = state.stack;
+        self.sp = state.sp;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.keypad = state.keypad;
+        self.video = state.video.clone();
+        self.hires = state.hires;
+        self.video_plane2 = state.video_plane2.clone();
+        self.plane_mask = state.plane_mask;
+
+        self.waiting_for_key = false;
+        self.waiting_key = None;
+        self.hit_breakpoint = false;
+        self.resume_at = None;
+        self.rewind.clear();
+        self.vblank = true;
+        self.halted = false;
+        self.last_error = None;
+        self.display_dirty = true;
+        self.build_font_atlas();
+
+        // FNV-1a rather than DefaultHasher, which may change between builds
+        self.rng = seeded_rng(state.fnv_hash());
+    }
+
+    // Current state as C definitions, for checking against a reference implementation
+    // on hardware that can't run Rust
+    pub fn export_state_as_c_header(&self) -> String {
+        fn array<T: std::fmt::UpperHex>(c_type: &str, name: &str, values: &[T], digits: usize)
+            -> String {
+            let rows: Vec<String> = values.chunks(16)
+                .map(|row| {
+                    let cells: Vec<String> = row.iter()
+                        .map(|value| format!("0x{:0width$X}", value, width = digits))
+                        .collect();
+                    format!("    {}", cells.join(", "))
+                })
+                .collect();
+            format!("const {} {}[{}] = {{\n{}\n}};\n", c_type, name, values.len(), rows.join(",\n"))
+        }
+
+        let mut header =
+            String::from("// CHIP-8 state exported by fries\n#pragma once\n#include <stdint.h>\n\n");
+        header += &format!("const uint16_t PC = 0x{:03X};\n", self.pc);
+        header += &format!("const uint16_t I = 0x{:03X};\n", self.index);
+        header += &format!("const uint8_t SP = 0x{:02X};\n", self.sp);
+        header += &format!("const uint8_t DELAY_TIMER = 0x{:02X};\n", self.delay_timer);
+        header += &format!("const uint8_t SOUND_TIMER = 0x{:02X};\n\n", self.sound_timer);
+        header += &array("uint8_t", "REGISTERS", &self.registers, 2);
+        header += &array("uint16_t", "STACK", &self.stack, 3);
+        header += &array("uint8_t", "MEMORY", &self.memory, 2);
+        header
+    }
+
+    // Run until the machine returns to a state it was already in, which means it's stuck in
+    // a loop. Only state hashes are kept; a hash seen before is confirmed by running one more
+    // period and checking the machine comes back to the exact same state. Returns the cycles
+    // run, confirmation included, or None if no repeat is confirmed within `timeout`.
+    pub fn run_until_cycle(&mut self, timeout: u64) -> Option<u64> {
+        let mut seen = HashMap::new();
+        seen.insert(self.save_state().fnv_hash(), 0);
+        let mut cycles = 0;
+        while cycles < timeout {
+            let _ = self.cycle();
+            cycles += 1;
+            let state = self.save_state();
+            let Some(&first_seen) = seen.get(&state.fnv_hash()) else {
+                seen.insert(state.fnv_hash(), cycles);
+                continue;
+            };
+            let confirm_at = cycles + (cycles - first_seen);
+            while cycles < confirm_at.min(timeout) {
+                let _ = self.cycle();
+                cycles += 1;
+                seen.entry(self.save_state().fnv_hash()).or_insert(cycles);
+            }
+            if cycles == confirm_at && self.save_state() == state {
+                return Some(cycles);
+            }
+        }
+        None
+    }
+
+    // Run until a pixel changes. Returns the number of cycles taken, including the one that drew.
+    pub fn run_until_display_changes(&mut self, max_cycles: u64) -> Result<u64, TimeoutError> {
+        self.display_dirty = false;
+        self.run_until(max_cycles, |chip8| chip8.display_dirty)
+    }
+
+    // Run until the next instruction to execute is at `target`
+    pub fn run_until_pc(&mut self, target: u16, max_cycles: u64) -> Result<u64, TimeoutError> {
+        self.run_until(max_cycles, |chip8| chip8.pc == target)
+    }
+
+    // Run until V`reg` holds `value`, the usual way test ROMs report a result
+    pub fn run_until_register_equals(&mut self, reg: usize, value: u8, max_cycles: u64)
+        -> Result<u64, TimeoutError> {
+        self.run_until(max_cycles, |chip8| chip8.registers[reg] == value)
+    }
+
+    // Cycle until `done` holds, checking before every cycle. Returns the cycles run.
+    fn run_until(&mut self, max_cycles: u64, done: impl Fn(&Self) -> bool) -> Result<u64, TimeoutError> {
+        let mut cycles = 0;
+        while !done(self) {
+            if cycles == max_cycles {
+                return Err(TimeoutError { cycles });
+            }
+            let _ = self.cycle();
+            cycles += 1;
+        }
+        Ok(cycles)
+    }
+
+    pub fn set_keys(&mut self, keys: &[bool; KEY_COUNT]) {
+        self.keypad = *keys;
+    }
+
+    // Start counting executions per address; addresses run `n` times become hotspots
+    pub fn set_hotspot_threshold(&mut self, n: u64) {
+        self.hotspot_threshold = Some(n);
+    }
+
+    pub fn get_hotspots(&self) -> &HashSet<u16> {
+        &self.hotspots
+    }
+
+    pub fn get_hotspot_counts(&self) -> &HashMap<u16, u64> {
+        &self.hotspot_counts
+    }
+
+    // Instructions run since the last reset, by kind
+    pub fn opcode_stats(&self) -> OpcodeStats {
+        self.opcode_stats.clone()
+    }
+
+    // Make cycle() stop before executing the instruction at `addr`. Breakpoints survive reset.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    // Append every executed instruction to `path`, disassembled and with the registers it
+    // changed, e.g. "0x0204: 6A55 LD VA, 0x55  [VA=0x55]". Separate from the debug output.
+    pub fn enable_trace(&mut self, path: &str) -> Result<()> {
+        let file = fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path, e))?;
+        self.trace = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    // Keep the last `depth` cycles so step_back can undo them; 0 turns recording off
+    pub fn set_rewind_depth(&mut self, depth: usize) {
+        self.rewind_depth = depth;
+        while self.rewind.len() > depth {
+            self.rewind.pop_front();
+        }
+    }
+
+    // Undo the most recent recorded cycle. Returns false once the history runs out.
+    pub fn step_back(&mut self) -> bool {
+        let Some(frame) = self.rewind.pop_back() else {
+            return false;
+        };
+        self.registers = frame.registers;
+        self.index = frame.index;
+        self.pc = frame.pc;
+        self.stack = frame.stack;
+        self.sp = frame.sp;
+        self.delay_timer = frame.delay_timer;
+        self.sound_timer = frame.sound_timer;
+        self.cycle_count = frame.cycle_count;
+        self.waiting_key = frame.waiting_key;
+        self.vblank = frame.vblank;
+        self.plane_mask = frame.plane_mask;
+        if let Some((video, video_plane2, hires)) = frame.video {
+            (self.video, self.video_plane2, self.hires) = (video, video_plane2, hires);
+            self.display_dirty = true;
+        }
+        // Newest first, so a byte written twice ends up with its oldest value
+        let font = FONTSET_START_ADDRESS as usize..FONTSET_START_ADDRESS as usize + FONTSET_SIZE;
+        for &(addr, old) in frame.memory.iter().rev() {
+            self.memory[addr] = old;
+            if font.contains(&addr) {
+                self.font_atlas_valid = false;
+            }
+        }
+        self.halted = false;
+        self.debug_print(&format!("Stepped back to 0x{:03X}", self.pc));
+        true
+    }
+
+    // State from before the instruction at PC, which has just been fetched into `opcode`
+    fn rewind_snapshot(&self) -> RewindFrame {
+        let draws = matches!(self.opcode, 0x00E0 | 0x00C0..=0x00CF | 0x00FB..=0x00FF | 0xD000..=0xDFFF);
+        RewindFrame {
+            registers: self.registers,
+            index: self.index,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            cycle_count: self.cycle_count - 1,
+            waiting_key: self.waiting_key,
+            vblank: self.vblank,
+            plane_mask: self.plane_mask,
+            video: draws.then(|| (self.video.clone(), self.video_plane2.clone(), self.hires)),
+            memory: Vec::new(),
+        }
+    }
+
+    // Write one byte for an instruction, journaling the old value if the cycle is recorded
+    fn store(&mut self, addr: usize, value: u8) {
+        if let Some(frame) = self.rewind_frame.as_mut() {
+            frame.memory.push((addr, self.memory[addr]));
+        }
+        self.memory[addr] = value;
+    }
+
+    // The address cycle() stopped at, if it hit a breakpoint. Taking it lets execution
+    // continue, starting with the instruction at the breakpoint.
+    pub fn take_breakpoint_hit(&mut self) -> Option<u16> {
+        if !std::mem::take(&mut self.hit_breakpoint) {
+            return None;
+        }
+        self.resume_at = Some(self.pc);
+        Some(self.pc)
+    }
+
+    // One cycle, except that a CALL runs its whole subroutine and stops at the instruction
+    // after it. A breakpoint or an error inside the subroutine stops it early, and it gives up
+    // after STEP_OVER_MAX_CYCLES. Stepping over from a breakpoint resumes from it first.
+    pub fn step_over(&mut self) -> Result<(), ExecutionError> {
+        self.take_breakpoint_hit();
+        let is_call = self.get_opcode_at(self.pc).is_some_and(|opcode| opcode & 0xF000 == 0x2000);
+        if !is_call {
+            return self.cycle();
+        }
+
+        let return_addr = self.pc.wrapping_add(2);
+        let temporary = self.breakpoints.insert(return_addr);
+        let result = self.run_to_return(return_addr);
+        if temporary {
+            self.breakpoints.remove(&return_addr);
+            if self.hit_breakpoint && self.pc == return_addr {
+                self.hit_breakpoint = false; // Our own stop, not one for the host
+            }
+        }
+        result
+    }
+
+    // step_over's CALL: run until a breakpoint stops execution at `return_addr` with the
+    // stack back at the caller's depth
+    fn run_to_return(&mut self, return_addr: u16) -> Result<(), ExecutionError> {
+        let sp = self.sp;
+        self.cycle()?;
+        for _ in 0..STEP_OVER_MAX_CYCLES {
+            if self.halted {
+                break;
+            }
+            if self.hit_breakpoint {
+                // A recursive call reaching the same address isn't back at our level yet
+                if self.pc != return_addr || self.sp <= sp {
+                    break;
+                }
+                self.take_breakpoint_hit();
+            }
+            self.cycle()?;
+        }
+        Ok(())
+    }
+
+    // Assemble a single instruction, e.g. ("LD", [5, 0x42]) -> 0x6542.
+    // The mnemonic is either a bare name ("LD") or a full form ("LD Vx, Vy").
+    pub fn encode_opcode(mnemonic: &str, operands: &[u16]) -> Result<u16> {
+        let wanted = mnemonic.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase();
+        let is_bare = !wanted.contains(' ');
+
+        let mut name_known = false;
+        let form = INSTRUCTION_FORMS.iter().find(|(form, _, _, slots)| {
+            let form = form.to_uppercase();
+            let matches = if is_bare {
+                form.split(' ').next() == Some(wanted.as_str())
+            } else {
+                form == wanted
+            };
+            name_known |= matches;
+            matches && slots.len() == operands.len()
+        });
+
+        let Some(&(form, base, _, slots)) = form else {
+            if name_known {
+                return Err(anyhow::anyhow!(
+                    "Wrong operand count for {}: got {}",
+                    mnemonic,
+                    operands.len()
+                ));
+            }
+            return Err(anyhow::anyhow!("Unknown mnemonic: {}", mnemonic));
+        };
+
+        let mut opcode = base;
+        for (slot, &value) in slots.iter().zip(operands) {
+            let (max, shift) = slot.max_and_shift();
+            if value > max {
+                return Err(anyhow::anyhow!(
+                    "Operand 0x{:X} out of range for {} (max 0x{:X})",
+                    value,
+                    form,
+                    max
+                ));
+            }
+            opcode |= value << shift;
+        }
+
+        Ok(opcode)
+    }
+
+    // Render one opcode as assembly, e.g. 0x6342 -> "LD V3, 0x42". Words that are not
+    // instructions come out as "DW 0xNNNN".
+    pub fn disassemble_opcode(opcode: u16) -> String {
+        let Some(&(form, _, _, slots)) = INSTRUCTION_FORMS
+            .iter()
+            .find(|&&(_, base, mask, _)| opcode & mask == base)
+        else {
+            return format!("DW 0x{:04X}", opcode);
+        };
+
+        let mut text = form.to_string();
+        for &slot in slots {
+            let (max, shift) = slot.max_and_shift();
+            let value = (opcode >> shift) & max;
+            let rendered = match slot {
+                Operand::Vx | Operand::Vy => format!("V{:X}", value),
+                Operand::Byte => format!("0x{:02X}", value),
+                Operand::Addr => format!("0x{:03X}", value),
+                Operand::Nibble | Operand::Planes => value.to_string(),
+            };
+            text = text.replacen(slot.placeholder(), &rendered, 1);
+        }
+        text
+    }
+
+    // Big-endian instruction word at `addr`, without executing it or counting the read
+    pub fn get_opcode_at(&self, addr: u16) -> Option<u16> {
+        if addr as usize + 1 >= MEMORY_SIZE {
+            return None;
+        }
+        let high = self.memory[addr as usize] as u16;
+        let low = self.memory[addr as usize + 1] as u16;
+        Some((high << 8) | low)
+    }
+
+    // True while the machine is parked on Fx0A: it'll keep re-running the same instruction
+    // until a key goes down
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    // The instruction executed by the last cycle(), as assembly
+    pub fn current_opcode_str(&self) -> String {
+        Self::disassemble_opcode(self.last_executed_opcode())
+    }
+
+    // Tell the disassembler that `len` bytes from `start` are data, not code
+    pub fn mark_data_region(&mut self, start: u16, len: u16) {
+        self.data_regions.push((start, len));
+    }
+
+    // Disassemble `count` lines starting at `start`. Code takes two bytes per line; the
+    // fontset and marked data regions are listed one byte per line as DB.
+    pub fn disassemble(&self, start: u16, count: usize) -> Vec<(u16, String)> {
+        let fontset = FONTSET_START_ADDRESS..FONTSET_START_ADDRESS + FONTSET_SIZE as u16;
+        let mut lines = Vec::with_capacity(count);
+        let mut addr = start;
+
+        while lines.len() < count {
+            let is_data = self.data_regions.iter()
+                .any(|&(region, len)| (region..region.saturating_add(len)).contains(&addr));
+            let opcode = self.get_opcode_at(addr);
+
+            let (text, size) = if fontset.contains(&addr) {
+                let offset = (addr - FONTSET_START_ADDRESS) as usize;
+                let text = format!(
+                    "DB 0x{:02X} ; FONT: '{:X}' row {}",
+                    self.memory[addr as usize],
+                    offset / FONT_GLYPH_HEIGHT,
+                    offset % FONT_GLYPH_HEIGHT
+                );
+                (text, 1)
+            } else if let (false, Some(opcode)) = (is_data, opcode) {
+                (Self::disassemble_opcode(opcode), 2)
+            } else {
+                (format!("DB 0x{:02X}", self.memory[addr as usize]), 1)
+            };
+            lines.push((addr, text));
+
+            // Stop at the end of memory
+            let Some(next) = addr.checked_add(size) else { break };
+            addr = next;
+        }
+        lines
+    }
+
+    // Fetch -> Decode -> Execute. An error means the ROM did something impossible; the machine
+    // has already carried on as ExecutionError describes, so the host can decide whether to
+    // stop. The error is also kept in last_error.
+    pub fn cycle(&mut self) -> Result<(), ExecutionError> {
+        self.cycle_error = None;
+        // Stop before the instruction at a breakpoint runs; it runs once the host takes the hit
+        if self.hit_breakpoint {
+            return Ok(());
+        }
+        let resuming = self.resume_at.take() == Some(self.pc);
+        if !resuming && self.breakpoints.contains(&self.pc) {
+            self.hit_breakpoint = true;
+            self.debug_print(&format!("Breakpoint at 0x{:03X}", self.pc));
+            return Ok(());
+        }
+
+        if self.execute_instruction() {
+            self.count_frame_cycle();
+        }
+        match self.cycle_error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    // `n` cycles back to back, e.g. to run a ROM without a window. Like the windowed loop it
+    // runs through errors, which are left in last_error.
+    pub fn run_cycles(&mut self, n: usize) {
+        for _ in 0..n {
+            let _ = self.cycle();
+        }
+    }
+
+    // Up to `max_cycles` cycles, stopping at the first error; for headless runs that should
+    // fail loudly. Returns the cycles run, which is fewer if the ROM exits with 00FD.
+    pub fn run_until_error(&mut self, max_cycles: u64) -> Result<u64, ExecutionError> {
+        for cycles in 0..max_cycles {
+            if self.halted {
+                return Ok(cycles);
+            }
+            self.cycle()?;
+        }
+        Ok(max_cycles)
+    }
+
+    // Run `input.cycles_to_run` instructions without touching the wall clock or the file
+    // system, and report what an outside observer would see. Timers only move when
+    // `input.timer_tick` is set, so the caller owns the 60Hz schedule.
+    pub fn process(&mut self, input: Input) -> Vec<Output> {
+        let mut outputs = Vec::new();
+        let video_before = (self.video.clone(), self.video_plane2.clone());
+        let sound_before = self.is_beeping();
+        self.set_keys(&input.keys);
+
+        let mut halted = None;
+        for _ in 0..input.cycles_to_run {
+            if let Err(reason) = self.execute_checked() {
+                halted = Some(reason);
+                break;
+            }
+            // The keys can't change until the next call, so the rest would just spin on Fx0A
+            if self.waiting_for_key {
+                break;
+            }
+        }
+
+        if input.timer_tick {
+            self.tick_timers();
+        }
+
+        if (&self.video, &self.video_plane2) != (&video_before.0, &video_before.1) {
+            let mut display = vec![0; self.video.len()];
+            self.copy_display_to_buffer(&mut display);
+            outputs.push(Output::DisplayUpdated(display));
+        }
+        let sound = self.is_beeping();
+        if sound != sound_before {
+            outputs.push(Output::SoundToggle(sound));
+        }
+        outputs.push(match halted {
+            Some(reason) => Output::Halted(reason),
+            None => Output::CycleComplete,
+        });
+        outputs
+    }
+
+    // CPU speed, as instructions per 60Hz frame
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.cycles_per_frame = cycles;
+    }
+
+    pub fn get_cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
+
+    // How often the front end refreshes the display, and so calls cycles_needed_this_frame
+    pub fn set_target_fps(&mut self, fps: f64) {
+        assert!(fps > 0.0 && fps.is_finite(), "invalid target fps: {}", fps);
+        self.target_fps = fps;
+    }
+
+    // Number of cycle() calls due before the next display refresh. Call once per frame;
+    // fractions carry over, so the average matches cycles_per_frame at 60Hz exactly.
+    pub fn cycles_needed_this_frame(&mut self) -> u32 {
+        self.cycle_budget += self.cycles_per_frame as f64 * 60.0 / self.target_fps;
+        let cycles = self.cycle_budget.floor();
+        self.cycle_budget -= cycles;
+        cycles as u32
+    }
+
+    // Run up to `n` cycles, stopping early if the machine halts
+    pub fn step_n(&mut self, n: u64) -> CycleStats {
+        let mut stats = CycleStats::default();
+        for _ in 0..n {
+            self.display_dirty = false;
+            let cycles_before = self.cycle_count;
+            let result = self.execute_checked();
+            // Like cycle(), count instructions towards the next timer tick
+            if self.cycle_count != cycles_before {
+                self.count_frame_cycle();
+                stats.instructions_run += 1;
+            }
+            if self.display_dirty {
+                stats.display_updates += 1;
+            }
+            if let Err(reason) = result {
+                stats.halted = true;
+                stats.halt_reason = Some(reason);
+                break;
+            }
+        }
+        stats
+    }
+
+    // Execute one instruction, reporting PC running off the end of memory, a jump to itself
+    // or 00FD
+    fn execute_checked(&mut self) -> Result<(), HaltReason> {
+        let pc = self.pc;
+        if !self.execute_instruction() {
+            return Err(if self.halted {
+                HaltReason::Exited(pc.wrapping_sub(2)) // PC stays just past the 00FD
+            } else {
+                HaltReason::PcOutOfBounds(pc)
+            });
+        }
+        if self.halted {
+            return Err(HaltReason::Exited(pc));
+        }
+        // Compare where the JP went, not the opcode: above 0x0FFF other opcodes can look like
+        // 0x1000 | pc
+        if self.opcode & 0xF000 == 0x1000 && self.pc == pc {
+            return Err(HaltReason::InfiniteLoop(pc));
+        }
+        Ok(())
+    }
+
+    // cycle() and step_n keep their own 60Hz clock: every cycles_per_frame instructions a
+    // frame has passed, so the timers tick and display_wait lets the next Dxyn through
+    fn count_frame_cycle(&mut self) {
+        self.frame_cycles += 1;
+        if self.frame_cycles >= self.cycles_per_frame {
+            self.frame_cycles = 0;
+            self.tick_timers();
+        }
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.vblank = true;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    // Fetch, decode and execute one instruction. Returns false if nothing ran because PC is
+    // out of bounds or the program has exited.
+    fn execute_instruction(&mut self) -> bool {
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+
+        if self.halted {
+            return false;
+        }
+
+        // Check if PC is in valid range
+        let Some(opcode) = self.get_opcode_at(self.pc) else {
+            self.record_error(ExecutionError::PcOutOfBounds { pc: self.pc });
+            self.debug_print(&format!("PC out of bounds: 0x{:03X}", self.pc));
+            return false;
+        };
+        self.opcode = opcode;
+        self.memory_read_count += 2;
+        self.cycle_count += 1;
+        self.opcode_stats.count(opcode);
+
+        self.debug_print(&format!("PC: 0x{:03X}, Opcode: 0x{:04X}", self.pc, self.opcode));
+
+        if let Some(threshold) = self.hotspot_threshold {
+            let count = self.hotspot_counts.entry(self.pc).or_insert(0);
+            *count += 1;
+            if *count >= threshold {
+                self.hotspots.insert(self.pc);
+            }
+        }
+
+        let (pc, registers_before, index_before) = (self.pc, self.registers, self.index);
+        if self.rewind_depth > 0 {
+            self.rewind_frame = Some(self.rewind_snapshot());
+        }
+        self.next_pc = self.pc.wrapping_add(2);
+        self.dispatch();
+        self.pc = self.next_pc;
+        if let Some(frame) = self.rewind_frame.take() {
+            if self.rewind.len() == self.rewind_depth {
+                self.rewind.pop_front();
+            }
+            self.rewind.push_back(frame);
+        }
+        if self.trace.is_some() {
+            self.write_trace_line(pc, &registers_before, index_before);
+        }
+
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+        true
+    }
+
+    fn write_trace_line(&mut self, pc: u16, registers_before: &[u8; REGISTER_COUNT], index_before: u16) {
+        let mut changes: Vec<String> = (0..REGISTER_COUNT)
+            .filter(|&x| self.registers[x] != registers_before[x])
+            .map(|x| format!("V{:X}=0x{:02X}", x, self.registers[x]))
+            .collect();
+        if self.index != index_before {
+            changes.push(format!("I=0x{:03X}", self.index));
+        }
+
+        let mut line = format!("0x{:04X}: {:04X} {}", pc, self.opcode, Self::disassemble_opcode(self.opcode));
+        if !changes.is_empty() {
+            line += &format!("  [{}]", changes.join(" "));
+        }
+
+        let Some(trace) = self.trace.as_mut() else { return };
+        let mut result = writeln!(trace, "{}", line);
+        if result.is_ok() && self.cycle_count.is_multiple_of(TRACE_FLUSH_INTERVAL) {
+            result = trace.flush();
+        }
+        // A failing trace file shouldn't stop the ROM
+        if let Err(e) = result {
+            eprintln!("Trace stopped: {}", e);
+            self.trace = None;
+        }
+    }
+
+    // Run `opcode` against the current state without fetching it or ticking timers. PC is
+    // taken to already point past the instruction, as it would after a fetch.
+    pub fn execute_opcode(&mut self, opcode: u16) {
+        self.opcode = opcode;
+        self.next_pc = self.pc;
+        self.pc = self.pc.wrapping_sub(2);
+        self.dispatch();
+        self.pc = self.next_pc;
+    }
+
+    // Skip the next instruction, all four bytes of it if it's XO-CHIP's F000 NNNN
+    fn skip_next_instruction(&mut self) {
+        let long = self.get_opcode_at(self.next_pc) == Some(0xF000);
+        self.next_pc = self.next_pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    // Decode and execute self.opcode
+    fn dispatch(&mut self) {
+        self.waiting_for_key = false;
+
+        match (self.opcode & 0xF000) >> 12 {
+            0x0 => self.execute_0xxx(),
+            0x1 => self.op_1nnn(), // JP addr
+            0x2 => self.op_2nnn(), // CALL addr
+            0x3 => self.op_3xkk(), // SE Vx, byte
+            0x4 => self.op_4xkk(), // SNE Vx, byte
+            0x5 => self.execute_5xxx(),
+            0x6 => self.op_6xkk(), // LD Vx, byte
+            0x7 => self.op_7xkk(), // ADD Vx, byte
+            0x8 => self.execute_8xxx(),
+            0x9 => self.op_9xy0(), // SNE Vx, Vy
+            0xA => self.op_annn(), // LD I, addr
+            0xB => self.op_bnnn(), // JP V0, addr
+            0xC => self.op_cxkk(), // RND Vx, byte
+            0xD => self.op_dxyn(), // DRW Vx, Vy, nibble
+            0xE => self.execute_exxx(),
+            0xF => self.execute_fxxx(),
+            _ => self.unknown_opcode(),
+        }
+    }
+
+    // Internal consistency checks, run around every cycle() in debug builds.
+    // Keypad entries are plain bools, so the type already rules out stray values there.
+    pub fn assert_invariants(&self) {
+        assert!(
+            self.sp as usize <= STACK_SIZE,
+            "stack pointer {} exceeds stack size {} (pc 0x{:03X})",
+            self.sp,
+            STACK_SIZE,
+            self.pc
+        );
+        assert_eq!(
+            self.video.len(),
+            self.video_width() * self.video_height(),
+            "video buffer doesn't match the {} resolution",
+            if self.hires { "high" } else { "low" }
+        );
+        if let Some(i) = self.video.iter().position(|&p| p != 0 && p != 0xFFFFFFFF) {
+            panic!(
+                "video pixel ({}, {}) has stray value 0x{:08X}",
+                i % self.video_width(),
+                i / self.video_width(),
+                self.video[i]
+            );
+        }
+    }
+
+    fn execute_0xxx(&mut self) {
+        match self.opcode & 0x00FF {
+            0xE0 => self.op_00e0(), // CLS
+            0xEE => self.op_00ee(), // RET
+            0xC0..=0xCF => self.op_00cn(), // SCD nibble (SUPER-CHIP)
+            0xFB => self.op_00fb(), // SCR (SUPER-CHIP)
+            0xFC => self.op_00fc(), // SCL (SUPER-CHIP)
+            0xFD => self.op_00fd(), // EXIT (SUPER-CHIP)
+            0xFE => self.op_00fe(), // LOW (SUPER-CHIP)
+            0xFF => self.op_00ff(), // HIGH (SUPER-CHIP)
+            _ => self.unknown_opcode(),
+        }
+    }
+
+    fn execute_5xxx(&mut self) {
+        match self.opcode & 0x000F {
+            0x0 => self.op_5xy0(), // SE Vx, Vy
+            0x1 if self.quirks.chip8e_mode => self.op_5xy1_8e(), // OR Vx, Vy (CHIP-8E)
+            0x2 if self.quirks.chip8e_mode => self.op_5xy2_8e(), // AND Vx, Vy (CHIP-8E)
+            0x3 if self.quirks.chip8e_mode => self.op_5xy3_8e(), // XOR Vx, Vy (CHIP-8E)
+            0x2 => self.op_5xy2(), // LD [I], Vx-Vy (XO-CHIP)
+            0x3 => self.op_5xy3(), // LD Vx-Vy, [I] (XO-CHIP)
+            _ => self.unknown_opcode(),
+        }
+    }
+
+    fn execute_8xxx(&mut self) {
+        match self.opcode & 0x000F { // Fixed: should check last nibble, not last byte
+            0x0 => self.op_8xy0(), // LD Vx, Vy
+            0x1 => self.op_8xy1(), // OR Vx, Vy
+            0x2 => self.op_8xy2(), // AND Vx, Vy
+            0x3 => self.op_8xy3(), // XOR Vx, Vy
+            0x4 => self.op_8xy4(), // ADD Vx, Vy
+            0x5 => self.op_8xy5(), // SUB Vx, Vy
+            0x6 => self.op_8xy6(), // SHR Vx
+            0x7 => self.op_8xy7(), // SUBN Vx, Vy
+            0xE => self.op_8xye(), // SHL Vx
+            _ => self.unknown_opcode(),
+        }
+    }
+
+    fn execute_exxx(&mut self) {
+        match self.opcode & 0x00FF {
+            0x9E => self.op_ex9e(), // SKP Vx
+            0xA1 => self.op_exa1(), // SKNP Vx
+            _ => self.unknown_opcode(),
+        }
+    }
+
+    fn execute_fxxx(&mut self) {
+        match self.opcode & 0x00FF {
+            0x00 if self.opcode == 0xF000 => self.op_f000(), // LD I, long
+            0x01 if self.opcode & 0x0C00 == 0 => self.op_fn01(), // PLANE mask
+            0x07 => self.op_fx07(), // LD Vx, DT
+            0x0A => self.op_fx0a(), // LD Vx, K
+            0x15 => self.op_fx15(), // LD DT, Vx
+            0x18 => self.op_fx18(), // LD ST, Vx
+            0x1E => self.op_fx1e(), // ADD I, Vx
+            0x29 => self.op_fx29(), // LD F, Vx
+            0x33 => self.op_fx33(), // LD B, Vx
+            0x55 => self.op_fx55(), // LD [I], Vx
+            0x65 => self.op_fx65(), // LD Vx, [I]
+            _ => self.unknown_opcode(),
+        }
+    }
+
+    // ===== INSTRUCTIONS =====
+
+    // 00E0: CLS Clear the display. Only the selected XO-CHIP planes are cleared.
+    fn op_00e0(&mut self) {
+        for plane in self.selected_planes() {
+            let video = self.plane_mut(plane);
+            let was_lit = video.iter().any(|&pixel| pixel != 0);
+            video.fill(0);
+            self.display_dirty |= was_lit;
+        }
+        self.debug_print("Cleared display");
+    }
+
+    // Scroll amounts are in pixels of the current resolution, as on the HP48 SUPER-CHIP 1.1:
+    // 00CN moves N rows in both modes, so a low-res scroll covers twice the screen distance.
+
+    // 00CN: SCD nibble Scroll the display down N rows (SUPER-CHIP).
+    fn op_00cn(&mut self) {
+        let rows = (self.opcode & 0x000F) as usize;
+        self.scroll_display(0, rows);
+        self.debug_print(&format!("SCD {}", rows));
+    }
+
+    // 00FB: SCR Scroll the display right 4 pixels (SUPER-CHIP).
+    fn op_00fb(&mut self) {
+        self.scroll_display(4, 0);
+        self.debug_print("SCR");
+    }
+
+    // 00FC: SCL Scroll the display left 4 pixels (SUPER-CHIP).
+    fn op_00fc(&mut self) {
+        self.scroll_display(-4, 0);
+        self.debug_print("SCL");
+    }
+
+    // Move the selected planes right by `dx` (left if negative) and down by `dy`, filling the
+    // uncovered edge with off pixels
+    fn scroll_display(&mut self, dx: isize, dy: usize) {
+        let (width, height) = (self.video_width(), self.video_height());
+        for plane in self.selected_planes() {
+            let video = self.plane_mut(plane);
+            let old = std::mem::replace(video, vec![0; width * height]);
+
+            for y in dy.min(height)..height {
+                for x in 0..width {
+                    let src_x = x as isize - dx;
+                    if (0..width as isize).contains(&src_x) {
+                        video[y * width + x] = old[(y - dy) * width + src_x as usize];
+                    }
+                }
+            }
+            let changed = *video != old;
+            self.display_dirty |= changed;
+        }
+    }
+
+    // 00FD: EXIT Stop the interpreter (SUPER-CHIP).
+    fn op_00fd(&mut self) {
+        self.halted = true;
+        self.debug_print("EXIT");
+    }
+
+    // 00FE: LOW Switch to the 64x32 display and clear it (SUPER-CHIP).
+    fn op_00fe(&mut self) {
+        self.set_hires(false);
+        self.debug_print("Low-res display");
+    }
+
+    // 00FF: HIGH Switch to the 128x64 display and clear it (SUPER-CHIP).
+    fn op_00ff(&mut self) {
+        self.set_hires(true);
+        self.debug_print("High-res display");
+    }
+
+    // 00EE: RET Return from a subroutine.
+    fn op_00ee(&mut self) {
+        if self.sp == 0 {
+            self.record_error(ExecutionError::StackUnderflow { pc: self.pc });
+            self.debug_print("RET with empty stack, ignored");
+            return;
+        }
+        self.sp -= 1;
+        self.next_pc = self.stack[self.sp as usize];
+        self.debug_print(&format!("RET to 0x{:03X}", self.next_pc));
+    }
+
+    // 1nnn: JP addr Jump to location nnn.
+    fn op_1nnn(&mut self) {
+        let address = self.opcode & 0x0FFF;
+        self.debug_print(&format!("JP to 0x{:03X}", address));
+        self.next_pc = address;
+    }
+
+    // 2nnn: CALL addr Call subroutine at nnn.
+    fn op_2nnn(&mut self) {
+        let address = self.opcode & 0x0FFF;
+        if self.sp as usize >= STACK_SIZE {
+            self.record_error(ExecutionError::StackOverflow { pc: self.pc });
+            self.debug_print(&format!("CALL 0x{:03X} with full stack, ignored", address));
+            return;
+        }
+        self.stack[self.sp as usize] = self.next_pc;
+        self.sp += 1;
+        self.next_pc = address;
+        self.debug_print(&format!("CALL 0x{:03X}", address));
+    }
+
+    // 3xkk - SE Vx, byte Skip next instruction if Vx = kk.
+    fn op_3xkk(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let byte = (self.opcode & 0x00FF) as u8;
+
+        if self.registers[vx] == byte {
+            self.skip_next_instruction();
+            self.debug_print(&format!("SE V{:X}, 0x{:02X} - SKIP", vx, byte));
+        } else {
+            self.debug_print(&format!("SE V{:X}, 0x{:02X} - NO SKIP", vx, byte));
+        }
+    }
+
+    // 4xkk - SNE Vx, byte Skip next instruction if Vx != kk.
+    fn op_4xkk(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let byte = (self.opcode & 0x00FF) as u8;
+
+        if self.registers[vx] != byte {
+            self.skip_next_instruction();
+            self.debug_print(&format!("SNE V{:X}, 0x{:02X} - SKIP", vx, byte));
+        } else {
+            self.debug_print(&format!("SNE V{:X}, 0x{:02X} - NO SKIP", vx, byte));
+        }
+    }
+
+    // 5xy0 - SE Vx, Vy Skip next instruction if Vx = Vy.
+    fn op_5xy0(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        if self.registers[vx] == self.registers[vy] {
+            self.skip_next_instruction();
+        }
+        self.debug_print(&format!("SE V{:X}, V{:X}", vx, vy));
+    }
+
+    // 5xy1 - OR Vx, Vy (CHIP-8E), Set Vx = Vx OR Vy, VF untouched.
+    fn op_5xy1_8e(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] |= self.registers[vy];
+        self.debug_print(&format!("OR V{:X}, V{:X} (8E)", vx, vy));
+    }
+
+    // 5xy2 - AND Vx, Vy (CHIP-8E), Set Vx = Vx AND Vy, VF untouched.
+    fn op_5xy2_8e(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] &= self.registers[vy];
+        self.debug_print(&format!("AND V{:X}, V{:X} (8E)", vx, vy));
+    }
+
+    // 5xy3 - XOR Vx, Vy (CHIP-8E), Set Vx = Vx XOR Vy, VF untouched.
+    fn op_5xy3_8e(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] ^= self.registers[vy];
+        self.debug_print(&format!("XOR V{:X}, V{:X} (8E)", vx, vy));
+    }
+
+    // Registers Vx through Vy in the order they go to memory. Ascending when x <= y; when
+    // x > y XO-CHIP walks down, so Vx still lands at I.
+    fn xo_register_range(&self) -> Vec<usize> {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+        if vx <= vy { (vx..=vy).collect() } else { (vy..=vx).rev().collect() }
+    }
+
+    // 5xy2 - LD [I], Vx-Vy (XO-CHIP), Store Vx through Vy starting at I. I is not changed.
+    fn op_5xy2(&mut self) {
+        let registers = self.xo_register_range();
+        for (offset, &reg) in registers.iter().enumerate() {
+            let addr = self.memory_addr(self.index, offset);
+            self.store(addr, self.registers[reg]);
+        }
+        self.memory_written(self.index as usize, registers.len());
+        self.debug_print(&format!("LD [I], V{:X}-V{:X}", registers[0], registers[registers.len() - 1]));
+    }
+
+    // 5xy3 - LD Vx-Vy, [I] (XO-CHIP), Load Vx through Vy starting at I. I is not changed.
+    fn op_5xy3(&mut self) {
+        let registers = self.xo_register_range();
+        for (offset, &reg) in registers.iter().enumerate() {
+            let addr = self.memory_addr(self.index, offset);
+            self.registers[reg] = self.memory[addr];
+        }
+        self.memory_read_count += registers.len() as u64;
+        self.debug_print(&format!("LD V{:X}-V{:X}, [I]", registers[0], registers[registers.len() - 1]));
+    }
+
+    // 6xkk - LD Vx, byte, Set Vx = kk.
+    fn op_6xkk(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let byte = (self.opcode & 0x00FF) as u8;
+
+        self.registers[vx] = byte;
+        self.debug_print(&format!("LD V{:X}, 0x{:02X}", vx, byte));
+    }
+
+    // 7xkk - ADD Vx, byte, Set Vx = Vx + kk.
+    fn op_7xkk(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let byte = (self.opcode & 0x00FF) as u8;
+
+        self.registers[vx] = self.registers[vx].wrapping_add(byte);
+        self.debug_print(&format!("ADD V{:X}, 0x{:02X}", vx, byte));
+    }
+
+    // 8xy0 - LD Vx, Vy, Set Vx = Vy.
+    fn op_8xy0(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] = self.registers[vy];
+        self.debug_print(&format!("LD V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xy1 - OR Vx, Vy, Set Vx = Vx OR Vy.
+    fn op_8xy1(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] |= self.registers[vy];
+        if self.quirks.vf_reset_on_logic {
+            self.registers[0xF] = 0;
+        }
+        self.debug_print(&format!("OR V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xy2 - AND Vx, Vy, Set Vx = Vx AND Vy.
+    fn op_8xy2(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] &= self.registers[vy];
+        if self.quirks.vf_reset_on_logic {
+            self.registers[0xF] = 0;
+        }
+        self.debug_print(&format!("AND V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xy3 - XOR Vx, Vy, Set Vx = Vx XOR Vy.
+    fn op_8xy3(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        self.registers[vx] ^= self.registers[vy];
+        if self.quirks.vf_reset_on_logic {
+            self.registers[0xF] = 0;
+        }
+        self.debug_print(&format!("XOR V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xy4 - ADD Vx, Vy, Set Vx = Vx + Vy, set VF = carry.
+    fn op_8xy4(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        let sum = self.registers[vx] as u16 + self.registers[vy] as u16;
+
+        // VF is written last (as on the VIP) so the flag wins when Vx is VF
+        self.registers[vx] = (sum & 0xFF) as u8;
+        self.registers[0xF] = if sum > 255 { 1 } else { 0 };
+        self.debug_print(&format!("ADD V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xy5 - SUB Vx, Vy, Set Vx = Vx - Vy, set VF = NOT borrow.
+    fn op_8xy5(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        let not_borrow = if self.registers[vx] > self.registers[vy] {
+            1
+        } else {
+            0
+        };
+
+        self.registers[vx] = self.registers[vx].wrapping_sub(self.registers[vy]);
+        self.registers[0xF] = not_borrow;
+        self.debug_print(&format!("SUB V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xy6 - SHR Vx, Set Vx = Vx SHR 1.
+    fn op_8xy6(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        if self.quirks.shift_uses_vy {
+            self.registers[vx] = self.registers[vy];
+        }
+        self.registers[0xF] = self.registers[vx] & 0x1;
+        self.registers[vx] >>= 1;
+        self.debug_print(&format!("SHR V{:X}", vx));
+    }
+
+    // 8xy7 - SUBN Vx, Vy, Set Vx = Vy - Vx, set VF = NOT borrow.
+    fn op_8xy7(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        let not_borrow = if self.registers[vy] > self.registers[vx] {
+            1
+        } else {
+            0
+        };
+
+        self.registers[vx] = self.registers[vy].wrapping_sub(self.registers[vx]);
+        self.registers[0xF] = not_borrow;
+        self.debug_print(&format!("SUBN V{:X}, V{:X}", vx, vy));
+    }
+
+    // 8xyE - SHL Vx {, Vy}, Set Vx = Vx SHL 1.
+    fn op_8xye(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        if self.quirks.shift_uses_vy {
+            self.registers[vx] = self.registers[vy];
+        }
+        self.registers[0xF] = (self.registers[vx] & 0x80) >> 7;
+        self.registers[vx] <<= 1;
+        self.debug_print(&format!("SHL V{:X}", vx));
+    }
+
+    // 9xy0 - SNE Vx, Vy, Skip next instruction if Vx != Vy.
+    fn op_9xy0(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        if self.registers[vx] != self.registers[vy] {
+            self.skip_next_instruction();
+        }
+        self.debug_print(&format!("SNE V{:X}, V{:X}", vx, vy));
+    }
+
+    // Annn - LD I, addr, Set I = nnn.
+    fn op_annn(&mut self) {
+        let address = self.opcode & 0x0FFF;
+        self.index = address;
+        self.debug_print(&format!("LD I, 0x{:03X}", address));
+    }
+
+    // Bnnn - JP V0, addr, Jump to location nnn + V0.
+    fn op_bnnn(&mut self) {
+        let address = self.opcode & 0x0FFF;
+        let offset = if self.quirks.jump_uses_vx { (address >> 8) as usize } else { 0 };
+        self.next_pc = address + self.registers[offset] as u16;
+        self.debug_print(&format!("JP V{:X}, 0x{:03X}", offset, address));
+    }
+
+    // Cxkk - RND Vx, byte, Set Vx = random byte AND kk.
+    fn op_cxkk(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let byte = (self.opcode & 0x00FF) as u8;
+
+        self.registers[vx] = self.random_byte() & byte;
+        self.debug_print(&format!("RND V{:X}, 0x{:02X}", vx, byte));
+    }
+
+    // Dxyn - DRW Vx, Vy, nibble
+    // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
+    // In high-res, Dxy0 draws a 16x16 sprite stored as 32 bytes, two per row (SUPER-CHIP).
+    fn op_dxyn(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let vy = ((self.opcode & 0x00F0) >> 4) as usize;
+        let height = (self.opcode & 0x000F) as usize;
+
+        let x_pos = self.registers[vx] as usize % self.video_width();
+        let y_pos = self.registers[vy] as usize % self.video_height();
+
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.next_pc = self.pc; // Run it again once the timers have ticked
+                self.debug_print(&format!("DRW V{:X}, V{:X}, {} (waiting for vblank)", vx, vy, height));
+                return;
+            }
+            self.vblank = false;
+        }
+
+        self.debug_print(&format!("DRW V{:X}, V{:X}, {} at ({}, {})", vx, vy, height, x_pos, y_pos));
+
+        self.registers[0xF] = 0; // Clear collision flag
+
+        // With both XO-CHIP planes selected, the second plane's sprite follows the first's
+        let sprite_bytes = if height == 0 && self.hires { 32 } else { height as u16 };
+        for (i, plane) in self.selected_planes().enumerate() {
+            let addr = self.index.wrapping_add(i as u16 * sprite_bytes);
+            self.draw_sprite(plane, addr, x_pos, y_pos, height);
+        }
+
+        // Count pixels that are on for debugging
+        let pixels_on = self.video.iter().filter(|&&p| p == 0xFFFFFFFF).count();
+        self.debug_print(&format!("  Pixels on after draw: {}", pixels_on));
+    }
+
+    // One plane's part of Dxyn: the sprite at `addr`, `height` rows tall (16x16 for 0 in hires)
+    fn draw_sprite(&mut self, plane: usize, addr: u16, x_pos: usize, y_pos: usize, height: usize) {
+        if height == 0 && self.hires {
+            self.memory_read_count += 32;
+            for row in 0..16 {
+                let (high, low) = (self.memory_addr(addr, 2 * row), self.memory_addr(addr, 2 * row + 1));
+                let sprite_row = (self.memory[high] as u16) << 8 | self.memory[low] as u16;
+                for col in 0..16 {
+                    if sprite_row & (0x8000 >> col) != 0 {
+                        self.xor_sprite_pixel(plane, x_pos + col, y_pos + row);
+                    }
+                }
+            }
+            return;
+        }
+
+        self.memory_read_count += height as u64; // Sprite rows, even when served from the atlas
+
+        let glyph = if addr == self.index { self.font_glyph_at_index(height) } else { None };
+        if let Some(glyph) = glyph {
+            self.debug_print(&format!("  Font glyph {:X} from atlas", glyph));
+            for row in 0..height {
+                for col in 0..8 {
+                    if self.font_atlas[glyph][row * 8 + col] != 0 {
+                        self.xor_sprite_pixel(plane, x_pos + col, y_pos + row);
+                    }
+                }
+            }
+        } else {
+            for row in 0..height {
+                let sprite_addr = self.memory_addr(addr, row);
+                let sprite_byte = self.memory[sprite_addr];
+                self.debug_print(&format!("  Row {}: 0b{:08b} (0x{:02X})", row, sprite_byte, sprite_byte));
+
+                for col in 0..8 {
+                    let sprite_pixel = sprite_byte & (0x80 >> col);
+
+                    if sprite_pixel == 0 { continue; }
+                    self.xor_sprite_pixel(plane, x_pos + col, y_pos + row);
+                }
+            }
+        }
+    }
+
+    // Bitplane 0 is `video`, 1 is XO-CHIP's second plane
+    fn plane_mut(&mut self, plane: usize) -> &mut Vec<u32> {
+        if plane == 0 { &mut self.video } else { &mut self.video_plane2 }
+    }
+
+    // The planes FN01 selected, in drawing order
+    fn selected_planes(&self) -> impl Iterator<Item = usize> + use<> {
+        let mask = self.plane_mask;
+        (0..2).filter(move |plane| mask & (1 << plane) != 0)
+    }
+
+    // XOR one sprite pixel onto the screen, clipping or wrapping at the edges and setting VF
+    // on collision
+    fn xor_sprite_pixel(&mut self, plane: usize, x: usize, y: usize) {
+        if self.quirks.clip_sprites && (x >= self.video_width() || y >= self.video_height()) {
+            return;
+        }
+        let (x, y) = (x % self.video_width(), y % self.video_height());
+
+        let screen_pixel_index = y * self.video_width() + x;
+        // Fields rather than plane_mut, so VF can be written while the pixel is borrowed
+        let video = if plane == 0 { &mut self.video } else { &mut self.video_plane2 };
+        let pixel = &mut video[screen_pixel_index];
+        if *pixel == 0xFFFFFFFF {
+            self.registers[0xF] = 1;
+        }
+        *pixel ^= 0xFFFFFFFF;
+        self.display_dirty = true;
+    }
+
+    // Ex9E - SKP Vx, Skip next instruction if key with the value of Vx is pressed.
+    fn op_ex9e(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let key = self.registers[vx] as usize;
+
+        if key < KEY_COUNT && self.keypad[key] {
+            self.skip_next_instruction();
+        }
+        self.debug_print(&format!("SKP V{:X}", vx));
+    }
+
+    // ExA1 - SKNP Vx, Skip next instruction if key with the value of Vx is not pressed.
+    fn op_exa1(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let key = self.registers[vx] as usize;
+
+        if key >= KEY_COUNT || !self.keypad[key] {
+            self.skip_next_instruction();
+        }
+        self.debug_print(&format!("SKNP V{:X}", vx));
+    }
+
+    // F000 NNNN - LD I, long, Set I = NNNN, the 16-bit word after the opcode (XO-CHIP).
+    fn op_f000(&mut self) {
+        let high = self.memory[self.next_pc as usize] as u16;
+        let low = self.memory[(self.next_pc as usize + 1) % MEMORY_SIZE] as u16;
+        self.memory_read_count += 2;
+        self.index = high << 8 | low;
+        self.next_pc = self.next_pc.wrapping_add(2);
+        self.debug_print(&format!("LD I, long 0x{:04X}", self.index));
+    }
+
+    // FN01 - PLANE mask, Select the XO-CHIP planes that Dxyn, 00E0 and the scrolls act on.
+    fn op_fn01(&mut self) {
+        self.plane_mask = ((self.opcode & 0x0300) >> 8) as u8;
+        self.debug_print(&format!("PLANE {}", self.plane_mask));
+    }
+
+    // Fx07 - LD Vx, DT, Set Vx = delay timer value.
+    fn op_fx07(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        self.registers[vx] = self.delay_timer;
+        self.debug_print(&format!("LD V{:X}, DT", vx));
+    }
+
+    // Fx0A - LD Vx, K, Wait for a key press, store the value of the key in Vx.
+    // Like the VIP, the key has to be released again before this completes, so a held key
+    // isn't read twice.
+    fn op_fx0a(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+
+        match self.waiting_key {
+            Some(key) if !self.keypad[key as usize] => {
+                self.registers[vx] = key;
+                self.waiting_key = None;
+                self.debug_print(&format!("LD V{:X}, K (key {})", vx, key));
+                return;
+            }
+            Some(_) => {}
+            None => self.waiting_key = self.keypad.iter().position(|&down| down).map(|key| key as u8),
+        }
+
+        self.next_pc = self.pc; // Stay on this instruction
+        self.waiting_for_key = true;
+        self.debug_print(&format!("LD V{:X}, K (waiting)", vx));
+    }
+
+    // Fx15 - LD DT, Vx, Set delay timer = Vx.
+    fn op_fx15(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        self.delay_timer = self.registers[vx];
+        self.debug_print(&format!("LD DT, V{:X}", vx));
+    }
+
+    // Fx18 - LD ST, Vx, Set sound timer = Vx.
+    fn op_fx18(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        self.sound_timer = self.registers[vx];
+        self.debug_print(&format!("LD ST, V{:X}", vx));
+    }
+
+    // Fx1E - ADD I, Vx, Set I = I + Vx. I is 16 bits wide, as on XO-CHIP.
+    fn op_fx1e(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let sum = self.index.wrapping_add(self.registers[vx] as u16);
+        if self.quirks.i_overflow_sets_vf {
+            self.registers[0xF] = (sum > 0x0FFF) as u8;
+        }
+        self.index = sum;
+        self.debug_print(&format!("ADD I, V{:X}", vx));
+    }
+
+    // Fx29 - LD F, Vx, Set I = location of sprite for digit Vx.
+    fn op_fx29(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let digit = self.registers[vx] as u16;
+
+        self.index = FONTSET_START_ADDRESS + (5 * digit);
+        self.debug_print(&format!("LD F, V{:X} (digit {}, addr 0x{:03X})", vx, digit, self.index));
+    }
+
+    // Fx33 - LD B, Vx, Store BCD representation of Vx in memory locations I, I+1, and I+2.
+    fn op_fx33(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+        let mut value = self.registers[vx];
+
+        for offset in (0..3).rev() {
+            let addr = self.memory_addr(self.index, offset);
+            self.store(addr, value % 10);
+            value /= 10;
+        }
+        self.memory_written(self.index as usize, 3);
+        self.debug_print(&format!("LD B, V{:X}", vx));
+    }
+
+    // Fx55 - LD [I], Vx: Store registers V0 through Vx in memory starting at location I
+    fn op_fx55(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+
+        for i in 0..=vx {
+            let addr = self.memory_addr(self.index, i);
+            self.store(addr, self.registers[i]);
+        }
+        self.memory_written(self.index as usize, vx + 1);
+        if self.quirks.load_store_increments_i {
+            self.index = self.index.wrapping_add(vx as u16 + 1);
+        }
+        self.debug_print(&format!("LD [I], V{:X}", vx));
+    }
+
+    // Fx65 - LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
+    fn op_fx65(&mut self) {
+        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+
+        for i in 0..=vx {
+            let addr = self.memory_addr(self.index, i);
+            self.registers[i] = self.memory[addr];
+        }
+        self.memory_read_count += vx as u64 + 1;
+        if self.quirks.load_store_increments_i {
+            self.index = self.index.wrapping_add(vx as u16 + 1);
+        }
+        self.debug_print(&format!("LD V{:X}, [I]", vx));
+    }
+
+    // Getter methods for testing
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+    pub fn get_register(&self, index: usize) -> u8 {
+        self.registers[index]
+    }
+
+    // Display name for V`index`; VF is marked as the flag register. Panics past VF.
+    pub fn get_register_name(index: usize) -> &'static str {
+        const NAMES: [&str; REGISTER_COUNT] = [
+            "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7",
+            "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF (flag)",
+        ];
+        NAMES[index]
+    }
+    pub fn get_index(&self) -> u16 {
+        self.index
+    }
+    pub fn get_sp(&self) -> u8 {
+        self.sp
+    }
+    pub fn get_stack(&self, index: usize) -> u16 {
+        self.stack[index]
+    }
+    pub fn get_stack_trace(&self) -> Vec<StackFrame> {
+        self.stack[..self.sp as usize]
+            .iter()
+            .enumerate()
+            .map(|(depth, &return_addr)| StackFrame { return_addr, depth: depth as u8 })
+            .collect()
+    }
+    pub fn get_cycle_count(&self) -> u64 {
+        self.cycle_count
+    }
+    // The opcode the last cycle() fetched and ran, unlike get_opcode_at(pc) which is the next one
+    pub fn last_executed_opcode(&self) -> u16 {
+        self.opcode
+    }
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+    // True once the program has run 00FD
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+    // The most recent ExecutionError, if the ROM has hit one
+    pub fn last_error(&self) -> Option<ExecutionError> {
+        self.last_error
+    }
+    // The buzzer sounds for as long as the sound timer is non-zero
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // Delay timer in milliseconds, for a timer ticking once per frame at `target_fps`
+    pub fn get_delay_timer_ms(&self, target_fps: f64) -> f64 {
+        self.delay_timer as f64 * (1000.0 / target_fps)
+    }
+
+    // Set the delay timer to the nearest tick count for `ms`, clamped to 0-255
+    pub fn set_delay_timer_ms(&mut self, ms: f64, target_fps: f64) {
+        self.delay_timer = (ms * target_fps / 1000.0).round().clamp(0.0, 255.0) as u8;
+    }
+    pub fn load_test_program(&mut self, program: &[u8]) {
+        let start = START_ADDRESS as usize;
+        for (i, &byte) in program.iter().enumerate() {
+            if start + i < MEMORY_SIZE {
+                self.memory[start + i] = byte;
+            }
+        }
+        self.debug_print(&format!("Loaded test program: {} bytes", program.len()));
+    }
+}
+
+// Compares observable machine state only; rng, debug and the latched opcode are ignored
+// and only the live part of the stack (0..sp) counts.
+impl PartialEq for Chip8 {
+    fn eq(&self, other: &Self) -> bool {
+        self.registers == other.registers
+            && self.memory == other.memory
+            && self.index == other.index
+            && self.pc == other.pc
+            && self.sp == other.sp
+            && self.stack[..self.sp as usize] == other.stack[..other.sp as usize]
+            && self.delay_timer == other.delay_timer
+            && self.sound_timer == other.sound_timer
+            && self.keypad == other.keypad
+            && self.video == other.video
+            && self.video_plane2 == other.video_plane2
+            && self.plane_mask == other.plane_mask
+            && self.hires == other.hires
+    }
+}
+
+// Memory and video are left out to keep assertion failures readable
+impl std::fmt::Debug for Chip8 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chip8")
+            .field("registers", &self.registers)
+            .field("index", &self.index)
+            .field("pc", &self.pc)
+            .field("stack", &&self.stack[..self.sp as usize])
+            .field("sp", &self.sp)
+            .field("delay_timer", &self.delay_timer)
+            .field("sound_timer", &self.sound_timer)
+            .field("keypad", &self.keypad)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chip8_initialization() {
+        let chip8 = Chip8::new();
+
+        assert_eq!(chip8.pc, START_ADDRESS);
+        assert_eq!(chip8.sp, 0);
+        assert_eq!(chip8.index, 0);
+
+        for &reg in chip8.registers.iter() {
+            assert_eq!(reg, 0);
+        }
+
+        for &pixel in chip8.video.iter() {
+            assert_eq!(pixel, 0);
+        }
+    }
+
+    #[test]
+    fn test_fontset_loaded() {
+        let chip8 = Chip8::new();
+        let start = FONTSET_START_ADDRESS as usize;
+
+        for (i, &expected) in FONTSET.iter().enumerate() {
+            assert_eq!(chip8.memory[start + i], expected)
+        }
+    }
+
+    #[test]
+    fn test_rom_loading() {
+        let dummy_rom = vec![0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08];
+
+        use std::io::Write;
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(&dummy_rom).unwrap();
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(temp_file.path().to_str().unwrap()).unwrap();
+
+        let start = START_ADDRESS as usize;
+        for (i, &expected) in dummy_rom.iter().enumerate() {
+            assert_eq!(chip8.memory[start + i], expected);
+        }
+    }
+
+    #[test]
+    fn test_rom_header_round_trip() {
+        let rom = [0x6A, 0x55, 0x12, 0x00];
+        let mut header = FriesRomHeader::new("Pong", "Paul Vervalin", "Two player pong");
+        header.cpf = 11;
+        header.quirks_flags = 0x0005;
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        Chip8::save_rom_with_header(path, &header, &rom).unwrap();
+
+        let mut chip8 = Chip8::new();
+        let loaded = chip8.load_rom_with_header(path).unwrap().unwrap();
+
+        assert_eq!(loaded, header);
+        assert!(chip8.get_quirks().chip8e_mode); // Bit 0 of quirks_flags
+        assert_eq!(chip8.get_cycles_per_frame(), 11);
+        assert_eq!(loaded.name_str(), "Pong");
+        assert_eq!(&chip8.memory[0x200..0x204], &rom);
+    }
+
+    #[test]
+    fn test_load_rom_with_header_plain_rom() {
+        let rom = [0xA2, 0x2A, 0x60, 0x0C];
+
+        use std::io::Write;
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(&rom).unwrap();
+
+        let mut chip8 = Chip8::new();
+        let header = chip8.load_rom_with_header(temp_file.path().to_str().unwrap()).unwrap();
+
+        assert!(header.is_none());
+        assert_eq!(&chip8.memory[0x200..0x204], &rom);
+    }
+
+    #[test]
+    fn test_rom_header_truncated() {
+        let bytes = b"CH8\0\x01";
+        assert!(FriesRomHeader::parse(bytes).is_err());
+    }
+
+    #[test]
+    fn test_rom_header_unknown_version() {
+        let mut bytes = FriesRomHeader::new("Pong", "", "").to_bytes();
+        bytes[4] = 2;
+        assert!(FriesRomHeader::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_rom_header_quirks() {
+        let mut header = FriesRomHeader::new("Pong", "", "");
+        assert_eq!(header.quirks(), QuirksConfig::default());
+
+        header.quirks_flags = 0;
+        assert_eq!(header.quirks(), QuirksConfig::from_flags(0));
+        header.quirks_flags = FriesRomHeader::DEFAULT_QUIRKS | QUIRK_FLAG_CHIP8E;
+        assert_eq!(header.quirks(), QuirksConfig::default());
+    }
+
+    #[test]
+    fn test_patch_rom() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x60, 0x01, 0x61, 0x02]);
+
+        chip8.patch_rom(2, 0x6A55).unwrap();
+
+        assert_eq!(chip8.memory[0x200], 0x60); // Untouched
+        assert_eq!(chip8.memory[0x202], 0x6A);
+        assert_eq!(chip8.memory[0x203], 0x55);
+    }
+
+    #[test]
+    fn test_patch_rom_out_of_bounds() {
+        let mut chip8 = Chip8::new();
+        let last = (MEMORY_SIZE - START_ADDRESS as usize - 1) as u16;
+
+        assert!(chip8.patch_rom(last, 0x1234).is_err());
+        assert!(chip8.patch_rom(last - 1, 0x1234).is_ok());
+    }
+
+    #[test]
+    fn test_random_byte_generation() {
+        let mut chip8 = Chip8::new();
+
+        let mut values = Vec::new();
+        for _ in 0..10 {
+            values.push(chip8.random_byte());
+        }
+
+        let first = values[0];
+        let all_same = values.iter().all(|&x| x == first);
+        assert!(!all_same, "Random generator produced all identical values");
+    }
+
+    #[test]
+    fn test_with_seed_is_reproducible() {
+        let mut a = Chip8::new().with_seed(42);
+        let mut b = Chip8::new().with_seed(42);
+
+        let first: Vec<u8> = (0..10).map(|_| a.random_byte()).collect();
+        let second: Vec<u8> = (0..10).map(|_| b.random_byte()).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_with_deterministic_sequence() {
+        let mut chip8 = Chip8::new().with_deterministic_sequence(&[0xFF, 0x0F]);
+        // RND V0, 0x3C; RND V1, 0xFF; RND V2, 0xFF
+        chip8.load_test_program(&[0xC0, 0x3C, 0xC1, 0xFF, 0xC2, 0xFF]);
+
+        chip8.step_n(3);
+
+        assert_eq!(&chip8.registers[..3], &[0x3C, 0x0F, 0xFF]);
+    }
+
+    #[test]
+    fn test_rng_stream() {
+        let mut chip8 = Chip8::new().with_deterministic_sequence(&[1, 2, 3]);
+
+        let taken: Vec<u8> = chip8.rng_stream().take(4).collect();
+        assert_eq!(taken, vec![1, 2, 3, 1]);
+
+        // RND V0, 0xFF gets the value after the ones taken above
+        chip8.load_test_program(&[0xC0, 0xFF]);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0], 2);
+    }
+
+    #[test]
+    fn test_rng_stream_matches_seeded_rnd() {
+        let expected: Vec<u8> = Chip8::new().with_seed(7).rng_stream().take(2).collect();
+        let mut chip8 = Chip8::new().with_seed(7);
+        chip8.load_test_program(&[0xC0, 0xFF, 0xC1, 0xFF]); // RND V0, 0xFF; RND V1, 0xFF
+
+        chip8.step_n(2);
+
+        assert_eq!(&chip8.registers[..2], expected.as_slice());
+    }
+
+    #[test]
+    fn test_with_rng_callback() {
+        let mut chip8 = Chip8::new().with_rng(|| 7);
+        assert_eq!(chip8.random_byte(), 7);
+    }
+
+    // OPCODE TESTS
+
+    #[test]
+    fn test_op_00e0_cls() {
+        let mut chip8 = Chip8::new();
+        chip8.video[0] = 0xFFFFFFFF;
+        chip8.video[100] = 0xFFFFFFFF;
+
+        chip8.op_00e0();
+
+        for &pixel in chip8.video.iter() {
+            assert_eq!(pixel, 0);
+        }
+    }
+
+    #[test]
+    fn test_display_hash() {
+        let mut chip8 = Chip8::new();
+        let empty = chip8.display_hash();
+
+        chip8.execute_opcode(0xA050); // LD I, font '0'
+        chip8.execute_opcode(0xD005); // DRW V0, V0, 5
+        assert_eq!(chip8.display_hash(), 0x035d51ba17427bf3);
+
+        chip8.execute_opcode(0x00E0);
+        assert_eq!(chip8.display_hash(), empty);
+        assert_eq!(empty, Chip8::new().display_hash());
+    }
+
+    #[test]
+    fn test_state_to_json() {
+        let mut chip8 = Chip8::new();
+        chip8.execute_opcode(0x6A55); // LD VA, 0x55
+        chip8.execute_opcode(0xA050); // LD I, font '0'
+        chip8.execute_opcode(0xD005); // DRW V0, V0, 5
+        chip8.execute_opcode(0x2300); // CALL 0x300
+
+        assert_eq!(chip8.save_state().to_json(), [
+            "{",
+            "  \"registers\": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 85, 0, 0, 0, 0, 0],",
+            "  \"pc\": 768,",
+            "  \"index\": 80,",
+            "  \"sp\": 1,",
+            "  \"stack\": [512],",
+            "  \"delay_timer\": 0,",
+            "  \"sound_timer\": 0,",
+            "  \"hires\": false,",
+            "  \"video_hash\": \"035d51ba17427bf3\"",
+            "}",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_op_00ee_ret() {
+        let mut chip8 = Chip8::new();
+        chip8.stack[0] = 0x300;
+        chip8.sp = 1;
+
+        chip8.op_00ee();
+
+        assert_eq!(chip8.next_pc, 0x300);
+        assert_eq!(chip8.sp, 0);
+    }
+
+    #[test]
+    fn test_op_00ee_empty_stack() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x00, 0xEE]); // RET
+
+        assert_eq!(chip8.cycle(), Err(ExecutionError::StackUnderflow { pc: 0x200 }));
+
+        assert_eq!(chip8.pc, 0x202); // Skipped
+        assert_eq!(chip8.sp, 0);
+        assert_eq!(chip8.last_error(), Some(ExecutionError::StackUnderflow { pc: 0x200 }));
+    }
+
+    #[test]
+    fn test_memory_ops_wrap_at_last_byte() {
+        let last = (MEMORY_SIZE - 1) as u16;
+
+        let mut chip8 = with_quirks(QuirksConfig::from_flags(0));
+        chip8.registers[..3].copy_from_slice(&[1, 2, 3]);
+        chip8.index = last;
+        chip8.pc = 0x202;
+        chip8.execute_opcode(0xF255); // LD [I], V2
+        assert_eq!((chip8.memory[0xFFFF], chip8.memory[0], chip8.memory[1]), (1, 2, 3));
+        assert_eq!(chip8.last_error(), Some(ExecutionError::MemoryWrap { pc: 0x200, addr: 0xFFFF }));
+
+        chip8.registers[..3].fill(0);
+        chip8.execute_opcode(0xF265); // LD V2, [I]
+        assert_eq!(chip8.registers[..3], [1, 2, 3]);
+
+        chip8.registers[4] = 254;
+        chip8.execute_opcode(0xF433); // LD B, V4
+        assert_eq!((chip8.memory[0xFFFF], chip8.memory[0], chip8.memory[1]), (2, 5, 4));
+
+        chip8.execute_opcode(0x5012); // LD [I], V0-V1
+        chip8.execute_opcode(0x5233); // LD V2-V3, [I]
+        assert_eq!(chip8.registers[2..4], [1, 2]);
+    }
+
+    #[test]
+    fn test_memory_wrap_is_not_a_cycle_error() {
+        let mut chip8 = Chip8::new();
+        // LD [I], V1 from the last byte of memory; EXIT
+        chip8.load_test_program(&[0xF1, 0x55, 0x00, 0xFD]);
+        chip8.index = (MEMORY_SIZE - 1) as u16;
+
+        assert_eq!(chip8.run_until_error(10), Ok(2));
+        assert!(matches!(chip8.last_error(), Some(ExecutionError::MemoryWrap { addr: 0xFFFF, .. })));
+    }
+
+    #[test]
+    fn test_op_dxyn_wraps_at_last_byte() {
+        let mut chip8 = with_quirks(QuirksConfig::from_flags(0));
+        chip8.memory[MEMORY_SIZE - 1] = 0x80;
+        chip8.memory[0] = 0x80;
+        chip8.index = (MEMORY_SIZE - 1) as u16;
+
+        chip8.execute_opcode(0xD00F); // DRW V0, V0, 15
+        assert_eq!(chip8.video[0], 0xFFFFFFFF);
+        assert_eq!(chip8.video[Chip8::VIDEO_WIDTH], 0xFFFFFFFF);
+        assert!(matches!(chip8.last_error(), Some(ExecutionError::MemoryWrap { addr: 0xFFFF, .. })));
+
+        chip8.set_hires(true);
+        chip8.execute_opcode(0xD000); // DRW V0, V0, 0: a 16x16 sprite, 32 bytes
+        assert!(matches!(chip8.last_error(), Some(ExecutionError::MemoryWrap { addr: 0xFFFF, .. })));
+    }
+
+    #[test]
+    fn test_read_and_write_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.write_memory(0x300, 0xAB);
+        chip8.write_memory(0xFFFF, 0xCD);
+
+        assert_eq!(chip8.read_memory(0x300), 0xAB);
+        assert_eq!(chip8.read_memory(0xFFFF), 0xCD);
+        assert_eq!(chip8.memory_slice(0x2FF, 3), [0, 0xAB, 0]);
+        assert_eq!(chip8.memory_slice(0xFFFE, 4), [0, 0xCD]); // Cut short at the end
+        assert_eq!(chip8.memory_slice(0, usize::MAX).len(), MEMORY_SIZE);
+        assert_eq!(chip8.memory_slice(0xFFFF, usize::MAX), [0xCD]);
+        assert_eq!(chip8.total_memory_writes(), 2);
+
+        chip8.write_memory(FONTSET_START_ADDRESS, 0xFF);
+        assert!(!chip8.font_atlas_valid);
+    }
+
+    #[test]
+    fn test_cycle_reports_unknown_opcode() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0xE1, 0x23, 0x60, 0x07]); // E123 isn't an instruction; LD V0, 7
+
+        let err = chip8.cycle().unwrap_err();
+        assert_eq!(err, ExecutionError::UnknownOpcode { pc: 0x200, opcode: 0xE123 });
+        assert_eq!(err.to_string(), "unknown opcode 0xE123 at 0x200");
+        assert_eq!(chip8.last_error(), Some(err));
+
+        // Skipped, and the error doesn't stick to later cycles
+        assert_eq!(chip8.cycle(), Ok(()));
+        assert_eq!(chip8.registers[0], 7);
+    }
+
+    #[test]
+    fn test_cycle_reports_pc_out_of_bounds() {
+        let mut chip8 = Chip8::new();
+        chip8.pc = 0xFFFF;
+
+        assert_eq!(chip8.cycle(), Err(ExecutionError::PcOutOfBounds { pc: 0xFFFF }));
+        assert_eq!(chip8.pc, 0xFFFF);
+        assert_eq!(chip8.get_cycle_count(), 0);
+    }
+
+    #[test]
+    fn test_run_until_error() {
+        let mut chip8 = Chip8::new();
+        // CALL 0x200 forever: the 17th overflows the stack
+        chip8.load_test_program(&[0x22, 0x00]);
+
+        assert_eq!(chip8.run_until_error(100), Err(ExecutionError::StackOverflow { pc: 0x200 }));
+        assert_eq!(chip8.get_cycle_count(), 17);
+
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x70, 0x01, 0x00, 0xFD]); // ADD V0, 1; EXIT
+        assert_eq!(chip8.run_until_error(100), Ok(2));
+        assert_eq!(chip8.run_until_error(100), Ok(0));
+    }
+
+    #[test]
+    fn test_op_2nnn_stack_overflow() {
+        let mut chip8 = Chip8::new();
+        // 17 nested CALLs, each to the next instruction
+        let program: Vec<u8> = (0..17u16)
+            .flat_map(|i| (0x2000 | (0x202 + 2 * i)).to_be_bytes())
+            .collect();
+        chip8.load_test_program(&program);
+
+        for _ in 0..16 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.sp as usize, STACK_SIZE);
+        assert_eq!(chip8.last_error(), None);
+
+        assert_eq!(chip8.cycle(), Err(ExecutionError::StackOverflow { pc: 0x220 }));
+
+        assert_eq!(chip8.sp as usize, STACK_SIZE);
+        assert_eq!(chip8.pc, 0x222); // The 17th CALL was skipped
+        assert_eq!(chip8.last_error(), Some(ExecutionError::StackOverflow { pc: 0x220 }));
+        assert_eq!(chip8.last_error().unwrap().to_string(), "stack overflow at 0x220");
+    }
+
+    #[test]
+    fn test_op_1nnn_jump() {
+        let mut chip8 = Chip8::new();
+        chip8.opcode = 0x1234;
+
+        chip8.op_1nnn();
+
+        assert_eq!(chip8.next_pc, 0x234);
+    }
+
+    #[test]
+    fn test_op_2nnn_call() {
+        let mut chip8 = Chip8::new();
+        chip8.pc = 0x2FE;
+        chip8.next_pc = 0x300;
+        chip8.opcode = 0x2456;
+
+        chip8.op_2nnn();
+
+        assert_eq!(chip8.stack[0], 0x300);
+        assert_eq!(chip8.sp, 1);
+        assert_eq!(chip8.next_pc, 0x456);
+    }
+
+    #[test]
+    fn test_op_3xkk_skip_equal() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0x33;
+        chip8.opcode = 0x3533; // SE V5, 0x33
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_3xkk();
+
+        assert_eq!(chip8.next_pc, 0x204); // Should skip
+    }
+
+    #[test]
+    fn test_op_3xkk_no_skip() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0x22;
+        chip8.opcode = 0x3533; // SE V5, 0x33
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_3xkk();
+
+        assert_eq!(chip8.next_pc, 0x202); // Should not skip
+    }
+
+    fn chip8e() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(QuirksConfig { chip8e_mode: true, ..QuirksConfig::default() });
+        chip8.registers[2] = 0b11110000;
+        chip8.registers[3] = 0b11001100;
+        chip8.registers[0xF] = 0x77;
+        chip8
+    }
+
+    #[test]
+    fn test_op_5xy1_chip8e_or() {
+        let mut chip8 = chip8e();
+        chip8.load_test_program(&[0x52, 0x31]); // OR V2, V3
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[2], 0b11111100);
+        assert_eq!(chip8.registers[0xF], 0x77); // VF untouched
+    }
+
+    #[test]
+    fn test_op_5xy2_chip8e_and() {
+        let mut chip8 = chip8e();
+        chip8.load_test_program(&[0x52, 0x32]); // AND V2, V3
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[2], 0b11000000);
+        assert_eq!(chip8.registers[0xF], 0x77);
+    }
+
+    #[test]
+    fn test_op_5xy3_chip8e_xor() {
+        let mut chip8 = chip8e();
+        chip8.load_test_program(&[0x52, 0x33]); // XOR V2, V3
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[2], 0b00111100);
+        assert_eq!(chip8.registers[0xF], 0x77);
+    }
+
+    #[test]
+    fn test_op_5xy1_ignored_without_chip8e() {
+        let mut chip8 = chip8e();
+        chip8.set_quirks(QuirksConfig::default());
+        chip8.load_test_program(&[0x52, 0x31]);
+
+        assert_eq!(chip8.cycle(), Err(ExecutionError::UnknownOpcode { pc: 0x200, opcode: 0x5231 }));
+
+        assert_eq!(chip8.registers[2], 0b11110000);
+    }
+
+    fn xo_chip() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.index = 0x300;
+        for (i, reg) in chip8.registers.iter_mut().enumerate() {
+            *reg = 0xA0 + i as u8;
+        }
+        chip8
+    }
+
+    #[test]
+    fn test_op_5xy2_store_range_ascending() {
+        let mut chip8 = xo_chip();
+        chip8.load_test_program(&[0x52, 0x42]); // LD [I], V2-V4
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(&chip8.memory[0x300..0x304], &[0xA2, 0xA3, 0xA4, 0x00]);
+        assert_eq!(chip8.index, 0x300);
+    }
+
+    #[test]
+    fn test_op_5xy2_store_single_register() {
+        let mut chip8 = xo_chip();
+        chip8.load_test_program(&[0x57, 0x72]); // LD [I], V7-V7
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(&chip8.memory[0x300..0x302], &[0xA7, 0x00]);
+    }
+
+    #[test]
+    fn test_op_5xy2_store_range_descending() {
+        let mut chip8 = xo_chip();
+        chip8.load_test_program(&[0x54, 0x22]); // LD [I], V4-V2
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(&chip8.memory[0x300..0x303], &[0xA4, 0xA3, 0xA2]);
+    }
+
+    #[test]
+    fn test_op_5xy3_load_range() {
+        let mut chip8 = xo_chip();
+        chip8.memory[0x300..0x303].copy_from_slice(&[0x11, 0x22, 0x33]);
+        // LD V2-V4, [I]; LD VA-VA, [I]; LD VE-VC, [I]
+        chip8.load_test_program(&[0x52, 0x43, 0x5A, 0xA3, 0x5E, 0xC3]);
+
+        chip8.step_n(3);
+
+        assert_eq!(&chip8.registers[2..5], &[0x11, 0x22, 0x33]);
+        assert_eq!(chip8.registers[0xA], 0x11);
+        assert_eq!(&chip8.registers[0xC..0xF], &[0x33, 0x22, 0x11]);
+        assert_eq!(chip8.index, 0x300);
+    }
+
+    #[test]
+    fn test_op_5xy2_is_and_in_chip8e_mode() {
+        let mut chip8 = chip8e();
+        chip8.index = 0x300;
+        chip8.load_test_program(&[0x52, 0x32]);
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[2], 0b11000000);
+        assert_eq!(chip8.memory[0x300], 0);
+    }
+
+    #[test]
+    fn test_quirks_display() {
+        assert_eq!(QuirksConfig::default().to_string(), "Quirks: []");
+        assert_eq!(
+            QuirksConfig::from_flags(0).to_string(),
+            "Quirks: [shift-in-place, fx55-no-increment, no-vf-reset, no-display-wait]"
+        );
+        let quirks = QuirksConfig { chip8e_mode: true, clip_sprites: false, ..QuirksConfig::default() };
+        assert_eq!(quirks.to_string(), "Quirks: [chip8e, wrap-sprites]");
+    }
+
+    #[test]
+    fn test_quirks_from_names() {
+        let quirks = QuirksConfig { chip8e_mode: true, shift_uses_vy: false, ..QuirksConfig::default() };
+        assert_eq!(QuirksConfig::from_names(&quirks.to_string()).unwrap(), quirks);
+        assert_eq!(QuirksConfig::from_names("chip8e,shift-in-place").unwrap(), quirks);
+        assert_eq!(QuirksConfig::from_names("").unwrap(), QuirksConfig::default());
+        assert!(QuirksConfig::from_names("vf_reset").is_err());
+    }
+
+    #[test]
+    fn test_quirks_flags_round_trip() {
+        let quirks = QuirksConfig { chip8e_mode: true, jump_uses_vx: true, ..QuirksConfig::default() };
+        assert_eq!(QuirksConfig::from_flags(quirks.to_flags()), quirks);
+        assert_eq!(QuirksConfig::from_flags(QuirksConfig::default().to_flags()), QuirksConfig::default());
+        assert_eq!(QuirksConfig::from_flags(0).to_flags(), 0);
+    }
+
+    // Each quirk on and off; everything else stays at the VIP defaults
+    fn with_quirks(quirks: QuirksConfig) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(quirks);
+        chip8
+    }
+
+    #[test]
+    fn test_quirk_shift_uses_vy() {
+        for (shift_uses_vy, expected) in [(true, 0b0000_0011), (false, 0b0100_0000)] {
+            let mut chip8 = with_quirks(QuirksConfig { shift_uses_vy, ..QuirksConfig::default() });
+            chip8.registers[1] = 0b1000_0000;
+            chip8.registers[2] = 0b0000_0111;
+
+            chip8.execute_opcode(0x8126); // SHR V1, V2
+
+            assert_eq!(chip8.registers[1], expected);
+            assert_eq!(chip8.registers[0xF], shift_uses_vy as u8); // Bit shifted out
+        }
+
+        for (shift_uses_vy, expected) in [(true, 0b0000_1110), (false, 0b0000_0010)] {
+            let mut chip8 = with_quirks(QuirksConfig { shift_uses_vy, ..QuirksConfig::default() });
+            chip8.registers[1] = 0b1000_0001;
+            chip8.registers[2] = 0b0000_0111;
+
+            chip8.execute_opcode(0x812E); // SHL V1, V2
+
+            assert_eq!(chip8.registers[1], expected);
+            assert_eq!(chip8.registers[0xF], !shift_uses_vy as u8);
+        }
+    }
+
+    #[test]
+    fn test_quirk_load_store_increments_i() {
+        for (load_store_increments_i, expected) in [(true, 0x303), (false, 0x300)] {
+            let quirks = QuirksConfig { load_store_increments_i, ..QuirksConfig::default() };
+            let mut chip8 = with_quirks(quirks);
+            chip8.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+            chip8.index = 0x300;
+            chip8.execute_opcode(0xF255); // LD [I], V2
+            assert_eq!(chip8.index, expected);
+            assert_eq!(chip8.memory[0x300..0x304], [1, 2, 3, 0]); // Either way, V3 isn't stored
+
+            let mut chip8 = with_quirks(quirks);
+            chip8.memory[0x300..0x304].copy_from_slice(&[5, 6, 7, 8]);
+            chip8.index = 0x300;
+            chip8.execute_opcode(0xF265); // LD V2, [I]
+            assert_eq!(chip8.index, expected);
+            assert_eq!(chip8.registers[..4], [5, 6, 7, 0]);
+        }
+    }
+
+    #[test]
+    fn test_quirk_jump_uses_vx() {
+        for (jump_uses_vx, expected) in [(true, 0x320), (false, 0x310)] {
+            let mut chip8 = with_quirks(QuirksConfig { jump_uses_vx, ..QuirksConfig::default() });
+            chip8.registers[0] = 0x10;
+            chip8.registers[3] = 0x20;
+
+            chip8.execute_opcode(0xB300); // JP V0, 0x300 (or V3 with the quirk)
+
+            assert_eq!(chip8.pc, expected);
+        }
+    }
+
+    #[test]
+    fn test_quirk_vf_reset_on_logic() {
+        for opcode in [0x8121, 0x8122, 0x8123] { // OR, AND, XOR V1, V2
+            for (vf_reset_on_logic, expected) in [(true, 0), (false, 0x55)] {
+                let mut chip8 = with_quirks(QuirksConfig { vf_reset_on_logic, ..QuirksConfig::default() });
+                chip8.registers[0xF] = 0x55;
+
+                chip8.execute_opcode(opcode);
+
+                assert_eq!(chip8.registers[0xF], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quirk_i_overflow_sets_vf() {
+        // (I, quirk) -> (I after ADD I, V1 with V1 = 0x20, VF)
+        for (index, i_overflow_sets_vf, expected) in [
+            (0x300, true, (0x320, 0)),
+            (0x300, false, (0x320, 0x55)),
+            (0xFF0, true, (0x1010, 1)),
+            (0xFF0, false, (0x1010, 0x55)),
+        ] {
+            let mut chip8 = with_quirks(QuirksConfig { i_overflow_sets_vf, ..QuirksConfig::default() });
+            chip8.index = index;
+            chip8.registers[1] = 0x20;
+            chip8.registers[0xF] = 0x55;
+
+            chip8.execute_opcode(0xF11E); // ADD I, V1
+
+            assert_eq!((chip8.index, chip8.registers[0xF]), expected);
+        }
+    }
+
+    #[test]
+    fn test_quirk_clip_sprites() {
+        let lit_pixels = |clip_sprites| {
+            let mut chip8 = with_quirks(QuirksConfig { clip_sprites, ..QuirksConfig::default() });
+            chip8.registers[0] = 62;
+            chip8.registers[1] = 30;
+            chip8.index = FONTSET_START_ADDRESS; // '0': F0 90 90 90 F0
+
+            chip8.execute_opcode(0xD015); // DRW V0, V1, 5
+
+            chip8.video.iter()
+                .enumerate()
+                .filter(|&(_, &pixel)| pixel != 0)
+                .map(|(i, _)| (i % VIDEO_WIDTH, i / VIDEO_WIDTH))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(lit_pixels(true), vec![(62, 30), (63, 30), (62, 31)]);
+
+        let wrapped = lit_pixels(false);
+        assert_eq!(wrapped.len(), 14); // The whole glyph
+        for pixel in [(0, 30), (1, 31), (62, 0), (1, 2)] {
+            assert!(wrapped.contains(&pixel), "{:?} not drawn", pixel);
+        }
+    }
+
+    #[test]
+    fn test_quirk_display_wait() {
+        for (display_wait, second_draw_pc) in [(true, 0x200), (false, 0x202)] {
+            let mut chip8 = with_quirks(QuirksConfig { display_wait, ..QuirksConfig::default() });
+            chip8.index = FONTSET_START_ADDRESS;
+
+            chip8.execute_opcode(0xD015); // DRW V0, V0, 5, drawn right away
+            assert_eq!(chip8.pc, 0x200);
+            assert_eq!(chip8.video[0], 0xFFFFFFFF);
+
+            // A second draw in the same frame is deferred: PC backs up and nothing changes
+            chip8.pc = 0x202;
+            chip8.execute_opcode(0xD015);
+            assert_eq!(chip8.pc, second_draw_pc);
+            assert_eq!(chip8.video[0], if display_wait { 0xFFFFFFFF } else { 0 });
+
+            // After the timer tick it goes through
+            chip8.tick_timers();
+            chip8.pc = 0x202;
+            chip8.execute_opcode(0xD015);
+            assert_eq!(chip8.pc, 0x202);
+        }
+    }
+
+    #[test]
+    fn test_display_wait_through_cycle() {
+        let mut chip8 = Chip8::new();
+        chip8.set_cycles_per_frame(4);
+        chip8.load_test_program(&[0xA0, 0x50, 0xD0, 0x15, 0xD0, 0x15]); // LD I, font '0'; DRW x2
+        chip8.sound_timer = 5;
+
+        chip8.run_cycles(2);
+        assert_eq!(chip8.video[0], 0xFFFFFFFF);
+        assert_eq!(chip8.sound_timer, 5); // Half a frame in, no tick yet
+
+        // The second draw waits out the rest of the frame, then erases the first
+        chip8.run_cycles(2);
+        assert_eq!(chip8.pc, 0x204);
+        assert_eq!(chip8.video[0], 0xFFFFFFFF);
+        assert_eq!(chip8.sound_timer, 4);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc, 0x206);
+        assert_eq!(chip8.video[0], 0);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_op_f000_long_index() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0xF0, 0x00, 0x12, 0x34]); // LD I, long 0x1234
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.index, 0x1234);
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn test_long_index_reaches_extended_memory() {
+        let mut chip8 = with_quirks(QuirksConfig::from_flags(0));
+        chip8.memory[0xE000] = 0x42;
+        // LD I, long 0xE000; LD V0, [I]
+        chip8.load_test_program(&[0xF0, 0x00, 0xE0, 0x00, 0xF0, 0x65]);
+
+        chip8.run_cycles(2);
+
+        assert_eq!(chip8.registers[0], 0x42);
+    }
+
+    #[test]
+    fn test_skip_steps_over_long_index() {
+        let mut chip8 = Chip8::new();
+        // SE V0, 0; LD I, long 0x1234; LD V1, 1
+        chip8.load_test_program(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01]);
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.pc, 0x206);
+    }
+
+    #[test]
+    fn test_plane_2_draws_leave_plane_1_alone() {
+        let mut chip8 = with_quirks(QuirksConfig::from_flags(0));
+        chip8.index = FONTSET_START_ADDRESS;
+        chip8.execute_opcode(0xD005); // DRW V0, V0, 5 into plane 1
+        let plane1 = chip8.video.clone();
+
+        chip8.execute_opcode(0xF201); // PLANE 2
+        chip8.execute_opcode(0xD005);
+
+        assert_eq!(chip8.video, plane1);
+        assert_eq!(chip8.video_plane2, plane1);
+        assert_eq!(chip8.registers[0xF], 0); // No collision across planes
+
+        chip8.execute_opcode(0x00E0); // Clears plane 2 only
+        assert_eq!(chip8.video, plane1);
+        assert!(chip8.video_plane2.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn test_both_planes_read_consecutive_sprites() {
+        let mut chip8 = with_quirks(QuirksConfig::from_flags(0));
+        chip8.index = 0x300;
+        chip8.memory[0x300] = 0b1100_0000; // Plane 1
+        chip8.memory[0x301] = 0b0110_0000; // Plane 2
+
+        chip8.execute_opcode(0xF301); // PLANE 3
+        chip8.execute_opcode(0xD001); // DRW V0, V0, 1
+
+        let mut buf = vec![0; VIDEO_SIZE];
+        chip8.copy_display_to_buffer(&mut buf);
+        assert_eq!(buf[..4], [0xFFFFFFFF, BOTH_PLANES_PIXEL, PLANE2_PIXEL, 0]);
+    }
+
+    #[test]
+    fn test_op_6xkk_load() {
+        let mut chip8 = Chip8::new();
+        chip8.opcode = 0x6A55; // LD VA, 0x55
+
+        chip8.op_6xkk();
+
+        assert_eq!(chip8.registers[0xA], 0x55);
+    }
+
+    #[test]
+    fn test_op_7xkk_add() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[3] = 0x10;
+        chip8.opcode = 0x7315; // ADD V3, 0x15
+
+        chip8.op_7xkk();
+
+        assert_eq!(chip8.registers[3], 0x25);
+    }
+
+    #[test]
+    fn test_op_7xkk_add_overflow() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[3] = 0xFF;
+        chip8.opcode = 0x7301; // ADD V3, 0x01
+
+        chip8.op_7xkk();
+
+        assert_eq!(chip8.registers[3], 0x00); // Should wrap around
+    }
+
+    #[test]
+    fn test_op_annn_load_index() {
+        let mut chip8 = Chip8::new();
+        chip8.opcode = 0xA123; // LD I, 0x123
+
+        chip8.op_annn();
+
+        assert_eq!(chip8.index, 0x123);
+    }
+
+    #[test]
+    fn test_fetch_decode_execute() {
+        let mut chip8 = Chip8::new();
+
+        // Place a simple instruction in memory: 6A55 (LD VA, 0x55)
+        chip8.memory[0x200] = 0x6A;
+        chip8.memory[0x201] = 0x55;
+
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[0xA], 0x55);
+        assert_eq!(chip8.pc, 0x202); // PC should advance
+    }
+
+    #[test]
+    fn test_op_8xy1_or() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b11110000;
+        chip8.registers[3] = 0b00001111;
+        chip8.opcode = 0x8231; // OR V2, V3
+
+        chip8.op_8xy1();
+
+        assert_eq!(chip8.registers[2], 0b11111111);
+    }
+
+    #[test]
+    fn test_op_8xy2_and() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b11110000;
+        chip8.registers[3] = 0b11001100;
+        chip8.opcode = 0x8232; // AND V2, V3
+
+        chip8.op_8xy2();
+
+        assert_eq!(chip8.registers[2], 0b11000000);
+    }
+
+    #[test]
+    fn test_op_8xy3_xor() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b11110000;
+        chip8.registers[3] = 0b11001100;
+        chip8.opcode = 0x8233; // XOR V2, V3
+
+        chip8.op_8xy3();
+
+        assert_eq!(chip8.registers[2], 0b00111100);
+    }
+
+    #[test]
+    fn test_op_8xy4_add_no_carry() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 100;
+        chip8.registers[3] = 50;
+        chip8.opcode = 0x8234; // ADD V2, V3
+
+        chip8.op_8xy4();
+
+        assert_eq!(chip8.registers[2], 150);
+        assert_eq!(chip8.registers[0xF], 0); // No carry
+    }
+
+    #[test]
+    fn test_op_8xy4_add_with_carry() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 200;
+        chip8.registers[3] = 100;
+        chip8.opcode = 0x8234; // ADD V2, V3
+
+        chip8.op_8xy4();
+
+        assert_eq!(chip8.registers[2], 44); // 300 & 0xFF = 44
+        assert_eq!(chip8.registers[0xF], 1); // Carry set
+    }
+
+    #[test]
+    fn test_op_8xy5_sub_no_borrow() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 100;
+        chip8.registers[3] = 50;
+        chip8.opcode = 0x8235; // SUB V2, V3
+
+        chip8.op_8xy5();
+
+        assert_eq!(chip8.registers[2], 50);
+        assert_eq!(chip8.registers[0xF], 1); // No borrow (Vx > Vy)
+    }
+
+    #[test]
+    fn test_op_8xy5_sub_with_borrow() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 50;
+        chip8.registers[3] = 100;
+        chip8.opcode = 0x8235; // SUB V2, V3
+
+        chip8.op_8xy5();
+
+        assert_eq!(chip8.registers[2], 206); // 50 - 100 wraps to 206
+        assert_eq!(chip8.registers[0xF], 0); // Borrow occurred (Vx < Vy)
+    }
+
+    #[test]
+    fn test_op_8xy4_vx_is_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[0xF] = 200;
+        chip8.registers[1] = 100;
+        chip8.opcode = 0x8F14; // ADD VF, V1
+
+        chip8.op_8xy4();
+
+        assert_eq!(chip8.registers[0xF], 1); // Carry overwrites the sum
+    }
+
+    #[test]
+    fn test_op_8xy4_vy_is_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 200;
+        chip8.registers[0xF] = 100;
+        chip8.opcode = 0x82F4; // ADD V2, VF
+
+        chip8.op_8xy4();
+
+        assert_eq!(chip8.registers[2], 44);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_op_8xy5_vx_is_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[0xF] = 50;
+        chip8.registers[1] = 100;
+        chip8.opcode = 0x8F15; // SUB VF, V1
+
+        chip8.op_8xy5();
+
+        assert_eq!(chip8.registers[0xF], 0); // Borrow flag overwrites the difference (206)
+    }
+
+    #[test]
+    fn test_op_8xy5_vy_is_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 100;
+        chip8.registers[0xF] = 50;
+        chip8.opcode = 0x82F5; // SUB V2, VF
+
+        chip8.op_8xy5();
+
+        assert_eq!(chip8.registers[2], 50);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_op_8xy7_vx_is_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[0xF] = 100;
+        chip8.registers[1] = 50;
+        chip8.opcode = 0x8F17; // SUBN VF, V1
+
+        chip8.op_8xy7();
+
+        assert_eq!(chip8.registers[0xF], 0); // Borrow flag overwrites the difference (206)
+    }
+
+    #[test]
+    fn test_op_8xy7_vy_is_vf() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 50;
+        chip8.registers[0xF] = 100;
+        chip8.opcode = 0x82F7; // SUBN V2, VF
+
+        chip8.op_8xy7();
+
+        assert_eq!(chip8.registers[2], 50);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_op_8xy6_shr() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b10101011;
+        chip8.opcode = 0x8226; // SHR V2, V2
+
+        chip8.op_8xy6();
+
+        assert_eq!(chip8.registers[2], 0b01010101);
+        assert_eq!(chip8.registers[0xF], 1); // LSB was 1
+    }
+
+    #[test]
+    fn test_op_8xy6_shr_lsb_zero() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b10101010;
+        chip8.opcode = 0x8226; // SHR V2, V2
+
+        chip8.op_8xy6();
+
+        assert_eq!(chip8.registers[2], 0b01010101);
+        assert_eq!(chip8.registers[0xF], 0); // LSB was 0
+    }
+
+    #[test]
+    fn test_op_8xy7_subn_no_borrow() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 50;
+        chip8.registers[3] = 100;
+        chip8.opcode = 0x8237; // SUBN V2, V3
+
+        chip8.op_8xy7();
+
+        assert_eq!(chip8.registers[2], 50); // 100 - 50
+        assert_eq!(chip8.registers[0xF], 1); // No borrow (Vy > Vx)
+    }
+
+    #[test]
+    fn test_op_8xy7_subn_with_borrow() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 100;
+        chip8.registers[3] = 50;
+        chip8.opcode = 0x8237; // SUBN V2, V3
+
+        chip8.op_8xy7();
+
+        assert_eq!(chip8.registers[2], 206); // 50 - 100 wraps to 206
+        assert_eq!(chip8.registers[0xF], 0); // Borrow occurred (Vy < Vx)
+    }
+
+    #[test]
+    fn test_op_8xye_shl() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b10101011;
+        chip8.opcode = 0x822E; // SHL V2, V2
+
+        chip8.op_8xye();
+
+        assert_eq!(chip8.registers[2], 0b01010110);
+        assert_eq!(chip8.registers[0xF], 1); // MSB was 1
+    }
+
+    #[test]
+    fn test_op_8xye_shl_msb_zero() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[2] = 0b01010101;
+        chip8.opcode = 0x822E; // SHL V2, V2
+
+        chip8.op_8xye();
+
+        assert_eq!(chip8.registers[2], 0b10101010);
+        assert_eq!(chip8.registers[0xF], 0); // MSB was 0
+    }
+
+    // Tests for Exxx opcodes
+
+    #[test]
+    fn test_op_ex9e_key_pressed() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0xA;
+        chip8.keypad[0xA] = true;
+        chip8.opcode = 0xE59E; // SKP V5
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_ex9e();
+
+        assert_eq!(chip8.next_pc, 0x204); // Should skip
+    }
+
+    #[test]
+    fn test_op_ex9e_key_not_pressed() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0xA;
+        chip8.keypad[0xA] = false;
+        chip8.opcode = 0xE59E; // SKP V5
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_ex9e();
+
+        assert_eq!(chip8.next_pc, 0x202); // Should not skip
+    }
+
+    #[test]
+    fn test_op_exa1_key_not_pressed() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0xA;
+        chip8.keypad[0xA] = false;
+        chip8.opcode = 0xE5A1; // SKNP V5
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_exa1();
+
+        assert_eq!(chip8.next_pc, 0x204); // Should skip
+    }
+
+    #[test]
+    fn test_op_exa1_key_pressed() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0xA;
+        chip8.keypad[0xA] = true;
+        chip8.opcode = 0xE5A1; // SKNP V5
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_exa1();
+
+        assert_eq!(chip8.next_pc, 0x202); // Should not skip
+    }
+
+    // Tests for Fxxx opcodes
+
+    #[test]
+    fn test_op_fx07_load_delay_timer() {
+        let mut chip8 = Chip8::new();
+        chip8.delay_timer = 0x42;
+        chip8.opcode = 0xF507; // LD V5, DT
+
+        chip8.op_fx07();
+
+        assert_eq!(chip8.registers[5], 0x42);
+    }
+
+    #[test]
+    fn test_op_fx0a_key_pressed() {
+        let mut chip8 = Chip8::new();
+        chip8.keypad[7] = true;
+        chip8.opcode = 0xF50A; // LD V5, K
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_fx0a();
+
+        assert_eq!(chip8.registers[5], 0); // Pressed but not released yet
+        assert_eq!(chip8.next_pc, 0x200);
+
+        chip8.keypad[7] = false;
+        chip8.next_pc = 0x202;
+        chip8.op_fx0a();
+
+        assert_eq!(chip8.registers[5], 7);
+        assert_eq!(chip8.next_pc, 0x202); // Moves on once the key is found
+    }
+
+    #[test]
+    fn test_op_fx0a_waits_for_release() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0xF5, 0x0A]); // LD V5, K
+
+        let mut keys = [false; KEY_COUNT];
+        keys[7] = true;
+        chip8.set_keys(&keys);
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+            assert_eq!(chip8.pc, 0x200); // Held: keeps repeating
+        }
+        assert_eq!(chip8.registers[5], 0);
+
+        chip8.set_keys(&[false; KEY_COUNT]);
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.registers[5], 7);
+        assert_eq!(chip8.pc, 0x202);
+        assert!(!chip8.is_waiting_for_key());
+    }
+
+    #[test]
+    fn test_op_fx0a_no_key_pressed() {
+        let mut chip8 = Chip8::new();
+        // All keys are false by default
+        chip8.opcode = 0xF50A; // LD V5, K
+        chip8.pc = 0x200;
+        chip8.next_pc = 0x202;
+
+        chip8.op_fx0a();
+
+        assert_eq!(chip8.next_pc, 0x200); // Stays on the instruction to run it again
+    }
+
+    #[test]
+    fn test_op_fx0a_waits_at_top_of_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0xFFFE..].copy_from_slice(&[0xF5, 0x0A]); // LD V5, K
+        chip8.pc = 0xFFFE;
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc, 0xFFFE);
+        assert!(chip8.is_waiting_for_key());
+    }
+
+    #[test]
+    fn test_is_waiting_for_key() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0xF5, 0x0A]); // LD V5, K
+
+        chip8.cycle().unwrap();
+        assert!(chip8.is_waiting_for_key());
+
+        chip8.keypad[3] = true;
+        chip8.cycle().unwrap();
+        assert!(chip8.is_waiting_for_key()); // Until the key comes back up
+
+        chip8.keypad[3] = false;
+        chip8.cycle().unwrap();
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.registers[5], 3);
+    }
+
+    #[test]
+    fn test_process_stops_while_waiting_for_key() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0xF5, 0x0A]); // LD V5, K
+
+        chip8.process(input(10, false));
+
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.get_cycle_count(), 1);
+    }
+
+    #[test]
+    fn test_op_fx15_set_delay_timer() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0x42;
+        chip8.opcode = 0xF515; // LD DT, V5
+
+        chip8.op_fx15();
+
+        assert_eq!(chip8.delay_timer, 0x42);
+    }
+
+    #[test]
+    fn test_op_fx18_set_sound_timer() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0x42;
+        chip8.opcode = 0xF518; // LD ST, V5
+
+        chip8.op_fx18();
+
+        assert_eq!(chip8.sound_timer, 0x42);
+    }
+
+    #[test]
+    fn test_op_fx1e_add_to_index() {
+        let mut chip8 = Chip8::new();
+        chip8.index = 0x200;
+        chip8.registers[5] = 0x10;
+        chip8.opcode = 0xF51E; // ADD I, V5
+
+        chip8.op_fx1e();
+
+        assert_eq!(chip8.index, 0x210);
+    }
+
+    #[test]
+    fn test_op_fx29_load_font_address() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 0xA;
+        chip8.opcode = 0xF529; // LD F, V5
+
+        chip8.op_fx29();
+
+        // Font for 'A' (0xA) should be at 0x50 + (5 * 0xA) = 0x50 + 50 = 0x82
+        assert_eq!(chip8.index, 0x50 + (5 * 0xA));
+    }
+
+    #[test]
+    fn test_op_fx33_bcd_conversion() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 234;
+        chip8.index = 0x300;
+        chip8.opcode = 0xF533; // LD B, V5
+
+        chip8.op_fx33();
+
+        assert_eq!(chip8.memory[0x300], 2); // Hundreds
+        assert_eq!(chip8.memory[0x301], 3); // Tens
+        assert_eq!(chip8.memory[0x302], 4); // Ones
+    }
+
+    #[test]
+    fn test_op_fx33_bcd_conversion_small() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[5] = 7;
+        chip8.index = 0x300;
+        chip8.opcode = 0xF533; // LD B, V5
+
+        chip8.op_fx33();
+
+        assert_eq!(chip8.memory[0x300], 0); // Hundreds
+        assert_eq!(chip8.memory[0x301], 0); // Tens
+        assert_eq!(chip8.memory[0x302], 7); // Ones
+    }
+
+    #[test]
+    fn test_op_fx55_store_registers() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[0] = 0x10;
+        chip8.registers[1] = 0x20;
+        chip8.registers[2] = 0x30;
+        chip8.index = 0x300;
+        chip8.opcode = 0xF255; // LD [I], V2 (store V0-V2)
+
+        chip8.op_fx55();
+
+        assert_eq!(chip8.memory[0x300], 0x10);
+        assert_eq!(chip8.memory[0x301], 0x20);
+        assert_eq!(chip8.memory[0x302], 0x30);
+    }
+
+    #[test]
+    fn test_op_fx65_load_registers() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x300] = 0x10;
+        chip8.memory[0x301] = 0x20;
+        chip8.memory[0x302] = 0x30;
+        chip8.index = 0x300;
+        chip8.opcode = 0xF265; // LD V2, [I] (load V0-V2)
+
+        chip8.op_fx65();
+
+        assert_eq!(chip8.registers[0], 0x10);
+        assert_eq!(chip8.registers[1], 0x20);
+        assert_eq!(chip8.registers[2], 0x30);
+    }
+
+    #[test]
+    fn test_op_dxyn_draw() {
+        let mut chip8 = Chip8::new();
+
+        // Set up a simple 1x1 sprite (just one byte with all bits set)
+        chip8.index = 0x300;
+        chip8.memory[0x300] = 0xFF; // 11111111 in binary
+
+        // Draw at position (0, 0)
+        chip8.registers[0] = 0; // x position
+        chip8.registers[1] = 0; // y position
+        chip8.opcode = 0xD011; // DRW V0, V1, 1
+
+        chip8.op_dxyn();
+
+        // Check that the first 8 pixels in the first row are set
+        for i in 0..8 {
+            assert_eq!(chip8.video[i], 0xFFFFFFFF);
+        }
+
+        // Check that collision flag is not set (nothing was there before)
+        assert_eq!(chip8.registers[0xF], 0);
+    }
+
+    #[test]
+    fn test_copy_display_to_buffer() {
+        let mut chip8 = Chip8::new();
+        chip8.video[5] = 0xFFFFFFFF;
+        let mut buf = [0u32; VIDEO_SIZE];
+
+        chip8.copy_display_to_buffer(&mut buf);
+
+        assert_eq!(&buf[..], chip8.get_display());
+    }
+
+    #[test]
+    #[should_panic(expected = "display buffer too small")]
+    fn test_copy_display_to_buffer_too_small() {
+        let chip8 = Chip8::new();
+        let mut buf = [0u32; 16];
+
+        chip8.copy_display_to_buffer(&mut buf);
+    }
+
+    #[test]
+    fn test_frame_buffer_diff() {
+        let mut chip8 = Chip8::new();
+        let before = chip8.get_frame_buffer();
+
+        chip8.video[0] = 0xFFFFFFFF; // (0, 0)
+        chip8.video[VIDEO_WIDTH + 3] = 0xFFFFFFFF; // (3, 1)
+        let after = chip8.get_frame_buffer();
+
+        assert_eq!(before.diff(&after), vec![(0, 0), (3, 1)]);
+        assert_eq!(after.diff(&after), vec![]);
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x6A, 0x55, 0xA3, 0x00, 0xF0, 0x55]); // LD VA, 0x55; LD I, 0x300; LD [I], V0
+        chip8.registers[0] = 0x42;
+        let before = chip8.save_state();
+
+        chip8.cycle().unwrap();
+        let diff = Chip8State::diff(&before, &chip8.save_state());
+        assert_eq!(diff.changed_registers, vec![(0xA, 0x00, 0x55)]);
+        assert_eq!(diff.pc_changed, Some((0x200, 0x202)));
+        assert_eq!(diff.index_changed, None);
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        let diff = Chip8State::diff(&before, &chip8.save_state());
+        assert_eq!(diff.changed_memory, vec![(0x300, 0x00, 0x42)]);
+        assert_eq!(diff.index_changed, Some((0x000, 0x301))); // Fx55 moved I past V0
+        assert!(!diff.display_changed);
+    }
+
+    #[test]
+    fn test_state_diff_stack_timers_and_keys() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x23, 0x00]); // CALL 0x300
+        let before = chip8.save_state();
+
+        chip8.cycle().unwrap();
+        chip8.delay_timer = 3;
+        chip8.sound_timer = 4;
+        chip8.keypad[0xB] = true;
+        let diff = Chip8State::diff(&before, &chip8.save_state());
+        assert_eq!(diff.changed_stack, vec![(0, 0x000, 0x202)]);
+        assert_eq!(diff.sp_changed, Some((0, 1)));
+        assert_eq!(diff.delay_timer_changed, Some((0, 3)));
+        assert_eq!(diff.sound_timer_changed, Some((0, 4)));
+        assert_eq!(diff.changed_keys, vec![(0xB, false, true)]);
+    }
+
+    #[test]
+    fn test_state_diff_identical() {
+        let chip8 = Chip8::new();
+        let state = chip8.save_state();
+
+        assert_eq!(Chip8State::diff(&state, &state), StateDiff::default());
+    }
+
+    #[test]
+    fn test_chip8_equality() {
+        let program = [0x6A, 0x55, 0x22, 0x06, 0x00, 0x00, 0x00, 0xEE]; // LD VA, 0x55; CALL 0x206; RET
+        let mut a = Chip8::new();
+        let mut b = Chip8::new();
+        a.load_test_program(&program);
+        b.load_test_program(&program);
+
+        for _ in 0..3 {
+            a.cycle().unwrap();
+            b.cycle().unwrap();
+        }
+        b.opcode = 0; // Internal latch is not compared
+        b.stack[5] = 0x123; // Above sp, not part of the call stack
+        assert_eq!(a, b);
+
+        b.registers[0] = 1;
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_hotspots() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 1; JP 0x200
+        chip8.set_hotspot_threshold(3);
+
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert!(chip8.get_hotspots().is_empty()); // Each address ran twice
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_hotspots().iter().copied().collect::<Vec<_>>(), vec![0x200]);
+        assert_eq!(chip8.get_hotspot_counts()[&0x202], 2);
+    }
+
+    #[test]
+    fn test_breakpoint_stops_before_executing() {
+        let mut chip8 = Chip8::new();
+        // LD V0, 1; LD V1, 2; LD V2, 3; LD V3, 4
+        chip8.load_test_program(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x63, 0x04]);
+        chip8.add_breakpoint(0x204);
+
+        chip8.run_cycles(5);
+        assert_eq!(chip8.pc, 0x204);
+        assert_eq!(chip8.registers[..4], [1, 2, 0, 0]);
+        assert_eq!(chip8.get_cycle_count(), 2);
+
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x204));
+        assert_eq!(chip8.take_breakpoint_hit(), None);
+
+        // Resuming runs the instruction at the breakpoint
+        chip8.run_cycles(2);
+        assert_eq!(chip8.registers[..4], [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_breakpoint_hits_again_on_the_next_visit() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 1; JP 0x200
+        chip8.add_breakpoint(0x200);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x200));
+        chip8.run_cycles(3); // ADD, JP, then the breakpoint again
+        assert_eq!(chip8.registers[0], 1);
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x200));
+
+        chip8.remove_breakpoint(0x200);
+        chip8.run_cycles(4);
+        assert_eq!(chip8.registers[0], 3);
+        assert_eq!(chip8.take_breakpoint_hit(), None);
+    }
+
+    #[test]
+    fn test_step_over_runs_the_whole_subroutine() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x60, 0x01, 0x23, 0x00, 0x61, 0x03]); // LD V0, 1; CALL 0x300; LD V1, 3
+        chip8.memory[0x300..0x306].copy_from_slice(&[0x70, 0x01, 0x70, 0x01, 0x00, 0xEE]); // ADD V0, 1 x2; RET
+
+        chip8.step_over().unwrap(); // Not a CALL: one instruction
+        assert_eq!((chip8.pc, chip8.registers[0]), (0x202, 1));
+
+        chip8.step_over().unwrap();
+        assert_eq!(chip8.pc, 0x204);
+        assert_eq!(chip8.registers[..2], [3, 0]);
+        assert_eq!(chip8.get_cycle_count(), 5);
+        assert_eq!(chip8.take_breakpoint_hit(), None);
+        assert!(chip8.breakpoints.is_empty());
+
+        // A breakpoint inside the subroutine still stops there
+        chip8.reset();
+        chip8.add_breakpoint(0x302);
+        chip8.run_cycles(1);
+        chip8.step_over().unwrap();
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x302));
+        assert_eq!(chip8.breakpoints, HashSet::from([0x302]));
+    }
+
+    #[test]
+    fn test_trace_lists_executed_instructions() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let mut chip8 = Chip8::new();
+        // LD VA, 0x55; LD I, 0x300; ADD VA, VA; JP 0x206
+        chip8.load_test_program(&[0x6A, 0x55, 0xA3, 0x00, 0x8A, 0xA4, 0x12, 0x06]);
+        chip8.enable_trace(path).unwrap();
+
+        chip8.run_cycles(4);
+        drop(chip8); // Flushes
+
+        let trace = fs::read_to_string(path).unwrap();
+        assert_eq!(trace.lines().collect::<Vec<_>>(), [
+            "0x0200: 6A55 LD VA, 0x55  [VA=0x55]",
+            "0x0202: A300 LD I, 0x300  [I=0x300]",
+            "0x0204: 8AA4 ADD VA, VA  [VA=0xAA]",
+            "0x0206: 1206 JP 0x206",
+        ]);
     }
 
-    // Fx0A - LD Vx, K, Wait for a key press, store the value of the key in Vx.
-    fn op_fx0a(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-
-        for (i, &key_pressed) in self.keypad.iter().enumerate() {
-            if key_pressed {
-                self.registers[vx] = i as u8;
-                self.debug_print(&format!("LD V{:X}, K (key {})", vx, i));
-                return;
-            }
+    #[test]
+    fn test_step_back() {
+        let mut chip8 = with_quirks(QuirksConfig::from_flags(0));
+        // LD V0, 1; LD V1, 2; LD I, 0x300; LD [I], V1; DRW V0, V0, 1
+        chip8.load_test_program(&[0x60, 0x01, 0x61, 0x02, 0xA3, 0x00, 0xF1, 0x55, 0xD0, 0x01]);
+        chip8.set_rewind_depth(8);
+
+        let mut history = Vec::new();
+        for _ in 0..5 {
+            history.push((chip8.pc, chip8.registers, chip8.index));
+            chip8.cycle().unwrap();
         }
+        assert_eq!(chip8.memory[0x300..0x302], [1, 2]);
+        assert!(chip8.video.contains(&0xFFFFFFFF));
 
-        self.pc -= 2;
-        self.debug_print(&format!("LD V{:X}, K (waiting)", vx));
+        for _ in 0..3 {
+            assert!(chip8.step_back());
+        }
+        assert_eq!((chip8.pc, chip8.registers, chip8.index), history[2]);
+        assert_eq!(chip8.memory[0x300..0x302], [0, 0]);
+        assert!(chip8.video.iter().all(|&p| p == 0));
+        assert_eq!(chip8.get_cycle_count(), 2);
+
+        // Running forward again redoes the same work
+        chip8.run_cycles(3);
+        assert_eq!(chip8.memory[0x300..0x302], [1, 2]);
     }
 
-    // Fx15 - LD DT, Vx, Set delay timer = Vx.
-    fn op_fx15(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.delay_timer = self.registers[vx];
-        self.debug_print(&format!("LD DT, V{:X}", vx));
+    #[test]
+    fn test_step_back_keeps_only_rewind_depth() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 1; JP 0x200
+        assert!(!chip8.step_back()); // Off by default
+
+        chip8.set_rewind_depth(2);
+        chip8.run_cycles(6);
+        assert!(chip8.step_back());
+        assert!(chip8.step_back());
+        assert!(!chip8.step_back());
+        assert_eq!((chip8.pc, chip8.registers[0]), (0x200, 2));
     }
 
-    // Fx18 - LD ST, Vx, Set sound timer = Vx.
-    fn op_fx18(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.sound_timer = self.registers[vx];
-        self.debug_print(&format!("LD ST, V{:X}", vx));
-    }
+    #[test]
+    fn test_hotspots_disabled_by_default() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x12, 0x00]); // JP 0x200
 
-    // Fx1E - ADD I, Vx, Set I = I + Vx.
-    fn op_fx1e(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.index += self.registers[vx] as u16;
-        self.debug_print(&format!("ADD I, V{:X}", vx));
+        for _ in 0..10 {
+            chip8.cycle().unwrap();
+        }
+        assert!(chip8.get_hotspot_counts().is_empty());
     }
 
-    // Fx29 - LD F, Vx, Set I = location of sprite for digit Vx.
-    fn op_fx29(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let digit = self.registers[vx] as u16;
+    #[test]
+    fn test_load_fontset_from_bytes() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.get_fontset(), &FONTSET);
 
-        self.index = FONTSET_START_ADDRESS + (5 * digit);
-        self.debug_print(&format!("LD F, V{:X} (digit {}, addr 0x{:03X})", vx, digit, self.index));
+        let custom = [0xFF; FONTSET_SIZE];
+        chip8.load_fontset_from_bytes(&custom).unwrap();
+        assert_eq!(chip8.get_fontset(), &custom);
+
+        // LD V0, 0x00; LD F, V0; DRW V0, V0, 5 draws the custom glyph from the atlas
+        chip8.load_test_program(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05]);
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert!(chip8.video[..8].iter().all(|&pixel| pixel == 0xFFFFFFFF));
     }
 
-    // Fx33 - LD B, Vx, Store BCD representation of Vx in memory locations I, I+1, and I+2.
-    fn op_fx33(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
-        let mut value = self.registers[vx];
+    #[test]
+    fn test_font_atlas_matches_fontset() {
+        let chip8 = Chip8::new();
 
-        self.memory[(self.index + 2) as usize] = value % 10;
-        value /= 10;
+        // Top row of '0' is 0xF0
+        assert_eq!(&chip8.font_atlas[0][0..8], &[0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF, 0, 0, 0, 0]);
+        // Second row of '1' is 0x60
+        assert_eq!(&chip8.font_atlas[1][8..16], &[0, 0xFFFFFFFF, 0xFFFFFFFF, 0, 0, 0, 0, 0]);
+    }
 
-        self.memory[(self.index + 1) as usize] = value % 10;
-        value /= 10;
+    #[test]
+    fn test_op_dxyn_font_glyph_matches_memory_draw() {
+        let mut atlas = Chip8::new();
+        let mut memory = Chip8::new();
+        memory.font_atlas_valid = false; // Force the per-row memory path
+
+        for chip8 in [&mut atlas, &mut memory] {
+            chip8.registers[0] = 0xA;
+            chip8.opcode = 0xF029; // LD F, V0
+            chip8.op_fx29();
+            chip8.registers[1] = 62; // Partly off the right edge
+            chip8.registers[2] = 3;
+            chip8.opcode = 0xD125; // DRW V1, V2, 5
+            chip8.op_dxyn();
+            chip8.tick_timers(); // Next frame, so display_wait lets the second draw through
+            chip8.op_dxyn(); // Second draw erases and collides
+        }
 
-        self.memory[self.index as usize] = value % 10;
-        self.debug_print(&format!("LD B, V{:X}", vx));
+        assert_eq!(atlas.video, memory.video);
+        assert_eq!(atlas.registers[0xF], 1);
+        assert_eq!(memory.registers[0xF], 1);
     }
 
-    // Fx55 - LD [I], Vx: Store registers V0 through Vx in memory starting at location I
-    fn op_fx55(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+    #[test]
+    fn test_font_atlas_invalidated_by_fontset_write() {
+        let mut chip8 = Chip8::new();
+        chip8.index = FONTSET_START_ADDRESS + 4;
+        chip8.opcode = 0xF055; // LD [I], V0
 
-        for i in 0..=vx {
-            self.memory[(self.index + i as u16) as usize] = self.registers[i];
-        }
-        self.debug_print(&format!("LD [I], V{:X}", vx));
+        chip8.op_fx55();
+
+        assert!(!chip8.font_atlas_valid);
+        chip8.index = FONTSET_START_ADDRESS;
+        assert_eq!(chip8.font_glyph_at_index(5), None);
     }
 
-    // Fx65 - LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
-    fn op_fx65(&mut self) {
-        let vx = ((self.opcode & 0x0F00) >> 8) as usize;
+    #[test]
+    fn test_memory_access_stats() {
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(QuirksConfig { load_store_increments_i: false, ..QuirksConfig::default() });
+        // LD I, 0x300; LD [I], V1; LD [I], V0; LD V1, [I]
+        chip8.load_rom_bytes(&[0xA3, 0x00, 0xF1, 0x55, 0xF0, 0x55, 0xF1, 0x65], true).unwrap();
+        assert_eq!(chip8.total_memory_writes(), 8);
 
-        for i in 0..=vx {
-            self.registers[i] = self.memory[(self.index + i as u16) as usize];
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
         }
-        self.debug_print(&format!("LD V{:X}, [I]", vx));
-    }
 
-    // Getter methods for testing
-    pub fn get_pc(&self) -> u16 {
-        self.pc
+        let stats = chip8.memory_access_stats();
+        assert_eq!(stats.writes, 8 + 2 + 1);
+        assert_eq!(stats.reads, 4 * 2 + 2); // Opcode fetches plus the Fx65 load
+        assert_eq!(stats.hottest_addr, 0x300); // Written by both stores
     }
-    pub fn get_register(&self, index: usize) -> u8 {
-        self.registers[index]
+
+    #[test]
+    fn test_get_stack_trace() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00]); // CALL 0x204; CALL 0x208
+        assert!(chip8.get_stack_trace().is_empty());
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        assert_eq!(
+            chip8.get_stack_trace(),
+            vec![
+                StackFrame { return_addr: 0x202, depth: 0 },
+                StackFrame { return_addr: 0x206, depth: 1 },
+            ]
+        );
     }
-    pub fn get_index(&self) -> u16 {
-        self.index
+
+    #[test]
+    fn test_assert_invariants_fresh_machine() {
+        let chip8 = Chip8::new();
+        chip8.assert_invariants();
     }
-    pub fn get_sp(&self) -> u8 {
-        self.sp
+
+    #[test]
+    #[should_panic(expected = "stack pointer")]
+    fn test_assert_invariants_stack_pointer() {
+        let mut chip8 = Chip8::new();
+        chip8.sp = STACK_SIZE as u8 + 1;
+        chip8.assert_invariants();
     }
-    pub fn get_stack(&self, index: usize) -> u16 {
-        self.stack[index]
+
+    #[test]
+    #[should_panic(expected = "stray value")]
+    fn test_assert_invariants_stray_pixel() {
+        let mut chip8 = Chip8::new();
+        chip8.video[70] = 0x1234;
+        chip8.assert_invariants();
     }
-    pub fn get_delay_timer(&self) -> u8 {
-        self.delay_timer
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "stack pointer")]
+    fn test_assert_invariants_checked_by_cycle() {
+        let mut chip8 = Chip8::new();
+        chip8.sp = STACK_SIZE as u8 + 1;
+        chip8.cycle().unwrap();
     }
-    pub fn get_sound_timer(&self) -> u8 {
-        self.sound_timer
+
+    // ASSEMBLER TESTS
+
+    #[test]
+    fn test_disassemble_opcode() {
+        assert_eq!(Chip8::disassemble_opcode(0x6342), "LD V3, 0x42");
+        assert_eq!(Chip8::disassemble_opcode(0xD015), "DRW V0, V1, 5");
+        assert_eq!(Chip8::disassemble_opcode(0x00E0), "CLS");
+        assert_eq!(Chip8::disassemble_opcode(0x8AB4), "ADD VA, VB");
+        assert_eq!(Chip8::disassemble_opcode(0xB2F0), "JP V0, 0x2F0");
+        assert_eq!(Chip8::disassemble_opcode(0x00FF), "HIGH");
+        assert_eq!(Chip8::disassemble_opcode(0x00C3), "SCD 3");
+        assert_eq!(Chip8::disassemble_opcode(0xF765), "LD V7, [I]");
+        assert_eq!(Chip8::disassemble_opcode(0x823E), "SHL V2"); // Vy is ignored
+        assert_eq!(Chip8::disassemble_opcode(0x5121), "DW 0x5121");
+        assert_eq!(Chip8::disassemble_opcode(0x5242), "LD [I], V2-V4");
+        assert_eq!(Chip8::disassemble_opcode(0xF201), "PLANE 2");
+        assert_eq!(Chip8::disassemble_opcode(0xFFFF), "DW 0xFFFF");
     }
-    pub fn load_test_program(&mut self, program: &[u8]) {
-        let start = START_ADDRESS as usize;
-        for (i, &byte) in program.iter().enumerate() {
-            if start + i < MEMORY_SIZE {
-                self.memory[start + i] = byte;
-            }
+
+    #[test]
+    fn test_disassemble_round_trips_encode() {
+        for &(form, _, _, slots) in INSTRUCTION_FORMS {
+            let operands: Vec<u16> = slots.iter().map(|slot| slot.max_and_shift().0).collect();
+            let opcode = Chip8::encode_opcode(form, &operands).unwrap();
+            let text = Chip8::disassemble_opcode(opcode);
+            assert!(!text.starts_with("DW"), "{} disassembled as {}", form, text);
         }
-        self.debug_print(&format!("Loaded test program: {} bytes", program.len()));
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
 
     #[test]
-    fn test_chip8_initialization() {
-        let chip8 = Chip8::new();
-
-        assert_eq!(chip8.pc, START_ADDRESS);
-        assert_eq!(chip8.sp, 0);
-        assert_eq!(chip8.index, 0);
+    fn test_load_rom_partial() {
+        let mut chip8 = Chip8::new();
+        let rom = vec![0xAB; MEMORY_SIZE];
 
-        for &reg in chip8.registers.iter() {
-            assert_eq!(reg, 0);
-        }
+        let (loaded, warning) = chip8.load_rom_partial(&rom);
 
-        for &pixel in chip8.video.iter() {
-            assert_eq!(pixel, 0);
-        }
+        assert_eq!(loaded, MEMORY_SIZE - 0x200);
+        assert!(matches!(
+            warning,
+            Some(Chip8Error::PartialRom { loaded: 0xFE00, expected: MEMORY_SIZE })
+        ));
+        assert_eq!(chip8.memory[MEMORY_SIZE - 1], 0xAB);
+        assert!(matches!(chip8.load_rom_partial(&[0x12, 0x00]), (2, None)));
     }
 
     #[test]
-    fn test_fontset_loaded() {
-        let chip8 = Chip8::new();
-        let start = FONTSET_START_ADDRESS as usize;
+    fn test_load_rom_missing_file() {
+        let mut chip8 = Chip8::new();
 
-        for (i, &expected) in FONTSET.iter().enumerate() {
-            assert_eq!(chip8.memory[start + i], expected)
-        }
+        let err = chip8.load_rom("/nonexistent/rom.ch8").unwrap_err();
+
+        assert!(matches!(&err, Chip8Error::RomNotFound(path) if path == "/nonexistent/rom.ch8"));
+        assert!(err.to_string().contains("ROM not found: /nonexistent/rom.ch8"));
     }
 
     #[test]
-    fn test_rom_loading() {
-        let dummy_rom = vec![0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08];
+    fn test_load_rom_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = Chip8::new().load_rom(dir.path().to_str().unwrap()).unwrap_err();
+        assert!(matches!(err, Chip8Error::RomIsDirectory(_)));
+        assert!(err.to_string().ends_with("is a directory, not a ROM file"));
+    }
 
-        use std::io::Write;
+    #[test]
+    fn test_load_rom_too_large() {
         let mut temp_file = tempfile::NamedTempFile::new().unwrap();
-        temp_file.write_all(&dummy_rom).unwrap();
+        temp_file.write_all(&vec![0; MEMORY_SIZE - 0x200 + 1]).unwrap();
+        let path = temp_file.path().to_str().unwrap();
 
-        let mut chip8 = Chip8::new();
-        chip8.load_rom(temp_file.path().to_str().unwrap()).unwrap();
+        let err = Chip8::new().load_rom(path).unwrap_err();
 
-        let start = START_ADDRESS as usize;
-        for (i, &expected) in dummy_rom.iter().enumerate() {
-            assert_eq!(chip8.memory[start + i], expected);
-        }
+        assert!(matches!(err, Chip8Error::RomTooLarge { size: 65025, max: 65024, .. }));
+        assert!(err.to_string().contains(&format!("ROM {} is 65025 bytes, but only 65024 fit", path)));
     }
 
     #[test]
-    fn test_random_byte_generation() {
+    fn test_load_rom_at() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(&[0x60, 0x42, 0x16, 0x00]).unwrap();
+        let path = temp_file.path().to_str().unwrap();
         let mut chip8 = Chip8::new();
 
-        let mut values = Vec::new();
-        for _ in 0..10 {
-            values.push(chip8.random_byte());
-        }
+        chip8.load_rom_at(path, 0x600).unwrap();
 
-        let first = values[0];
-        let all_same = values.iter().all(|&x| x == first);
-        assert!(!all_same, "Random generator produced all identical values");
+        assert_eq!(chip8.memory[0x600..0x604], [0x60, 0x42, 0x16, 0x00]);
+        assert!(chip8.memory[0x200..0x600].iter().all(|&b| b == 0));
+        assert_eq!(chip8.pc, 0x600);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[0], 0x42);
+        chip8.reset();
+        assert_eq!(chip8.pc, 0x600);
+
+        // Four bytes don't fit in the last three
+        let err = chip8.load_rom_at(path, 0xFFFD).unwrap_err();
+        assert!(matches!(err, Chip8Error::RomTooLarge { size: 4, max: 3, addr: 0xFFFD, .. }));
     }
 
-    // OPCODE TESTS
+    #[test]
+    fn test_load_rom_with_patch() {
+        let dir = tempfile::tempdir().unwrap();
+        let rom_path = dir.path().join("game.ch8");
+        let patch_path = dir.path().join("fix.ips");
+        fs::write(&rom_path, [0x00, 0xE0, 0x12, 0x00]).unwrap();
+        fs::write(&patch_path, b"PATCH\x00\x00\x03\x00\x01\x02EOF").unwrap();
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_with_patch(&rom_path, &patch_path).unwrap();
+
+        assert_eq!(&chip8.memory[0x200..0x204], &[0x00, 0xE0, 0x12, 0x02]);
+        assert!(chip8.load_rom_with_patch(&rom_path, &rom_path).is_err());
+    }
 
     #[test]
-    fn test_op_00e0_cls() {
+    fn test_load_rom_bytes_strict() {
         let mut chip8 = Chip8::new();
-        chip8.video[0] = 0xFFFFFFFF;
-        chip8.video[100] = 0xFFFFFFFF;
+        let rom = vec![0xAB; MEMORY_SIZE];
 
-        chip8.op_00e0();
+        let err = chip8.load_rom_bytes(&rom, true).unwrap_err();
+        assert!(matches!(err, Chip8Error::PartialRom { .. }));
+        assert_eq!(chip8.memory[0x200], 0);
 
-        for &pixel in chip8.video.iter() {
-            assert_eq!(pixel, 0);
-        }
+        chip8.load_rom_bytes(&rom, false).unwrap();
+        assert_eq!(chip8.memory[0x200], 0xAB);
     }
 
     #[test]
-    fn test_op_00ee_ret() {
+    fn test_display_as_ansi() {
         let mut chip8 = Chip8::new();
-        chip8.stack[0] = 0x300;
-        chip8.sp = 1;
+        chip8.video[1] = 0xFFFFFFFF; // Top row only
 
-        chip8.op_00ee();
+        let ansi = chip8.display_as_ansi(AnsiColor::Green, AnsiColor::Black);
+        let lines: Vec<&str> = ansi.lines().collect();
 
-        assert_eq!(chip8.pc, 0x300);
-        assert_eq!(chip8.sp, 0);
+        assert_eq!(lines.len(), VIDEO_HEIGHT / 2);
+        assert!(lines[0].starts_with("\x1b[30;40m▀\x1b[32;40m▀\x1b[30;40m▀▀"));
+        assert!(lines[0].ends_with("\x1b[0m"));
+        assert_eq!(lines[1], format!("\x1b[30;40m{}\x1b[0m", "▀".repeat(VIDEO_WIDTH)));
     }
 
     #[test]
-    fn test_op_1nnn_jump() {
+    fn test_opcode_stats() {
         let mut chip8 = Chip8::new();
-        chip8.opcode = 0x1234;
+        chip8.load_test_program(&[
+            0x60, 0x01, // 200: LD V0, 1
+            0x61, 0x02, // 202: LD V1, 2
+            0x80, 0x14, // 204: ADD V0, V1
+            0x80, 0x14, // 206: ADD V0, V1
+            0x80, 0x15, // 208: SUB V0, V1
+            0xF0, 0x29, // 20A: LD F, V0
+            0xF0, 0x1E, // 20C: ADD I, V0
+            0x12, 0x00, // 20E: JP 0x200
+        ]);
+
+        chip8.step_n(10); // Once through, then the two LDs again
+
+        let stats = chip8.opcode_stats();
+        assert_eq!(stats.by_nibble.iter().sum::<u64>(), 10);
+        assert_eq!((stats.by_nibble[0x6], stats.by_nibble[0x8], stats.by_nibble[0xF]), (4, 3, 2));
+        assert_eq!(stats.by_nibble[0x1], 1);
+        assert_eq!((stats.alu[0x4], stats.alu[0x5]), (2, 1));
+        assert_eq!((stats.misc[0x29], stats.misc[0x1E]), (1, 1));
+
+        let labels = ["6xkk", "8xy4", "1nnn", "8xy5", "Fx1E", "Fx29"];
+        let counts = [4, 2, 1, 1, 1, 1];
+        let expected: Vec<(String, u64)> = labels.iter().map(|l| l.to_string()).zip(counts).collect();
+        assert_eq!(stats.breakdown(), expected);
+
+        chip8.reset();
+        assert_eq!(chip8.opcode_stats(), OpcodeStats::default());
+    }
 
-        chip8.op_1nnn();
+    #[test]
+    fn test_display_ascii() {
+        let mut chip8 = Chip8::new();
+        chip8.execute_opcode(0x00E0);
+        chip8.registers[0] = 2;
+        chip8.registers[1] = 3;
+        chip8.index = FONTSET_START_ADDRESS + 5; // '1': 20 60 20 20 70
+        chip8.execute_opcode(0xD015);
+
+        let ascii = chip8.display_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+
+        assert_eq!(lines.len(), VIDEO_HEIGHT);
+        assert!(lines.iter().all(|line| line.chars().count() == VIDEO_WIDTH));
+        let glyph: Vec<String> = lines[3..8].iter().map(|line| line.chars().take(8).collect()).collect();
+        assert_eq!(glyph, ["    █   ", "   ██   ", "    █   ", "    █   ", "   ███  "]);
+        assert!(lines[..3].iter().chain(&lines[8..]).all(|line| line.trim().is_empty()));
+    }
+
+    #[test]
+    fn test_video_iter_rows() {
+        let mut chip8 = Chip8::new();
+        chip8.video[2 * VIDEO_WIDTH + 5] = 0xFFFFFFFF;
 
-        assert_eq!(chip8.pc, 0x234);
+        let rows: Vec<&[u32]> = chip8.video_iter_rows().collect();
+
+        assert_eq!(rows.len(), VIDEO_HEIGHT);
+        assert!(rows.iter().all(|row| row.len() == VIDEO_WIDTH));
+        assert_eq!(rows[2][5], 0xFFFFFFFF);
     }
 
     #[test]
-    fn test_op_2nnn_call() {
+    fn test_paste_at_pc_hex() {
         let mut chip8 = Chip8::new();
         chip8.pc = 0x300;
-        chip8.opcode = 0x2456;
 
-        chip8.op_2nnn();
+        assert_eq!(chip8.paste_at_pc("A2 2A\n600c").unwrap(), 4);
+        assert_eq!(&chip8.memory[0x300..0x304], &[0xA2, 0x2A, 0x60, 0x0C]);
+        assert_eq!(chip8.pc, 0x300);
+    }
 
-        assert_eq!(chip8.stack[0], 0x300);
-        assert_eq!(chip8.sp, 1);
-        assert_eq!(chip8.pc, 0x456);
+    #[test]
+    fn test_paste_at_pc_base64() {
+        let mut chip8 = Chip8::new();
+
+        // "oipgDA==" is base64 for A2 2A 60 0C
+        assert_eq!(chip8.paste_at_pc("oipgDA==").unwrap(), 4);
+        assert_eq!(&chip8.memory[0x200..0x204], &[0xA2, 0x2A, 0x60, 0x0C]);
     }
 
     #[test]
-    fn test_op_3xkk_skip_equal() {
+    fn test_paste_at_pc_errors() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0x33;
-        chip8.opcode = 0x3533; // SE V5, 0x33
-        chip8.pc = 0x200;
+        assert!(chip8.paste_at_pc("not a patch!").is_err());
+        assert!(chip8.paste_at_pc("  ").is_err());
 
-        chip8.op_3xkk();
+        chip8.pc = 0xFFFE;
+        assert!(chip8.paste_at_pc("112233").is_err());
+    }
 
-        assert_eq!(chip8.pc, 0x202); // Should skip
+    #[test]
+    fn test_get_register_name() {
+        assert_eq!(Chip8::get_register_name(0), "V0");
+        assert_eq!(Chip8::get_register_name(0xA), "VA");
+        assert_eq!(Chip8::get_register_name(0xF), "VF (flag)");
     }
 
     #[test]
-    fn test_op_3xkk_no_skip() {
+    fn test_delay_timer_ms() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0x22;
-        chip8.opcode = 0x3533; // SE V5, 0x33
-        chip8.pc = 0x200;
+        chip8.delay_timer = 60;
+        assert!((chip8.get_delay_timer_ms(60.0) - 1000.0).abs() < 1e-9);
+        assert_eq!(chip8.get_delay_timer_ms(50.0), 1200.0);
+
+        chip8.set_delay_timer_ms(250.0, 60.0);
+        assert_eq!(chip8.delay_timer, 15);
+        chip8.set_delay_timer_ms(10_000.0, 60.0);
+        assert_eq!(chip8.delay_timer, 255);
+        chip8.set_delay_timer_ms(-5.0, 60.0);
+        assert_eq!(chip8.delay_timer, 0);
+    }
 
-        chip8.op_3xkk();
+    #[test]
+    fn test_raw_pointers() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x63, 0x42]);
+        chip8.video[3] = 0xFFFFFFFF;
 
-        assert_eq!(chip8.pc, 0x200); // Should not skip
+        let memory = unsafe { std::slice::from_raw_parts(chip8.memory_as_ptr(), chip8.memory_len()) };
+        let video = unsafe { std::slice::from_raw_parts(chip8.video_as_ptr(), chip8.video_len()) };
+
+        assert_eq!(memory.len(), MEMORY_SIZE);
+        assert_eq!(&memory[0x200..0x202], &[0x63, 0x42]);
+        assert_eq!(video.len(), VIDEO_SIZE);
+        assert_eq!(video[3], 0xFFFFFFFF);
     }
 
     #[test]
-    fn test_op_6xkk_load() {
+    fn test_disassemble_fontset_as_data() {
+        let chip8 = Chip8::new();
+
+        let lines = chip8.disassemble(0x04E, 8);
+
+        assert_eq!(lines[0], (0x04E, "DW 0x0000".to_string())); // Code up to the fontset
+        assert_eq!(lines[1], (0x050, "DB 0xF0 ; FONT: '0' row 0".to_string()));
+        assert_eq!(lines[2], (0x051, "DB 0x90 ; FONT: '0' row 1".to_string()));
+        assert_eq!(lines[6], (0x055, "DB 0x20 ; FONT: '1' row 0".to_string()));
+
+        let last = chip8.disassemble(0x09F, 2);
+        assert_eq!(last[0], (0x09F, "DB 0x80 ; FONT: 'F' row 4".to_string()));
+        assert_eq!(last[1].0, 0x0A0);
+    }
+
+    #[test]
+    fn test_disassemble_program() {
         let mut chip8 = Chip8::new();
-        chip8.opcode = 0x6A55; // LD VA, 0x55
+        // LD V5, 0x33; LD I, 0x208; DRW V0, V1, 5; unknown word
+        chip8.load_test_program(&[0x65, 0x33, 0xA2, 0x08, 0xD0, 0x15, 0x51, 0x21]);
+
+        assert_eq!(chip8.disassemble(0x200, 4), vec![
+            (0x200, "LD V5, 0x33".to_string()),
+            (0x202, "LD I, 0x208".to_string()),
+            (0x204, "DRW V0, V1, 5".to_string()),
+            (0x206, "DW 0x5121".to_string()),
+        ]);
+        // Stops at the end of memory; the last odd byte can only be data
+        assert_eq!(chip8.disassemble(0xFFFD, 10).iter().map(|&(addr, _)| addr).collect::<Vec<_>>(),
+            vec![0xFFFD, 0xFFFF]);
+    }
 
-        chip8.op_6xkk();
+    #[test]
+    fn test_disassemble_marked_data_region() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x63, 0x42, 0x12, 0x04, 0xFF, 0x81]);
+        chip8.mark_data_region(0x204, 2);
+
+        assert_eq!(chip8.disassemble(0x200, 4), vec![
+            (0x200, "LD V3, 0x42".to_string()),
+            (0x202, "JP 0x204".to_string()),
+            (0x204, "DB 0xFF".to_string()),
+            (0x205, "DB 0x81".to_string()),
+        ]);
+    }
 
-        assert_eq!(chip8.registers[0xA], 0x55);
+    #[test]
+    fn test_get_opcode_at() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x63, 0x42]);
+        chip8.memory[0xFFFE] = 0x12;
+        chip8.memory[0xFFFF] = 0x34;
+
+        assert_eq!(chip8.get_opcode_at(0x200), Some(0x6342));
+        assert_eq!(chip8.get_opcode_at(0xFFFE), Some(0x1234));
+        assert_eq!(chip8.get_opcode_at(0xFFFF), None);
+        assert_eq!(chip8.pc, 0x200);
+        assert_eq!(chip8.memory_access_stats().reads, 0);
     }
 
     #[test]
-    fn test_op_7xkk_add() {
+    fn test_current_opcode_str() {
         let mut chip8 = Chip8::new();
-        chip8.registers[3] = 0x10;
-        chip8.opcode = 0x7315; // ADD V3, 0x15
+        chip8.load_test_program(&[0x63, 0x42]);
 
-        chip8.op_7xkk();
+        chip8.cycle().unwrap();
 
-        assert_eq!(chip8.registers[3], 0x25);
+        assert_eq!(chip8.current_opcode_str(), "LD V3, 0x42");
     }
 
     #[test]
-    fn test_op_7xkk_add_overflow() {
+    fn test_execute_opcode() {
         let mut chip8 = Chip8::new();
-        chip8.registers[3] = 0xFF;
-        chip8.opcode = 0x7301; // ADD V3, 0x01
+        chip8.registers[2] = 0x10;
+        chip8.delay_timer = 5;
 
-        chip8.op_7xkk();
+        chip8.execute_opcode(0x7205); // ADD V2, 0x05
 
-        assert_eq!(chip8.registers[3], 0x00); // Should wrap around
+        assert_eq!(chip8.registers[2], 0x15);
+        assert_eq!(chip8.last_executed_opcode(), 0x7205);
+        assert_eq!(chip8.pc, START_ADDRESS); // Nothing fetched
+        assert_eq!(chip8.delay_timer, 5);
+        assert_eq!(chip8.get_cycle_count(), 0);
+
+        chip8.execute_opcode(0x3215); // SE V2, 0x15
+        assert_eq!(chip8.pc, START_ADDRESS + 2);
     }
 
     #[test]
-    fn test_op_annn_load_index() {
+    fn test_last_executed_opcode() {
         let mut chip8 = Chip8::new();
-        chip8.opcode = 0xA123; // LD I, 0x123
+        chip8.load_test_program(&[0x63, 0x42, 0x00, 0xE0]);
+        assert_eq!(chip8.last_executed_opcode(), 0);
 
-        chip8.op_annn();
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.last_executed_opcode(), 0x6342);
+        assert_eq!(chip8.get_opcode_at(chip8.get_pc()), Some(0x00E0));
+    }
+
+    #[test]
+    fn test_encode_opcode() {
+        assert_eq!(Chip8::encode_opcode("LD", &[5, 0x42]).unwrap(), 0x6542);
+        assert_eq!(Chip8::encode_opcode("DRW", &[2, 3, 4]).unwrap(), 0xD234);
+        assert_eq!(Chip8::encode_opcode("cls", &[]).unwrap(), 0x00E0);
+        assert_eq!(Chip8::encode_opcode("JP", &[0x345]).unwrap(), 0x1345);
+    }
 
-        assert_eq!(chip8.index, 0x123);
+    #[test]
+    fn test_encode_opcode_full_form() {
+        assert_eq!(Chip8::encode_opcode("LD Vx, Vy", &[1, 2]).unwrap(), 0x8120);
+        assert_eq!(Chip8::encode_opcode("LD I, addr", &[0x2EA]).unwrap(), 0xA2EA);
+        assert_eq!(Chip8::encode_opcode("JP V0, addr", &[0x300]).unwrap(), 0xB300);
+        assert_eq!(Chip8::encode_opcode("LD [I], Vx", &[7]).unwrap(), 0xF755);
     }
 
     #[test]
-    fn test_fetch_decode_execute() {
-        let mut chip8 = Chip8::new();
+    fn test_encode_opcode_errors() {
+        let err = Chip8::encode_opcode("LD", &[1, 2, 3]).unwrap_err();
+        assert!(err.to_string().contains("operand count"));
 
-        // Place a simple instruction in memory: 6A55 (LD VA, 0x55)
-        chip8.memory[0x200] = 0x6A;
-        chip8.memory[0x201] = 0x55;
+        let err = Chip8::encode_opcode("LD", &[16, 0x42]).unwrap_err();
+        assert!(err.to_string().contains("out of range"));
 
-        chip8.cycle();
+        let err = Chip8::encode_opcode("MOV", &[1, 2]).unwrap_err();
+        assert!(err.to_string().contains("Unknown mnemonic"));
+    }
 
-        assert_eq!(chip8.registers[0xA], 0x55);
-        assert_eq!(chip8.pc, 0x202); // PC should advance
+    // MESSAGE-PASSING TESTS
+
+    fn input(cycles_to_run: u32, timer_tick: bool) -> Input {
+        Input { keys: [false; KEY_COUNT], timer_tick, cycles_to_run }
     }
 
     #[test]
-    fn test_op_8xy1_or() {
+    fn test_plane2_only_draw_counts_as_display_change() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b11110000;
-        chip8.registers[3] = 0b00001111;
-        chip8.opcode = 0x8231; // OR V2, V3
+        // PLANE 2; LD I, 0x050 (glyph "0"); DRW V0, V0, 5
+        chip8.load_test_program(&[0xF2, 0x01, 0xA0, 0x50, 0xD0, 0x05]);
+        let hash_before = chip8.display_hash();
 
-        chip8.op_8xy1();
+        let outputs = chip8.process(input(3, false));
 
-        assert_eq!(chip8.registers[2], 0b11111111);
+        assert!(chip8.video.iter().all(|&p| p == 0));
+        assert!(matches!(&outputs[0], Output::DisplayUpdated(video) if video[0] == PLANE2_PIXEL));
+        assert_ne!(chip8.display_hash(), hash_before);
+        let json = chip8.save_state().to_json();
+        assert!(json.contains(&format!("\"video_hash\": \"{:016x}\"", chip8.display_hash())));
     }
 
     #[test]
-    fn test_op_8xy2_and() {
+    fn test_process_reports_display_and_completion() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b11110000;
-        chip8.registers[3] = 0b11001100;
-        chip8.opcode = 0x8232; // AND V2, V3
+        // LD I, 0x050 (glyph "0"); DRW V0, V0, 5
+        chip8.load_test_program(&[0xA0, 0x50, 0xD0, 0x05]);
 
-        chip8.op_8xy2();
+        let outputs = chip8.process(input(2, false));
 
-        assert_eq!(chip8.registers[2], 0b11000000);
+        assert_eq!(outputs.len(), 2);
+        assert!(matches!(&outputs[0], Output::DisplayUpdated(video) if video[0] == 0xFFFFFFFF));
+        assert_eq!(outputs[1], Output::CycleComplete);
     }
 
     #[test]
-    fn test_op_8xy3_xor() {
+    fn test_process_timers_only_tick_when_asked() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b11110000;
-        chip8.registers[3] = 0b11001100;
-        chip8.opcode = 0x8233; // XOR V2, V3
-
-        chip8.op_8xy3();
+        // LD V0, 0x02; LD ST, V0
+        chip8.load_test_program(&[0x60, 0x02, 0xF0, 0x18]);
+
+        let outputs = chip8.process(input(2, false));
+        assert_eq!(outputs, vec![Output::SoundToggle(true), Output::CycleComplete]);
+        assert_eq!(chip8.sound_timer, 2);
+
+        assert_eq!(chip8.process(input(0, true)), vec![Output::CycleComplete]);
+        assert_eq!(
+            chip8.process(input(0, true)),
+            vec![Output::SoundToggle(false), Output::CycleComplete]
+        );
+    }
 
-        assert_eq!(chip8.registers[2], 0b00111100);
+    #[test]
+    fn test_process_halts() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x12, 0x00]); // JP 0x200
+
+        assert_eq!(
+            chip8.process(input(10, false)),
+            vec![Output::Halted(HaltReason::InfiniteLoop(0x200))]
+        );
+
+        chip8.pc = 0xFFFF;
+        assert_eq!(
+            chip8.process(input(1, false)),
+            vec![Output::Halted(HaltReason::PcOutOfBounds(0xFFFF))]
+        );
     }
 
     #[test]
-    fn test_op_8xy4_add_no_carry() {
+    fn test_hires_mode_switch() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 100;
-        chip8.registers[3] = 50;
-        chip8.opcode = 0x8234; // ADD V2, V3
+        chip8.video[0] = 0xFFFFFFFF;
+        chip8.load_test_program(&[0x00, 0xFF, 0x00, 0xFE]); // HIGH; LOW
 
-        chip8.op_8xy4();
+        chip8.cycle().unwrap();
+        assert!(chip8.is_hires());
+        assert_eq!((chip8.video_width(), chip8.video_height()), (128, 64));
+        assert_eq!(chip8.get_display().len(), HIRES_VIDEO_SIZE);
+        assert!(chip8.get_display().iter().all(|&p| p == 0));
 
-        assert_eq!(chip8.registers[2], 150);
-        assert_eq!(chip8.registers[0xF], 0); // No carry
+        chip8.cycle().unwrap();
+        assert!(!chip8.is_hires());
+        assert_eq!(chip8.get_display().len(), VIDEO_SIZE);
     }
 
     #[test]
-    fn test_op_8xy4_add_with_carry() {
+    fn test_op_dxyn_hires() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 200;
-        chip8.registers[3] = 100;
-        chip8.opcode = 0x8234; // ADD V2, V3
-
-        chip8.op_8xy4();
-
-        assert_eq!(chip8.registers[2], 44); // 300 & 0xFF = 44
-        assert_eq!(chip8.registers[0xF], 1); // Carry set
+        chip8.execute_opcode(0x00FF);
+        chip8.registers[0] = 100;
+        chip8.registers[1] = 40;
+        chip8.index = FONTSET_START_ADDRESS; // '0', top row 0xF0
+
+        chip8.execute_opcode(0xD011);
+
+        // Past the low-res edges, and positions wrap at 128x64 rather than 64x32
+        assert!(chip8.video[40 * 128 + 100..40 * 128 + 104].iter().all(|&p| p == 0xFFFFFFFF));
+        chip8.registers[0] = 128 + 4;
+        chip8.tick_timers();
+        chip8.execute_opcode(0xD011);
+        assert_eq!(chip8.video[40 * 128 + 4], 0xFFFFFFFF);
     }
 
     #[test]
-    fn test_op_8xy5_sub_no_borrow() {
+    fn test_op_dxy0_hires_16x16() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 100;
-        chip8.registers[3] = 50;
-        chip8.opcode = 0x8235; // SUB V2, V3
+        chip8.execute_opcode(0x00FF);
+        chip8.index = 0x300;
+        chip8.memory[0x300] = 0x80; // Row 0: leftmost pixel
+        chip8.memory[0x31F] = 0x01; // Row 15: rightmost pixel
 
-        chip8.op_8xy5();
+        chip8.execute_opcode(0xD000);
 
-        assert_eq!(chip8.registers[2], 50);
-        assert_eq!(chip8.registers[0xF], 1); // No borrow (Vx > Vy)
+        assert_eq!(chip8.video[0], 0xFFFFFFFF);
+        assert_eq!(chip8.video[15 * 128 + 15], 0xFFFFFFFF);
+        assert_eq!(chip8.video.iter().filter(|&&p| p != 0).count(), 2);
+        assert_eq!(chip8.registers[0xF], 0);
     }
 
     #[test]
-    fn test_op_8xy5_sub_with_borrow() {
+    fn test_op_dxy0_hires_16x16_pattern_and_collision() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 50;
-        chip8.registers[3] = 100;
-        chip8.opcode = 0x8235; // SUB V2, V3
+        chip8.execute_opcode(0x00FF);
+        chip8.index = 0x300;
+        // A 16x16 box: solid top and bottom rows, both edges in between
+        for row in 0..16 {
+            let bits: u16 = if row == 0 || row == 15 { 0xFFFF } else { 0x8001 };
+            chip8.memory[0x300 + 2 * row..0x302 + 2 * row].copy_from_slice(&bits.to_be_bytes());
+        }
+        chip8.registers[0] = 20;
+        chip8.registers[1] = 10;
 
-        chip8.op_8xy5();
+        chip8.execute_opcode(0xD010);
 
-        assert_eq!(chip8.registers[2], 206); // 50 - 100 wraps to 206
-        assert_eq!(chip8.registers[0xF], 0); // Borrow occurred (Vx < Vy)
+        let lit = |chip8: &Chip8, x: usize, y: usize| chip8.video[(10 + y) * 128 + 20 + x] != 0;
+        assert!((0..16).all(|x| lit(&chip8, x, 0) && lit(&chip8, x, 15)));
+        assert!((1..15).all(|y| lit(&chip8, 0, y) && lit(&chip8, 15, y) && !lit(&chip8, 7, y)));
+        assert_eq!(chip8.video.iter().filter(|&&p| p != 0).count(), 60);
+        assert_eq!(chip8.registers[0xF], 0);
+
+        // Overlapping only the right edge still reports the collision
+        chip8.registers[0] = 35;
+        chip8.tick_timers();
+        chip8.execute_opcode(0xD010);
+        assert_eq!(chip8.registers[0xF], 1);
+        assert!(!lit(&chip8, 15, 0));
+        assert!(lit(&chip8, 16, 0));
     }
 
     #[test]
-    fn test_op_8xy6_shr() {
+    fn test_op_00cn_scroll_down() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b10101011;
-        chip8.opcode = 0x8206; // SHR V2
+        chip8.video[3] = 0xFFFFFFFF; // (3, 0)
+        chip8.video[30 * VIDEO_WIDTH] = 0xFFFFFFFF; // (0, 30), scrolled off the bottom
 
-        chip8.op_8xy6();
+        chip8.execute_opcode(0x00C2); // SCD 2
 
-        assert_eq!(chip8.registers[2], 0b01010101);
-        assert_eq!(chip8.registers[0xF], 1); // LSB was 1
+        assert_eq!(chip8.video[2 * VIDEO_WIDTH + 3], 0xFFFFFFFF);
+        assert_eq!(chip8.video.iter().filter(|&&p| p != 0).count(), 1);
+        assert!(chip8.display_dirty);
     }
 
     #[test]
-    fn test_op_8xy6_shr_lsb_zero() {
+    fn test_op_00fb_00fc_scroll_sideways() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b10101010;
-        chip8.opcode = 0x8206; // SHR V2
-
-        chip8.op_8xy6();
-
-        assert_eq!(chip8.registers[2], 0b01010101);
-        assert_eq!(chip8.registers[0xF], 0); // LSB was 0
+        chip8.execute_opcode(0x00FF);
+        chip8.video[5 * 128 + 10] = 0xFFFFFFFF;
+        chip8.video[6 * 128 + 126] = 0xFFFFFFFF; // Falls off the right edge
+
+        chip8.execute_opcode(0x00FB); // SCR
+        assert_eq!(chip8.video[5 * 128 + 14], 0xFFFFFFFF);
+        assert_eq!(chip8.video.iter().filter(|&&p| p != 0).count(), 1);
+
+        chip8.execute_opcode(0x00FC); // SCL
+        chip8.execute_opcode(0x00FC);
+        assert_eq!(chip8.video[5 * 128 + 6], 0xFFFFFFFF);
+        assert_eq!(chip8.video.iter().filter(|&&p| p != 0).count(), 1);
     }
 
     #[test]
-    fn test_op_8xy7_subn_no_borrow() {
+    fn test_op_00cn_hires_scrolls_hires_rows() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 50;
-        chip8.registers[3] = 100;
-        chip8.opcode = 0x8237; // SUBN V2, V3
+        chip8.execute_opcode(0x00FF);
+        chip8.video[0] = 0xFFFFFFFF;
 
-        chip8.op_8xy7();
+        chip8.execute_opcode(0x00C4); // SCD 4
 
-        assert_eq!(chip8.registers[2], 50); // 100 - 50
-        assert_eq!(chip8.registers[0xF], 1); // No borrow (Vy > Vx)
+        assert_eq!(chip8.video[4 * 128], 0xFFFFFFFF);
     }
 
     #[test]
-    fn test_op_8xy7_subn_with_borrow() {
+    fn test_op_00fd_exit() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 100;
-        chip8.registers[3] = 50;
-        chip8.opcode = 0x8237; // SUBN V2, V3
+        chip8.load_test_program(&[0x60, 0x01, 0x00, 0xFD, 0x60, 0x02]); // LD V0, 1; EXIT; LD V0, 2
 
-        chip8.op_8xy7();
+        chip8.cycle().unwrap();
+        assert!(!chip8.is_halted());
+        chip8.cycle().unwrap();
+        assert!(chip8.is_halted());
 
-        assert_eq!(chip8.registers[2], 206); // 50 - 100 wraps to 206
-        assert_eq!(chip8.registers[0xF], 0); // Borrow occurred (Vy < Vx)
+        chip8.cycle().unwrap(); // Does nothing once halted
+        assert_eq!(chip8.registers[0], 1);
+        assert_eq!(chip8.pc, 0x204);
+        assert_eq!(chip8.get_cycle_count(), 2);
     }
 
     #[test]
-    fn test_op_8xye_shl() {
+    fn test_process_reports_exit() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b10101011;
-        chip8.opcode = 0x820E; // SHL V2
+        chip8.load_test_program(&[0x00, 0xFD]);
 
-        chip8.op_8xye();
+        assert_eq!(chip8.process(input(5, false)), vec![Output::Halted(HaltReason::Exited(0x200))]);
+        assert_eq!(chip8.process(input(5, false)), vec![Output::Halted(HaltReason::Exited(0x200))]);
 
-        assert_eq!(chip8.registers[2], 0b01010110);
-        assert_eq!(chip8.registers[0xF], 1); // MSB was 1
+        let stats = chip8.step_n(3);
+        assert_eq!(stats.instructions_run, 0);
+        assert_eq!(stats.halt_reason, Some(HaltReason::Exited(0x200)));
     }
 
     #[test]
-    fn test_op_8xye_shl_msb_zero() {
+    fn test_op_00e0_hires() {
         let mut chip8 = Chip8::new();
-        chip8.registers[2] = 0b01010101;
-        chip8.opcode = 0x820E; // SHL V2
+        chip8.execute_opcode(0x00FF);
+        chip8.video[HIRES_VIDEO_SIZE - 1] = 0xFFFFFFFF;
 
-        chip8.op_8xye();
+        chip8.execute_opcode(0x00E0);
 
-        assert_eq!(chip8.registers[2], 0b10101010);
-        assert_eq!(chip8.registers[0xF], 0); // MSB was 0
+        assert!(chip8.is_hires());
+        assert!(chip8.video.iter().all(|&p| p == 0));
     }
 
-    // Tests for Exxx opcodes
-
     #[test]
-    fn test_op_ex9e_key_pressed() {
+    fn test_reset() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0xA;
-        chip8.keypad[0xA] = true;
-        chip8.opcode = 0xE59E; // SKP V5
-        chip8.pc = 0x200;
-
-        chip8.op_ex9e();
+        // LD V0, 0x05; LD F, V0; DRW V0, V0, 5; CALL 0x200
+        chip8.load_test_program(&[0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x00]);
+        chip8.step_n(4);
+        chip8.keypad[3] = true;
+        chip8.sound_timer = 9;
+
+        chip8.reset();
+
+        assert_eq!(chip8.registers, [0; REGISTER_COUNT]);
+        assert!(chip8.video.iter().all(|&p| p == 0));
+        assert_eq!((chip8.pc, chip8.index, chip8.sp, chip8.opcode), (START_ADDRESS, 0, 0, 0));
+        assert_eq!(chip8.stack, [0; STACK_SIZE]);
+        assert!(!chip8.keypad[3]);
+        assert_eq!(chip8.sound_timer, 0);
+        assert_eq!(chip8.get_fontset(), &FONTSET);
+        assert_eq!(&chip8.memory[0x200..0x202], &[0x60, 0x05]); // ROM left in place
+    }
 
-        assert_eq!(chip8.pc, 0x202); // Should skip
+    #[test]
+    fn test_reset_clears_profiling() {
+        let mut chip8 = Chip8::new();
+        let power_on = chip8.memory_access_stats();
+        chip8.set_hotspot_threshold(1);
+        // LD V0, 0x05; LD [I], V0; JP 0x200
+        chip8.load_test_program(&[0x60, 0x05, 0xF0, 0x55, 0x12, 0x00]);
+        chip8.step_n(6);
+        assert!(!chip8.get_hotspot_counts().is_empty());
+
+        chip8.reset();
+
+        assert!(chip8.get_hotspot_counts().is_empty());
+        assert!(chip8.hotspots.is_empty());
+        assert_eq!(chip8.memory_access_stats(), power_on);
+        assert_eq!(chip8.video.len(), VIDEO_SIZE);
     }
 
     #[test]
-    fn test_op_ex9e_key_not_pressed() {
+    fn test_reset_and_reload() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0xA;
-        chip8.keypad[0xA] = false;
-        chip8.opcode = 0xE59E; // SKP V5
-        chip8.pc = 0x200;
+        chip8.load_test_program(&[0x60, 0x05]);
+        chip8.memory[0xFFF] = 0xAA;
+        chip8.cycle().unwrap();
 
-        chip8.op_ex9e();
+        chip8.reset_and_reload();
 
-        assert_eq!(chip8.pc, 0x200); // Should not skip
+        assert_eq!(chip8.registers[0], 0);
+        assert!(chip8.memory[START_ADDRESS as usize..].iter().all(|&b| b == 0));
+        assert_eq!(chip8.get_fontset(), &FONTSET);
     }
 
     #[test]
-    fn test_op_exa1_key_not_pressed() {
+    fn test_is_beeping() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0xA;
-        chip8.keypad[0xA] = false;
-        chip8.opcode = 0xE5A1; // SKNP V5
-        chip8.pc = 0x200;
+        assert!(!chip8.is_beeping());
 
-        chip8.op_exa1();
+        chip8.sound_timer = 1;
+        assert!(chip8.is_beeping());
 
-        assert_eq!(chip8.pc, 0x202); // Should skip
+        chip8.tick_timers();
+        assert!(!chip8.is_beeping());
     }
 
     #[test]
-    fn test_op_exa1_key_pressed() {
+    fn test_process_sets_keys() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0xA;
-        chip8.keypad[0xA] = true;
-        chip8.opcode = 0xE5A1; // SKNP V5
-        chip8.pc = 0x200;
+        let mut keys = [false; KEY_COUNT];
+        keys[5] = true;
 
-        chip8.op_exa1();
+        chip8.process(Input { keys, timer_tick: false, cycles_to_run: 0 });
 
-        assert_eq!(chip8.pc, 0x200); // Should not skip
+        assert!(chip8.keypad[5]);
     }
 
-    // Tests for Fxxx opcodes
-
     #[test]
-    fn test_op_fx07_load_delay_timer() {
+    fn test_export_state_as_c_header() {
         let mut chip8 = Chip8::new();
-        chip8.delay_timer = 0x42;
-        chip8.opcode = 0xF507; // LD V5, DT
+        chip8.registers[0] = 0xAB;
+        chip8.index = 0x2EA;
+
+        let header = chip8.export_state_as_c_header();
+
+        assert!(header.contains("const uint16_t PC = 0x200;"));
+        assert!(header.contains("const uint16_t I = 0x2EA;"));
+        assert!(header.contains("const uint8_t REGISTERS[16] = {\n    0xAB, 0x00,"));
+        assert!(header.contains("const uint8_t MEMORY[65536] = {"));
+        // Fontset starts at 0x050: row 5 of the memory dump
+        assert!(header.contains("    0xF0, 0x90, 0x90, 0x90, 0xF0,"));
+        assert!(header.ends_with("};\n"));
+    }
 
-        chip8.op_fx07();
+    // STATE HASH TESTS
 
-        assert_eq!(chip8.registers[5], 0x42);
+    fn hash_of(state: &Chip8State) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        state.hash(&mut hasher);
+        hasher.finish()
     }
 
     #[test]
-    fn test_op_fx0a_key_pressed() {
+    fn test_load_state_round_trip() {
         let mut chip8 = Chip8::new();
+        // LD V0, 0x05; LD F, V0; DRW V0, V0, 5; CALL 0x300
+        chip8.load_test_program(&[0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x23, 0x00]);
+        chip8.step_n(4);
         chip8.keypad[7] = true;
-        chip8.opcode = 0xF50A; // LD V5, K
-        chip8.pc = 0x200;
-
-        chip8.op_fx0a();
-
-        assert_eq!(chip8.registers[5], 7);
-        assert_eq!(chip8.pc, 0x200); // PC should not change when key found
+        let saved = chip8.save_state();
+        let mut expected = Chip8::new();
+        expected.load_state(&saved);
+
+        chip8.registers[3] = 0x99;
+        chip8.execute_opcode(0x00E0);
+        chip8.execute_opcode(0x00EE);
+        chip8.execute_opcode(0x00FF);
+        chip8.load_state(&saved);
+
+        assert_eq!(chip8.save_state(), saved);
+        assert_eq!(chip8, expected);
+        // Same snapshot, same random numbers
+        assert_eq!(chip8.random_byte(), expected.random_byte());
+        // Seeded from the state's FNV-1a hash, so it holds across builds too
+        let mut seeded = Chip8::new().with_seed(saved.fnv_hash());
+        chip8.load_state(&saved);
+        assert_eq!(chip8.random_byte(), seeded.random_byte());
     }
 
     #[test]
-    fn test_op_fx0a_no_key_pressed() {
+    fn test_load_state_short_memory() {
         let mut chip8 = Chip8::new();
-        // All keys are false by default
-        chip8.opcode = 0xF50A; // LD V5, K
-        chip8.pc = 0x200;
-
-        chip8.op_fx0a();
-
-        assert_eq!(chip8.pc, 0x1FE); // PC should decrement by 2 (repeat instruction)
+        chip8.memory[0x1800] = 0xAA;
+        let mut state = chip8.save_state();
+        state.memory.truncate(0x1000);
+        state.memory[0x200] = 0x12;
+
+        chip8.load_state(&state);
+        assert_eq!(chip8.memory[0x200], 0x12);
+        assert_eq!(chip8.memory[0x1800], 0);
+
+        state.memory.resize(MEMORY_SIZE + 16, 0xFF);
+        chip8.load_state(&state);
+        assert_eq!(chip8.memory.len(), MEMORY_SIZE);
     }
 
     #[test]
-    fn test_op_fx15_set_delay_timer() {
+    fn test_state_bytes_round_trip() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0x42;
-        chip8.opcode = 0xF515; // LD DT, V5
+        chip8.registers[0xF] = 1;
+        chip8.stack[3] = 0xABC;
+        chip8.sp = 4;
+        chip8.keypad[0xE] = true;
+        chip8.execute_opcode(0x00FF);
+        chip8.video[HIRES_VIDEO_SIZE - 1] = 0xFFFFFFFF;
+        let state = chip8.save_state();
+
+        assert_eq!(Chip8State::parse(&state.to_bytes()).unwrap(), state);
+    }
 
-        chip8.op_fx15();
+    #[test]
+    fn test_state_round_trips_xo_chip_planes() {
+        let mut chip8 = Chip8::new();
+        chip8.execute_opcode(0xF201); // Select plane 2 only
+        chip8.video_plane2[5] = 0xFFFFFFFF;
+        let state = chip8.save_state();
+        let parsed = Chip8State::parse(&state.to_bytes()).unwrap();
+        assert_eq!(parsed, state);
+
+        let mut restored = Chip8::new();
+        restored.load_state(&parsed);
+        assert_eq!(restored.plane_mask, 2);
+        assert_eq!(restored.video_plane2[5], 0xFFFFFFFF);
+        assert_eq!(restored.video[5], 0);
+    }
 
-        assert_eq!(chip8.delay_timer, 0x42);
+    #[test]
+    fn test_state_parse_errors() {
+        let bytes = Chip8::new().save_state().to_bytes();
+
+        assert!(Chip8State::parse(b"nope").is_err());
+        assert!(Chip8State::parse(&bytes[..bytes.len() - 1]).is_err());
+        let mut corrupt = bytes.clone();
+        corrupt[5 + REGISTER_COUNT + MEMORY_SIZE + 4 + 2 * STACK_SIZE] = 0xFF; // sp
+        assert!(Chip8State::parse(&corrupt).is_err());
     }
 
     #[test]
-    fn test_op_fx18_set_sound_timer() {
-        let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0x42;
-        chip8.opcode = 0xF518; // LD ST, V5
+    fn test_state_hash_matches_equality() {
+        let chip8 = Chip8::new();
+        let a = chip8.save_state();
+        let mut b = a.clone();
+        assert_eq!(hash_of(&a), hash_of(&b));
 
-        chip8.op_fx18();
+        b.video[VIDEO_SIZE - 1] = 0xFFFFFFFF;
+        assert_ne!(hash_of(&a), hash_of(&b));
 
-        assert_eq!(chip8.sound_timer, 0x42);
+        let states: HashSet<Chip8State> = [a.clone(), a, b].into_iter().collect();
+        assert_eq!(states.len(), 2);
     }
 
     #[test]
-    fn test_op_fx1e_add_to_index() {
+    fn test_run_until_cycle() {
         let mut chip8 = Chip8::new();
-        chip8.index = 0x200;
-        chip8.registers[5] = 0x10;
-        chip8.opcode = 0xF51E; // ADD I, V5
+        // LD V0, 0x01; JP 0x202
+        chip8.load_test_program(&[0x60, 0x01, 0x12, 0x02]);
+        assert_eq!(chip8.run_until_cycle(10), Some(3)); // Repeats at 2, confirmed at 3
+        assert_eq!(chip8.get_cycle_count(), 3);
 
-        chip8.op_fx1e();
+        // LD V0, 0x01; JP 0x200 first repeats the state after LD, two cycles apart
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x60, 0x01, 0x12, 0x00]);
+        assert_eq!(chip8.run_until_cycle(10), Some(5));
+        assert_eq!(chip8.pc, 0x202);
 
-        assert_eq!(chip8.index, 0x210);
+        // ADD V0, 0x01; JP 0x200 never repeats until V0 wraps around
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x70, 0x01, 0x12, 0x00]);
+        assert_eq!(chip8.run_until_cycle(100), None);
     }
 
     #[test]
-    fn test_op_fx29_load_font_address() {
+    fn test_run_until_cycle_near_timeout() {
+        // LD V0, 0x01; JP 0x200 repeats at cycle 3 but needs until 5 to confirm it
+        let program = [0x60, 0x01, 0x12, 0x00];
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 0xA;
-        chip8.opcode = 0xF529; // LD F, V5
+        chip8.load_test_program(&program);
+        assert_eq!(chip8.run_until_cycle(4), None);
+        assert_eq!(chip8.get_cycle_count(), 4); // Never past the budget
 
-        chip8.op_fx29();
-
-        // Font for 'A' (0xA) should be at 0x50 + (5 * 0xA) = 0x50 + 50 = 0x82
-        assert_eq!(chip8.index, 0x50 + (5 * 0xA));
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&program);
+        assert_eq!(chip8.run_until_cycle(5), Some(5));
     }
 
+    // RUN-UNTIL TESTS
+
     #[test]
-    fn test_op_fx33_bcd_conversion() {
+    fn test_run_until_display_changes() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 234;
-        chip8.index = 0x300;
-        chip8.opcode = 0xF533; // LD B, V5
-
-        chip8.op_fx33();
+        // LD V0, 0x05; LD I, 0x050; DRW V0, V0, 5
+        chip8.load_test_program(&[0x60, 0x05, 0xA0, 0x50, 0xD0, 0x05]);
 
-        assert_eq!(chip8.memory[0x300], 2); // Hundreds
-        assert_eq!(chip8.memory[0x301], 3); // Tens
-        assert_eq!(chip8.memory[0x302], 4); // Ones
+        assert_eq!(chip8.run_until_display_changes(10), Ok(3));
+        assert_eq!(chip8.video[5 * VIDEO_WIDTH + 5], 0xFFFFFFFF);
     }
 
     #[test]
-    fn test_op_fx33_bcd_conversion_small() {
+    fn test_run_until_display_changes_times_out() {
         let mut chip8 = Chip8::new();
-        chip8.registers[5] = 7;
-        chip8.index = 0x300;
-        chip8.opcode = 0xF533; // LD B, V5
+        chip8.load_test_program(&[0x00, 0xE0, 0x12, 0x00]); // CLS on a blank screen; JP 0x200
 
-        chip8.op_fx33();
+        let err = chip8.run_until_display_changes(50).unwrap_err();
 
-        assert_eq!(chip8.memory[0x300], 0); // Hundreds
-        assert_eq!(chip8.memory[0x301], 0); // Tens
-        assert_eq!(chip8.memory[0x302], 7); // Ones
+        assert_eq!(err, TimeoutError { cycles: 50 });
+        assert_eq!(err.to_string(), "timed out after 50 cycles");
     }
 
     #[test]
-    fn test_op_fx55_store_registers() {
+    fn test_run_until_pc() {
         let mut chip8 = Chip8::new();
-        chip8.registers[0] = 0x10;
-        chip8.registers[1] = 0x20;
-        chip8.registers[2] = 0x30;
-        chip8.index = 0x300;
-        chip8.opcode = 0xF255; // LD [I], V2 (store V0-V2)
-
-        chip8.op_fx55();
+        // CALL 0x206; JP 0x202; (pad); RET
+        chip8.load_test_program(&[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE]);
 
-        assert_eq!(chip8.memory[0x300], 0x10);
-        assert_eq!(chip8.memory[0x301], 0x20);
-        assert_eq!(chip8.memory[0x302], 0x30);
+        assert_eq!(chip8.run_until_pc(0x200, 10), Ok(0));
+        assert_eq!(chip8.run_until_pc(0x202, 10), Ok(2));
+        assert_eq!(chip8.run_until_pc(0x300, 10), Err(TimeoutError { cycles: 10 }));
     }
 
     #[test]
-    fn test_op_fx65_load_registers() {
+    fn test_run_until_register_equals() {
         let mut chip8 = Chip8::new();
-        chip8.memory[0x300] = 0x10;
-        chip8.memory[0x301] = 0x20;
-        chip8.memory[0x302] = 0x30;
-        chip8.index = 0x300;
-        chip8.opcode = 0xF265; // LD V2, [I] (load V0-V2)
+        chip8.load_test_program(&[0x73, 0x01, 0x12, 0x00]); // ADD V3, 0x01; JP 0x200
 
-        chip8.op_fx65();
+        assert_eq!(chip8.run_until_register_equals(3, 4, 100), Ok(7));
+        assert_eq!(chip8.registers[3], 4);
+        assert!(chip8.run_until_register_equals(3, 3, 5).is_err());
+    }
 
-        assert_eq!(chip8.registers[0], 0x10);
-        assert_eq!(chip8.registers[1], 0x20);
-        assert_eq!(chip8.registers[2], 0x30);
+    #[test]
+    fn test_cycles_needed_this_frame() {
+        let mut chip8 = Chip8::new();
+        chip8.set_cycles_per_frame(10);
+        assert_eq!(chip8.cycles_needed_this_frame(), 10);
+
+        // At 144Hz each frame gets 600/144 = 4.17 cycles, adding up to 600 a second
+        chip8.set_target_fps(144.0);
+        let frames: Vec<u32> = (0..144).map(|_| chip8.cycles_needed_this_frame()).collect();
+        assert!(frames.iter().all(|&cycles| cycles == 4 || cycles == 5));
+        assert!((599..=600).contains(&frames.iter().sum::<u32>()));
     }
 
     #[test]
-    fn test_op_dxyn_draw() {
+    fn test_step_n() {
         let mut chip8 = Chip8::new();
+        // LD I, 0x050; DRW V0, V0, 5; ADD V1, 0x01; JP 0x204
+        chip8.load_test_program(&[0xA0, 0x50, 0xD0, 0x05, 0x71, 0x01, 0x12, 0x04]);
+
+        let stats = chip8.step_n(6);
+        assert_eq!(stats, CycleStats {
+            instructions_run: 6,
+            display_updates: 1,
+            halted: false,
+            halt_reason: None,
+        });
+        assert_eq!(chip8.registers[1], 2);
+    }
 
-        // Set up a simple 1x1 sprite (just one byte with all bits set)
-        chip8.index = 0x300;
-        chip8.memory[0x300] = 0xFF; // 11111111 in binary
+    #[test]
+    fn test_step_n_stops_when_halted() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x60, 0x01, 0x12, 0x02]); // LD V0, 0x01; JP 0x202
 
-        // Draw at position (0, 0)
-        chip8.registers[0] = 0; // x position
-        chip8.registers[1] = 0; // y position
-        chip8.opcode = 0xD011; // DRW V0, V1, 1
+        let stats = chip8.step_n(100);
+        assert_eq!(stats.instructions_run, 2);
+        assert_eq!(stats.halt_reason, Some(HaltReason::InfiniteLoop(0x202)));
 
-        chip8.op_dxyn();
+        chip8.pc = 0xFFFF;
+        let stats = chip8.step_n(100);
+        assert_eq!(stats.instructions_run, 0);
+        assert_eq!(stats.halt_reason, Some(HaltReason::PcOutOfBounds(0xFFFF)));
+    }
 
-        // Check that the first 8 pixels in the first row are set
-        for i in 0..8 {
-            assert_eq!(chip8.video[i], 0xFFFFFFFF);
-        }
+    #[test]
+    fn test_step_n_no_false_loop_above_0fff() {
+        let mut chip8 = Chip8::new();
+        // SE V2, 0 at 0x2200 is the opcode 0x1000 | 0x2200, but it skips rather than loops
+        chip8.memory[0x2200..0x2202].copy_from_slice(&[0x32, 0x00]);
+        chip8.pc = 0x2200;
 
-        // Check that collision flag is not set (nothing was there before)
-        assert_eq!(chip8.registers[0xF], 0);
+        let stats = chip8.step_n(1);
+        assert!(!stats.halted);
+        assert_eq!(chip8.pc, 0x2204);
     }
 }
@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+// Data read by one instruction. Dynamic reads depend on an I value that a straight-line
+// sweep can't pin down.
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum MemoryAccess {
+    Static(BTreeSet<u16>),
+    DynamicRead,
+}
+
+// Instruction address -> memory it reads, for every instruction that reads data.
+// Instruction fetches themselves are not listed.
+#[allow(dead_code)]
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct AccessGraph {
+    pub reads: BTreeMap<u16, MemoryAccess>,
+}
+
+#[allow(dead_code)]
+impl AccessGraph {
+    // Every statically known address read by some instruction
+    pub fn static_reads(&self) -> BTreeSet<u16> {
+        self.reads.values()
+            .filter_map(|access| match access {
+                MemoryAccess::Static(addrs) => Some(addrs.iter().copied()),
+                MemoryAccess::DynamicRead => None,
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+// Sweep `rom` (loaded at `base`) two bytes at a time, tracking I through straight-line code.
+// I is forgotten at jump/call targets and after anything that changes it by a runtime value.
+#[allow(dead_code)]
+pub fn memory_access_graph(rom: &[u8], base: u16) -> AccessGraph {
+    let opcodes: Vec<(u16, u16)> = rom.chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| (base + 2 * i as u16, (pair[0] as u16) << 8 | pair[1] as u16))
+        .collect();
+
+    // Anything that can be reached by a jump may see a different I
+    let targets: BTreeSet<u16> = opcodes.iter()
+        .filter(|&&(_, opcode)| matches!(opcode >> 12, 0x1 | 0x2))
+        .map(|&(_, opcode)| opcode & 0x0FFF)
+        .collect();
+
+    let mut graph = AccessGraph::default();
+    let mut index: Option<u16> = None;
+
+    let mut words = opcodes.iter().peekable();
+    while let Some(&(addr, opcode)) = words.next() {
+        if targets.contains(&addr) {
+            index = None;
+        }
+
+        let x = (opcode & 0x0F00) >> 8;
+        let read = |len: u16| match index {
+            Some(i) => MemoryAccess::Static((0..len).map(|k| i.wrapping_add(k)).collect()),
+            None => MemoryAccess::DynamicRead,
+        };
+
+        match opcode >> 12 {
+            0xA => index = Some(opcode & 0x0FFF),
+            // XO-CHIP's F000 NNNN: the next word is the new I, not an instruction
+            0xF if opcode == 0xF000 => index = words.next().map(|&(_, nnnn)| nnnn),
+            // Dxy0 reads nothing in lores but 32 bytes in hires, and the mode isn't known here
+            0xD if opcode & 0x000F == 0 => {
+                graph.reads.insert(addr, MemoryAccess::DynamicRead);
+            }
+            0xD => {
+                graph.reads.insert(addr, read(opcode & 0x000F));
+            }
+            0xF if opcode & 0x00FF == 0x65 => {
+                graph.reads.insert(addr, read(x + 1));
+                index = None; // Moved past the registers, or not, depending on the quirks
+            }
+            0xF if opcode & 0x00FF == 0x55 => index = None,
+            // Fx1E (ADD I, Vx) and Fx29 (LD F, Vx) depend on a register
+            0xF if matches!(opcode & 0x00FF, 0x1E | 0x29) => index = None,
+            // Control leaves the straight line: whatever follows is reached some other way
+            0x0 if opcode == 0x00EE => index = None,
+            0x1 | 0x2 | 0xB => index = None,
+            _ => {}
+        }
+    }
+
+    graph
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memory_access_graph_static_reads() {
+        // LD I, 0x300; DRW V0, V1, 3; LD V2, [I]
+        let rom = [0xA3, 0x00, 0xD0, 0x13, 0xF2, 0x65];
+
+        let graph = memory_access_graph(&rom, 0x200);
+
+        assert_eq!(graph.reads.len(), 2);
+        assert_eq!(graph.reads[&0x202], MemoryAccess::Static((0x300..0x303).collect()));
+        assert_eq!(graph.reads[&0x204], MemoryAccess::Static((0x300..0x303).collect()));
+        assert_eq!(graph.static_reads(), (0x300..0x303).collect());
+    }
+
+    #[test]
+    fn test_memory_access_graph_dynamic_reads() {
+        // LD I, 0x300; ADD I, V0; DRW V0, V1, 1; LD I, 0x310; DRW V0, V1, 1; JP 0x208
+        let rom = [0xA3, 0x00, 0xF0, 0x1E, 0xD0, 0x11, 0xA3, 0x10, 0xD0, 0x11, 0x12, 0x08];
+
+        let graph = memory_access_graph(&rom, 0x200);
+
+        assert_eq!(graph.reads[&0x204], MemoryAccess::DynamicRead);
+        // 0x208 is a jump target, so I from 0x206 can't be trusted there
+        assert_eq!(graph.reads[&0x208], MemoryAccess::DynamicRead);
+    }
+
+    #[test]
+    fn test_memory_access_graph_dxy0() {
+        // LD I, 0x300; DRW V0, V1, 0
+        let graph = memory_access_graph(&[0xA3, 0x00, 0xD0, 0x10], 0x200);
+        assert_eq!(graph.reads[&0x202], MemoryAccess::DynamicRead);
+    }
+
+    #[test]
+    fn test_memory_access_graph_long_index() {
+        // LD I, 0x2300 (F000 2300); DRW V0, V1, 2; LD V1, [I]
+        let rom = [0xF0, 0x00, 0x23, 0x00, 0xD0, 0x12, 0xF1, 0x65];
+
+        let graph = memory_access_graph(&rom, 0x200);
+
+        assert_eq!(graph.reads.len(), 2); // The 0x2300 word isn't decoded as an instruction
+        assert_eq!(graph.reads[&0x204], MemoryAccess::Static((0x2300..0x2302).collect()));
+        assert_eq!(graph.reads[&0x206], MemoryAccess::Static((0x2300..0x2302).collect()));
+    }
+}
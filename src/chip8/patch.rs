@@ -0,0 +1,106 @@
+// IPS patches: "PATCH", then records of a 3-byte offset and 2-byte length followed by that
+// many bytes, ending with "EOF". A zero length marks an RLE record: 2-byte count, 1-byte value.
+// All numbers are big-endian.
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_EOF: [u8; 3] = *b"EOF";
+
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum PatchError {
+    MissingHeader,
+    Truncated { offset: usize }, // Patch ended inside a record starting at `offset`
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::MissingHeader => write!(f, "Not an IPS patch: missing PATCH header"),
+            PatchError::Truncated { offset } => {
+                write!(f, "IPS patch truncated in record at byte {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// Apply an IPS patch to a copy of `rom`. Records past the end grow the ROM, zero-filling any
+// gap; an optional 3-byte size after EOF truncates it.
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut records = patch.strip_prefix(IPS_HEADER).ok_or(PatchError::MissingHeader)?;
+    let mut output = rom.to_vec();
+
+    loop {
+        let record_start = patch.len() - records.len();
+        let truncated = PatchError::Truncated { offset: record_start };
+
+        let offset_bytes: [u8; 3] = records.get(..3).ok_or(truncated.clone())?.try_into().unwrap();
+        if offset_bytes == IPS_EOF {
+            if let Some(size) = records.get(3..6) {
+                output.truncate(be_u24(size));
+            }
+            return Ok(output);
+        }
+
+        let offset = be_u24(&offset_bytes);
+        let size = be_u16(records.get(3..5).ok_or(truncated.clone())?);
+        let data: Vec<u8> = if size == 0 {
+            let run = records.get(5..8).ok_or(truncated.clone())?;
+            records = &records[8..];
+            vec![run[2]; be_u16(&run[..2])]
+        } else {
+            let data = records.get(5..5 + size).ok_or(truncated.clone())?;
+            records = &records[5 + size..];
+            data.to_vec()
+        };
+
+        if output.len() < offset + data.len() {
+            output.resize(offset + data.len(), 0);
+        }
+        output[offset..offset + data.len()].copy_from_slice(&data);
+    }
+}
+
+fn be_u16(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 8 | bytes[1] as usize
+}
+
+fn be_u24(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_ips_records() {
+        let rom = [0x00, 0xE0, 0x12, 0x00];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0x12, 0x02]); // 2 bytes at 2
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, 0xAA]); // RLE at 6
+        patch.extend_from_slice(b"EOF");
+
+        let patched = apply_ips(&rom, &patch).unwrap();
+
+        assert_eq!(patched, vec![0x00, 0xE0, 0x12, 0x02, 0x00, 0x00, 0xAA, 0xAA, 0xAA]);
+    }
+
+    #[test]
+    fn test_apply_ips_truncate_after_eof() {
+        let patch = [b"PATCH".as_slice(), b"EOF", &[0x00, 0x00, 0x02]].concat();
+
+        assert_eq!(apply_ips(&[1, 2, 3, 4], &patch).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_apply_ips_errors() {
+        assert_eq!(apply_ips(&[], b"NOPE"), Err(PatchError::MissingHeader));
+        assert_eq!(
+            apply_ips(&[], b"PATCH\x00\x00\x01\x00\x05\xAA"),
+            Err(PatchError::Truncated { offset: 5 })
+        );
+        assert_eq!(apply_ips(&[], b"PATCH"), Err(PatchError::Truncated { offset: 5 }));
+    }
+}
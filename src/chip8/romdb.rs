@@ -0,0 +1,74 @@
+// Quirk profiles for known ROMs, so they run right without the user picking quirks. ROMs are
+// identified by rom_hash, FNV-1a over the file's bytes.
+
+use super::{fnv1a, QuirksConfig, FNV_OFFSET_BASIS};
+
+#[allow(dead_code)]
+#[derive(PartialEq, Debug)]
+pub struct KnownRom {
+    pub title: &'static str,
+    pub profile: &'static str, // "vip" or "chip48", see profile_quirks
+}
+
+// Seeded with the ROMs bundled in rom/
+const KNOWN_ROMS: &[(u64, KnownRom)] = &[
+    (0x2f57183db1eb1fd6, KnownRom { title: "Cave", profile: "vip" }),
+    (0x786dfe58a174264b, KnownRom { title: "Soccer", profile: "vip" }),
+    (0x56049e83866b207d, KnownRom { title: "Tic-Tac-Toe (David Winter)", profile: "chip48" }),
+    (0xb45b7f671fd4e77b, KnownRom { title: "Corax89 opcode test", profile: "chip48" }),
+];
+
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, rom)
+}
+
+pub fn known_rom(rom_hash: u64) -> Option<&'static KnownRom> {
+    KNOWN_ROMS.iter().find(|(hash, _)| *hash == rom_hash).map(|(_, rom)| rom)
+}
+
+// The quirks a known ROM needs; None means use the defaults
+pub fn quirks_for(rom_hash: u64) -> Option<QuirksConfig> {
+    known_rom(rom_hash).map(|rom| profile_quirks(rom.profile))
+}
+
+// "vip" is the COSMAC VIP, which the defaults already follow. "chip48" is the HP-48 and
+// later PC interpreters most 90s ROMs were written against: shifts work on Vx in place,
+// Fx55/Fx65 leave I alone, logic ops keep VF, Dxyn doesn't wait for the display and Bnnn
+// is SUPER-CHIP's Bxnn. Everything else stays at the defaults.
+fn profile_quirks(profile: &str) -> QuirksConfig {
+    match profile {
+        "chip48" => QuirksConfig {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            vf_reset_on_logic: false,
+            display_wait: false,
+            jump_uses_vx: true,
+            ..QuirksConfig::default()
+        },
+        _ => QuirksConfig::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quirks_for_known_rom() {
+        let hash = rom_hash(include_bytes!("../../rom/Tic-Tac_Toe.ch8"));
+
+        assert_eq!(known_rom(hash).unwrap().profile, "chip48");
+        let quirks = quirks_for(hash).unwrap();
+        assert!(!quirks.shift_uses_vy && !quirks.load_store_increments_i && !quirks.display_wait);
+        assert!(!quirks.vf_reset_on_logic && quirks.jump_uses_vx);
+        assert_eq!(quirks.clip_sprites, QuirksConfig::default().clip_sprites);
+
+        let soccer = rom_hash(include_bytes!("../../rom/Soccer.ch8"));
+        assert_eq!(quirks_for(soccer), Some(QuirksConfig::default()));
+    }
+
+    #[test]
+    fn test_quirks_for_unknown_rom() {
+        assert_eq!(quirks_for(rom_hash(&[0x12, 0x00])), None);
+    }
+}
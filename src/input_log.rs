@@ -0,0 +1,120 @@
+use anyhow::Result;
+use std::{fs, io::Write, path::Path};
+
+// One record per keypad change: 8-byte cycle number, then a 2-byte bitfield with key N in
+// bit N. Both big-endian.
+const RECORD_SIZE: usize = 10;
+
+fn keys_to_bits(keys: &[bool; 16]) -> u16 {
+    keys.iter().enumerate().fold(0, |bits, (i, &down)| bits | (down as u16) << i)
+}
+
+fn bits_to_keys(bits: u16) -> [bool; 16] {
+    std::array::from_fn(|i| bits & (1 << i) != 0)
+}
+
+// Writes the keypad to a file whenever it changes
+pub struct InputRecorder {
+    file: fs::File,
+    last: Option<[bool; 16]>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+        Ok(Self { file, last: None })
+    }
+
+    pub fn record(&mut self, cycle: u64, keys: &[bool; 16]) -> Result<()> {
+        if self.last == Some(*keys) {
+            return Ok(());
+        }
+        let mut record = [0u8; RECORD_SIZE];
+        record[..8].copy_from_slice(&cycle.to_be_bytes());
+        record[8..].copy_from_slice(&keys_to_bits(keys).to_be_bytes());
+        self.file.write_all(&record)?;
+        self.last = Some(*keys);
+        Ok(())
+    }
+}
+
+// Plays a recording back: the keypad is whatever the last record at or before a cycle says
+pub struct InputReplay {
+    records: Vec<(u64, u16)>,
+    next: usize,
+    keys: [bool; 16],
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_bytes(&data)
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        if !data.len().is_multiple_of(RECORD_SIZE) {
+            return Err(anyhow::anyhow!("Input recording is truncated: {} bytes", data.len()));
+        }
+        let records = data.chunks_exact(RECORD_SIZE)
+            .map(|record| {
+                let cycle = u64::from_be_bytes(record[..8].try_into().unwrap());
+                (cycle, u16::from_be_bytes([record[8], record[9]]))
+            })
+            .collect();
+        Ok(Self { records, next: 0, keys: [false; 16] })
+    }
+
+    // Keypad state at `cycle`. Cycles must not go backwards between calls.
+    pub fn keys_at(&mut self, cycle: u64) -> [bool; 16] {
+        while let Some(&(at, bits)) = self.records.get(self.next) {
+            if at > cycle {
+                break;
+            }
+            self.keys = bits_to_keys(bits);
+            self.next += 1;
+        }
+        self.keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_and_replay() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut pressed = [false; 16];
+
+        let mut recorder = InputRecorder::create(file.path()).unwrap();
+        recorder.record(0, &pressed).unwrap();
+        pressed[0xA] = true;
+        recorder.record(100, &pressed).unwrap();
+        recorder.record(110, &pressed).unwrap(); // Unchanged, not written
+        recorder.record(250, &[false; 16]).unwrap();
+
+        assert_eq!(fs::metadata(file.path()).unwrap().len(), 3 * RECORD_SIZE as u64);
+
+        let mut replay = InputReplay::load(file.path()).unwrap();
+        assert_eq!(replay.keys_at(50), [false; 16]);
+        assert_eq!(replay.keys_at(100), pressed);
+        assert_eq!(replay.keys_at(249), pressed);
+        assert_eq!(replay.keys_at(1000), [false; 16]);
+    }
+
+    #[test]
+    fn test_replay_rejects_truncated_file() {
+        assert!(InputReplay::from_bytes(&[0; RECORD_SIZE + 3]).is_err());
+    }
+
+    #[test]
+    fn test_key_bits() {
+        let mut keys = [false; 16];
+        keys[0] = true;
+        keys[0xF] = true;
+        assert_eq!(keys_to_bits(&keys), 0x8001);
+        assert_eq!(bits_to_keys(0x8001), keys);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::chip8::{Chip8, Chip8State};
+
+// Glyphs are 3x5; each cell adds one pixel of spacing on the right and bottom
+pub const GLYPH_WIDTH: usize = 4;
+pub const GLYPH_HEIGHT: usize = 6;
+pub const COLUMNS: usize = 32;
+pub const ROWS: usize = 16;
+
+const FOREGROUND: u32 = 0x33FF66FF;
+const BACKGROUND: u32 = 0x101010FF;
+
+// One row per byte, using the low 3 bits (bit 2 is the leftmost pixel)
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        ' ' => [0b000; 5],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010], // ?
+    }
+}
+
+// Rasterize text into a COLUMNS x ROWS character grid of 0xRRGGBBAA pixels.
+// Lines and columns past the grid are cut off.
+pub fn render_text(lines: &[String]) -> Vec<u32> {
+    let width = COLUMNS * GLYPH_WIDTH;
+    let mut buffer = vec![BACKGROUND; width * ROWS * GLYPH_HEIGHT];
+
+    for (row, line) in lines.iter().take(ROWS).enumerate() {
+        for (col, c) in line.chars().take(COLUMNS).enumerate() {
+            for (y, bits) in glyph(c).iter().enumerate() {
+                for x in 0..3 {
+                    if bits & (0b100 >> x) != 0 {
+                        let px = col * GLYPH_WIDTH + x;
+                        let py = row * GLYPH_HEIGHT + y;
+                        buffer[py * width + px] = FOREGROUND;
+                    }
+                }
+            }
+        }
+    }
+
+    buffer
+}
+
+// Text shown in the inspector window for a CPU snapshot
+pub fn cpu_lines(state: &Chip8State) -> Vec<String> {
+    let mut lines = Vec::new();
+    let pc = state.pc as usize;
+
+    lines.push(format!(
+        "PC {:03X} I {:03X} SP {:X} DT {:02X} ST {:02X}",
+        state.pc, state.index, state.sp, state.delay_timer, state.sound_timer
+    ));
+    lines.push(match (state.memory.get(pc), state.memory.get(pc + 1)) {
+        (Some(&high), Some(&low)) => {
+            let opcode = (high as u16) << 8 | low as u16;
+            format!("OP {:04X} {}", opcode, Chip8::disassemble_opcode(opcode))
+        }
+        _ => "OP ----".to_string(),
+    });
+    for row in state.registers.chunks(4).enumerate() {
+        let (i, regs) = row;
+        let cells: Vec<String> = regs.iter().enumerate()
+            .map(|(j, value)| format!("V{:X} {:02X}", i * 4 + j, value))
+            .collect();
+        lines.push(cells.join(" "));
+    }
+
+    lines.push("STACK".to_string());
+    let stack: Vec<String> = state.stack[..state.sp as usize].iter()
+        .map(|addr| format!("{:03X}", addr))
+        .collect();
+    for chunk in stack.chunks(7) {
+        lines.push(chunk.join(" "));
+    }
+
+    lines.push("MEMORY".to_string());
+    let base = (pc & !0x7).saturating_sub(8);
+    for row_start in (base..base + 32).step_by(8) {
+        let bytes: Vec<String> = (row_start..row_start + 8)
+            .map(|addr| state.memory.get(addr).map_or("--".to_string(), |b| format!("{:02X}", b)))
+            .collect();
+        let marker = if (row_start..row_start + 8).contains(&pc) { '>' } else { ' ' };
+        lines.push(format!("{}{:03X} {}", marker, row_start, bytes.join(" ")));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_text_draws_glyph() {
+        let buffer = render_text(&["1".to_string()]);
+        let width = COLUMNS * GLYPH_WIDTH;
+
+        // Top row of '1' is 0b010: only the middle pixel is lit
+        assert_eq!(buffer[0], BACKGROUND);
+        assert_eq!(buffer[1], FOREGROUND);
+        assert_eq!(buffer[2], BACKGROUND);
+        // Bottom row is 0b111
+        assert_eq!(&buffer[4 * width..4 * width + 3], &[FOREGROUND; 3]);
+    }
+
+    #[test]
+    fn test_cpu_lines() {
+        let mut chip8 = Chip8::new();
+        chip8.load_test_program(&[0x6A, 0x55]);
+        let lines = cpu_lines(&chip8.save_state());
+
+        assert_eq!(lines[0], "PC 200 I 000 SP 0 DT 00 ST 00");
+        assert_eq!(lines[1], "OP 6A55 LD VA, 0x55");
+        assert!(lines.iter().any(|line| line.starts_with(">200 6A 55")));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::options::OPTIONS;
+use anyhow::Result;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Scale and delay used when a ROM is opened from the file manager
+const LAUNCH_ARGS: &str = "10 2";
+
+// The scripts are built from options::OPTIONS, the same table the usage text comes from
+fn bash_completion() -> String {
+    let names: Vec<&str> = OPTIONS.iter().map(|option| option.name).collect();
+    let with_files: Vec<&str> = OPTIONS.iter()
+        .filter(|option| option.files)
+        .map(|option| option.name)
+        .collect();
+    let with_values: Vec<&str> = OPTIONS.iter()
+        .filter(|option| option.value_count() > 0 && !option.files)
+        .map(|option| option.name)
+        .collect();
+
+    format!(r#"_fries() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+
+    case "$prev" in
+        {files})
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return ;;
+        {values})
+            return ;;
+    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{names}" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "install" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 3 ]]; then
+        COMPREPLY=($(compgen -f -X '!*.ch8' -- "$cur") $(compgen -d -- "$cur"))
+    fi
+}}
+complete -F _fries fries
+"#,
+        files = with_files.join("|"),
+        values = with_values.join("|"),
+        names = names.join(" "),
+    )
+}
+
+fn zsh_completion() -> String {
+    let mut script = String::from("#compdef fries\n\n_arguments \\\n");
+    for option in OPTIONS {
+        let action = if option.files { "_files" } else { "" };
+        let values: String = option.value.split('<').skip(1)
+            .map(|value| format!(":{}:{}", value.trim_end().trim_end_matches('>'), action))
+            .collect();
+        // Colons separate the fields of an _arguments spec, so the description escapes them
+        let summary = option.summary().replace(':', "\\:");
+        script += &format!("    '{}[{}]{}' \\\n", option.name, summary, values);
+    }
+    script += "    '1:scale or subcommand:(install)' \\\n";
+    script += "    '2:delay (ms):' \\\n";
+    script += "    '3:ROM:_files -g \"*.ch8\"'\n";
+    script
+}
+
+fn fish_completion() -> String {
+    let mut script = String::from("complete -c fries -f\n");
+    script += "complete -c fries -n \"__fish_use_subcommand\" -a install -d \"Install shell completions and file associations\"\n";
+    for option in OPTIONS {
+        let takes_value = match (option.value_count() > 0, option.files) {
+            (true, true) => " -r -F",
+            (true, false) => " -r",
+            (false, _) => "",
+        };
+        script += &format!(
+            "complete -c fries -l {}{} -d \"{}\"\n",
+            option.name.trim_start_matches("--"),
+            takes_value,
+            option.summary()
+        );
+    }
+    script += "complete -c fries -k -a \"(__fish_complete_suffix .ch8)\"\n";
+    script
+}
+
+// `fries install`: shell completions plus a .ch8 file association for this executable
+pub fn run() -> Result<()> {
+    let home = PathBuf::from(
+        std::env::var_os("HOME").ok_or_else(|| anyhow::anyhow!("HOME is not set"))?,
+    );
+    let exe = std::env::current_exe()?;
+
+    write_file(&data_dir(&home).join("bash-completion/completions/fries"), &bash_completion())?;
+    write_file(&home.join(".zfunc/_fries"), &zsh_completion())?;
+    write_file(&config_dir(&home).join("fish/completions/fries.fish"), &fish_completion())?;
+    println!("zsh: make sure ~/.zfunc is in your fpath");
+
+    install_file_association(&home, &exe)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn install_file_association(home: &Path, exe: &Path) -> Result<()> {
+    let desktop = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=FRIES-8\n\
+         Comment=CHIP-8 emulator\n\
+         Exec=\"{}\" {} %f\n\
+         MimeType=application/x-chip8-rom;\n\
+         Categories=Game;Emulator;\n\
+         Terminal=false\n",
+        exe.display(),
+        LAUNCH_ARGS
+    );
+    let mime = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                <mime-info xmlns=\"http://www.freedesktop.org/standards/shared-mime-info\">\n\
+                \x20 <mime-type type=\"application/x-chip8-rom\">\n\
+                \x20   <comment>CHIP-8 ROM</comment>\n\
+                \x20   <glob pattern=\"*.ch8\"/>\n\
+                \x20 </mime-type>\n\
+                </mime-info>\n";
+
+    write_file(&data_dir(home).join("applications/fries.desktop"), &desktop)?;
+    write_file(&data_dir(home).join("mime/packages/fries.xml"), mime)?;
+    println!("Run `update-mime-database ~/.local/share/mime` to register .ch8 files");
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn install_file_association(home: &Path, exe: &Path) -> Result<()> {
+    let contents = home.join("Applications/Fries.app/Contents");
+    let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleName</key>
+    <string>FRIES-8</string>
+    <key>CFBundleIdentifier</key>
+    <string>io.github.yuann3.fries</string>
+    <key>CFBundleExecutable</key>
+    <string>fries</string>
+    <key>CFBundlePackageType</key>
+    <string>APPL</string>
+    <key>CFBundleDocumentTypes</key>
+    <array>
+        <dict>
+            <key>CFBundleTypeName</key>
+            <string>CHIP-8 ROM</string>
+            <key>CFBundleTypeRole</key>
+            <string>Viewer</string>
+            <key>CFBundleTypeExtensions</key>
+            <array>
+                <string>ch8</string>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+    let launcher = format!("#!/bin/sh\nexec \"{}\" {} \"$@\"\n", exe.display(), LAUNCH_ARGS);
+
+    write_file(&contents.join("Info.plist"), plist)?;
+    let launcher_path = contents.join("MacOS/fries");
+    write_file(&launcher_path, &launcher)?;
+
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(&launcher_path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn install_file_association(_home: &Path, _exe: &Path) -> Result<()> {
+    println!("File associations are not supported on this platform");
+    Ok(())
+}
+
+fn data_dir(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local/share"))
+}
+
+fn config_dir(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"))
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_completions_cover_every_option() {
+        let scripts = [bash_completion(), zsh_completion(), fish_completion()];
+        for option in OPTIONS {
+            let flag = option.name.trim_start_matches("--");
+            assert!(scripts.iter().all(|script| script.contains(flag)), "{} missing", option.name);
+        }
+    }
+}
@@ -0,0 +1,2 @@
+// The emulator core, shared by the fries binary and the integration tests under tests/
+pub mod chip8;
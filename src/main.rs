@@ -1,21 +1,159 @@
 use anyhow::Result;
-use std::time::{Duration, Instant};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::Write,
+    path::Path,
+    rc::Rc,
+};
 
-mod chip8;
+mod input_log;
+mod inspector;
+mod install;
+mod options;
 mod platform;
+mod screenshot;
 
-use chip8::Chip8;
-use platform::Platform;
+// Re-exported at the crate root so the other modules can keep using crate::chip8
+use fries::chip8;
+use chip8::{Chip8, Input};
+use input_log::{InputRecorder, InputReplay};
+use platform::{DisplayBuffer, DisplayMode, Platform, RunControl, SPLIT_DISPLAY_WIDTH};
+
+// Fastest speed for Delay, --ipf and the = key: 60,000 instructions per second
+const MAX_CYCLES_PER_FRAME: u32 = 1000;
 
 fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
+    if std::env::args().nth(1).as_deref() == Some("install") {
+        return install::run();
+    }
+
+    let mut args: Vec<String> = Vec::new();
+    let mut palette: Option<DisplayMode> = None;
+    let mut show_inspector = false;
+    let mut phosphor = false;
+    let mut run_in_background = false;
+    let mut hotspot_threshold: Option<u64> = None;
+    let mut input_lag: usize = 0;
+    let mut ab_roms: Option<(String, String)> = None;
+    let mut ips_patch: Option<String> = None;
+    let mut record_inputs: Option<String> = None;
+    let mut replay_inputs: Option<String> = None;
+    let mut seed: Option<u64> = None;
+    let mut headless_cycles: Option<usize> = None;
+    let mut dump_state = false;
+    let mut instructions_per_frame: Option<u32> = None;
+    let mut load_addr: Option<u16> = None;
+    let mut quirks: Option<chip8::QuirksConfig> = None;
+
+    // Pull out --options, leaving the positional arguments in order
+    let mut raw_args = std::env::args();
+    while let Some(arg) = raw_args.next() {
+        match arg.as_str() {
+            "--palette" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--palette requires a value"))?;
+                palette = Some(DisplayMode::from_hex_palette(&value)?);
+            }
+            "--colors" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--colors requires a value"))?;
+                palette = Some(DisplayMode::from_preset(&value)?);
+            }
+            "--inspector" => show_inspector = true,
+            "--phosphor" => phosphor = true,
+            "--background" => run_in_background = true,
+            "--hotspots" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--hotspots requires a value"))?;
+                hotspot_threshold = Some(value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid hotspot threshold: {}", value))?);
+            }
+            "--input-lag" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--input-lag requires a value"))?;
+                input_lag = value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid input lag: {}", value))?;
+            }
+            "--ips" => {
+                ips_patch = Some(raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--ips requires a value"))?);
+            }
+            "--record-inputs" => {
+                record_inputs = Some(raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--record-inputs requires a value"))?);
+            }
+            "--replay-inputs" => {
+                replay_inputs = Some(raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--replay-inputs requires a value"))?);
+            }
+            "--headless" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--headless requires a value"))?;
+                headless_cycles = Some(value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid cycle count: {}", value))?);
+            }
+            "--ipf" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--ipf requires a value"))?;
+                instructions_per_frame = Some(value.parse().ok()
+                    .filter(|&n| (1..=MAX_CYCLES_PER_FRAME).contains(&n))
+                    .ok_or_else(|| anyhow::anyhow!(
+                        "Invalid instructions per frame: {} (1 to {})", value, MAX_CYCLES_PER_FRAME
+                    ))?);
+            }
+            "--dump-state" => dump_state = true,
+            "--load-addr" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--load-addr requires a value"))?;
+                let digits = value.strip_prefix("0x").unwrap_or(&value);
+                load_addr = Some(u16::from_str_radix(digits, 16)
+                    .map_err(|_| anyhow::anyhow!("Invalid load address: {}", value))?);
+            }
+            "--quirks" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--quirks requires a value"))?;
+                quirks = Some(chip8::QuirksConfig::from_names(&value)?);
+            }
+            "--seed" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--seed requires a value"))?;
+                seed = Some(value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid seed: {}", value))?);
+            }
+            "--ab-compare" => {
+                let (Some(rom_a), Some(rom_b)) = (raw_args.next(), raw_args.next()) else {
+                    return Err(anyhow::anyhow!("--ab-compare requires two ROMs"));
+                };
+                ab_roms = Some((rom_a, rom_b));
+            }
+            #[cfg(feature = "bench")]
+            "--benchmark-roms" => {
+                let value = raw_args.next()
+                    .ok_or_else(|| anyhow::anyhow!("--benchmark-roms requires a value"))?;
+                let minimum_ips: u64 = value.parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid minimum IPS: {}", value))?;
+                return benchmark_roms(minimum_ips);
+            }
+            _ => args.push(arg),
+        }
+    }
 
-    if args.len() != 4 {
-        println!("Usage: {} <Scale> <Delay> <ROM>", args[0]);
+    // In A/B mode the ROMs come from --ab-compare instead of the last positional argument
+    let positional_count = if ab_roms.is_some() { 3 } else { 4 };
+    if args.len() != positional_count {
+        println!("Usage: {} <Scale> <Delay> <ROM> [Options]", args[0]);
+        println!("       {} <Scale> <Delay> --ab-compare <ROM A> <ROM B> [Options]", args[0]);
+        println!("       {} install  (shell completions and .ch8 file association)", args[0]);
         println!("  Scale: Window scale factor (e.g., 10)");
         println!("  Delay: Cycle delay in milliseconds (e.g., 1)");
         println!("  ROM: Path to CHIP-8 ROM file (e.g., test_opcode.ch8)");
         println!();
+        println!("Options:");
+        for line in options::usage_lines() {
+            println!("{}", line);
+        }
+        println!();
         println!("Examples:");
         println!("  {} 10 1 test_opcode.ch8", args[0]);
         println!("  {} 10 3 Tetris.ch8", args[0]);
@@ -27,47 +165,427 @@ fn main() -> Result<()> {
         .map_err(|_| anyhow::anyhow!("Invalid scale factor: {}", args[1]))?;
     let cycle_delay: u64 = args[2].parse()
         .map_err(|_| anyhow::anyhow!("Invalid delay: {}", args[2]))?;
+    let cycles = instructions_per_frame.unwrap_or_else(|| cycles_per_frame(cycle_delay));
+
+    if let Some((rom_a, rom_b)) = ab_roms {
+        return run_ab_compare(cycles, &rom_a, &rom_b, palette, quirks);
+    }
     let rom_filename = &args[3];
 
+    // RND has to repeat the recorded run's values for a replay to follow the same path
+    if replay_inputs.is_some() && seed.is_none() {
+        return Err(anyhow::anyhow!("--replay-inputs needs the --seed used for the recording"));
+    }
+    let mut recorder = record_inputs.as_deref()
+        .map(|path| InputRecorder::create(Path::new(path)))
+        .transpose()?;
+    let mut replay = replay_inputs.as_deref()
+        .map(|path| InputReplay::load(Path::new(path)))
+        .transpose()?;
+
     // Calculate window dimensions
-    const VIDEO_WIDTH: u32 = 64;
-    const VIDEO_HEIGHT: u32 = 32;
-    let window_width = VIDEO_WIDTH * video_scale;
-    let window_height = VIDEO_HEIGHT * video_scale;
+    let window_width = Chip8::VIDEO_WIDTH as u32 * video_scale;
+    let window_height = Chip8::VIDEO_HEIGHT as u32 * video_scale;
 
-    println!("CHIP-8 Emulator");
-    println!("Scale: {}x, Delay: {}ms, ROM: {}", video_scale, cycle_delay, rom_filename);
+    if dump_state && headless_cycles.is_none() {
+        return Err(anyhow::anyhow!("--dump-state needs --headless"));
+    }
+    // With --dump-state stdout is only the JSON, so progress goes to stderr
+    let mut status: Box<dyn Write> = if dump_state {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+
+    writeln!(status, "CHIP-8 Emulator")?;
+    match instructions_per_frame {
+        Some(ipf) => writeln!(status, "Scale: {}x, {} instructions per frame, ROM: {}", video_scale, ipf, rom_filename)?,
+        None => writeln!(status, "Scale: {}x, Delay: {}ms, ROM: {}", video_scale, cycle_delay, rom_filename)?,
+    }
 
-    let mut chip8 = Chip8::new();
+    let mut chip8 = match seed {
+        Some(seed) => Chip8::new().with_seed(seed),
+        None => Chip8::new(),
+    };
     chip8.enable_debug(false); // Disable debug for clean output like tutorial
+    chip8.set_cycles_per_frame(cycles);
+    if let Some(threshold) = hotspot_threshold {
+        chip8.set_hotspot_threshold(threshold);
+    }
 
     // Load ROM
-    println!("Loading ROM: {}", rom_filename);
-    chip8.load_rom(rom_filename)?;
-    println!("ROM loaded successfully!");
+    writeln!(status, "Loading ROM: {}", rom_filename)?;
+    load_rom_with_quirks(&mut chip8, rom_filename, load_addr, ips_patch.as_deref(), quirks, &mut status)?;
+
+    if let Some(cycles) = headless_cycles {
+        // Stop at the ROM's first error, show where it got to, then fail
+        let result = chip8.run_until_error(cycles as u64);
+        if dump_state {
+            println!("{}", chip8.save_state().to_json());
+        } else {
+            print_headless_summary(&chip8);
+        }
+        return result.map(|_| ()).map_err(|err| anyhow::anyhow!("ROM error: {}", err));
+    }
 
     // Initialize platform
-    let platform = Platform::new("CHIP-8 Emulator", window_width, window_height)?;
+    let mut platform = Platform::new(Some(rom_title(rom_filename)), window_width, window_height, None)?
+        .with_phosphor(phosphor)
+        .with_background_running(run_in_background);
+    if let Some(mode) = palette {
+        platform.set_display_mode(mode);
+    }
+
+    // Shared with the inspector window, which reads CPU state every frame
+    let chip8 = Rc::new(RefCell::new(chip8));
+    let emulator = chip8.clone();
+    // = and - retune the frame-rate governor while the ROM runs. Input logs only hold keypad
+    // changes, so a recording or replay keeps one speed to stay on the same frame schedule.
+    let speed_keys = recorder.is_none() && replay.is_none();
+    if speed_keys {
+        let chip8 = chip8.clone();
+        platform = platform.with_speed_keys(move |faster| {
+            let mut chip8 = chip8.borrow_mut();
+            let cycles = adjusted_cycles_per_frame(chip8.get_cycles_per_frame(), faster);
+            chip8.set_cycles_per_frame(cycles);
+            println!("Speed: {} instructions per second", cycles * 60);
+        });
+    }
+    // Dropping a ROM on the window swaps it in and starts it from the top
+    {
+        let chip8 = chip8.clone();
+        platform = platform.with_file_drop(move |path| {
+            match load_dropped_rom(&mut chip8.borrow_mut(), path, load_addr, quirks) {
+                Ok(title) => Some(title),
+                Err(err) => {
+                    eprintln!("Failed to load {}, keeping the current ROM: {}", path.display(), err);
+                    None
+                }
+            }
+        });
+    }
+    // While paused, Ctrl+V writes a hex or base64 patch from the clipboard at PC
+    {
+        let chip8 = chip8.clone();
+        platform = platform.with_clipboard_paste(clipboard_text, move |text| {
+            let mut chip8 = chip8.borrow_mut();
+            let pc = chip8.get_pc();
+            match chip8.paste_at_pc(text) {
+                Ok(len) => println!("Pasted {} bytes at 0x{:03X}", len, pc),
+                Err(err) => eprintln!("Failed to paste: {}", err),
+            }
+        });
+    }
+    if show_inspector {
+        let chip8 = chip8.clone();
+        platform = platform.with_inspector(move || inspector::cpu_lines(&chip8.borrow().save_state()));
+    }
 
     println!("Controls: 1234/QWER/ASDF/ZXCV keys map to CHIP-8 keypad");
+    println!("Press P to pause or resume, N to run one instruction while paused");
+    if speed_keys {
+        println!("Press = or - to speed up or slow down, F2 to save a screenshot");
+    } else {
+        println!("Press F2 to save a screenshot (= and - are off while recording or replaying)");
+    }
+    println!("Press F3 to turn the phosphor fade on or off");
+    println!("Drop a ROM file on the window to run it instead");
+    println!("Press Ctrl+V while paused to paste hex or base64 bytes at PC");
     println!("Press ESC or close window to exit");
 
-    // Main emulation loop
-    let cycle_duration = Duration::from_millis(cycle_delay);
-    let mut last_cycle_time = Instant::now();
+    // Keypad states from the last `input_lag` frames; the emulator sees the oldest one
+    let mut key_history: VecDeque<[bool; 16]> = VecDeque::from(vec![[false; 16]; input_lag]);
+    let mut last_error = None; // Reported once each, not every frame
 
-    platform.run(move |keys: &mut [bool; 16]| {
-        chip8.set_keys(keys);
+    // Main emulation loop: one batch of cycles and one timer tick per frame
+    platform.run(move |keys: &mut [bool; 16], display_buffer: &mut DisplayBuffer, control| {
+        let mut chip8 = chip8.borrow_mut();
+        let cycles_before = chip8.get_cycle_count();
+        // While paused nothing runs, so the display below is still the last frame
+        if control != RunControl::Paused {
+            key_history.push_back(*keys);
+            let mut frame_keys = key_history.pop_front().unwrap_or(*keys);
 
-        let now = Instant::now();
-        if now.duration_since(last_cycle_time) >= cycle_duration {
-            chip8.cycle();
-            last_cycle_time = now;
-        }
+            let cycle = chip8.get_cycle_count();
+            if let Some(replay) = replay.as_mut() {
+                frame_keys = replay.keys_at(cycle);
+            }
+            if let Some(Err(err)) = recorder.as_mut().map(|recorder| recorder.record(cycle, &frame_keys)) {
+                eprintln!("Stopped recording inputs: {}", err);
+                recorder = None;
+            }
 
-        let display_buffer = chip8.get_display().to_vec();
-        (display_buffer, false)
+            step_or_run_frame(&mut chip8, frame_keys, control);
+        }
+        if chip8.last_error() != last_error {
+            last_error = chip8.last_error();
+            if let Some(err) = last_error {
+                eprintln!("ROM error: {}", err);
+            }
+        }
+        let (width, height) = (chip8.video_width() as u32, chip8.video_height() as u32);
+        chip8.copy_display_to_buffer(display_buffer.resize(width, height));
+        if chip8.is_halted() {
+            println!("ROM exited");
+        }
+        let instructions = chip8.get_cycle_count() - cycles_before;
+        (chip8.is_halted(), control == RunControl::Run && chip8.is_beeping(), instructions)
     })?;
 
+    if hotspot_threshold.is_some() {
+        print_hotspots(&emulator.borrow());
+    }
+
+    Ok(())
+}
+
+// The Delay argument is milliseconds per instruction; the governor wants instructions per
+// 60Hz frame
+fn cycles_per_frame(cycle_delay: u64) -> u32 {
+    (1000.0 / 60.0 / cycle_delay as f64).round().clamp(1.0, MAX_CYCLES_PER_FRAME as f64) as u32
+}
+
+// One =/- press: about 25% faster or slower, by at least one instruction per frame, kept
+// within the range Delay and --ipf allow
+fn adjusted_cycles_per_frame(cycles: u32, faster: bool) -> u32 {
+    let step = (cycles / 4).max(1);
+    let cycles = if faster { cycles + step } else { cycles.saturating_sub(step) };
+    cycles.clamp(1, MAX_CYCLES_PER_FRAME)
+}
+
+// Everything the emulator does between two display refreshes. At the governor's default
+// 60 FPS that's exactly get_cycles_per_frame() instructions, then one timer tick.
+fn run_frame(chip8: &mut Chip8, keys: [bool; 16]) {
+    let cycles_to_run = chip8.cycles_needed_this_frame();
+    chip8.process(Input { keys, timer_tick: true, cycles_to_run });
+}
+
+// Load the ROM at `load_addr` (0x200 by default), optionally IPS-patched, then switch to the
+// quirks the ROM database has for it, unless --quirks chose them. Progress goes to `status`.
+fn load_rom_with_quirks(chip8: &mut Chip8, rom_filename: &str, load_addr: Option<u16>,
+    ips_patch: Option<&str>, quirks: Option<chip8::QuirksConfig>, status: &mut dyn Write) -> Result<()> {
+    match (ips_patch, load_addr) {
+        (Some(_), Some(_)) => return Err(anyhow::anyhow!("--ips only supports ROMs loaded at 0x200")),
+        (Some(patch), None) => chip8.load_rom_with_patch(Path::new(rom_filename), Path::new(patch))?,
+        (None, Some(addr)) => chip8.load_rom_at(rom_filename, addr)?,
+        (None, None) => chip8.load_rom(rom_filename)?,
+    }
+    writeln!(status, "ROM loaded successfully!")?;
+
+    if let Some(quirks) = quirks {
+        chip8.set_quirks(quirks);
+        writeln!(status, "{} (from --quirks)", quirks)?;
+        return Ok(());
+    }
+
+    // Known ROMs get the quirks they were written for
+    let rom_hash = chip8::romdb::rom_hash(&std::fs::read(rom_filename)?);
+    match (chip8::romdb::known_rom(rom_hash), chip8::romdb::quirks_for(rom_hash)) {
+        (Some(rom), Some(quirks)) => {
+            chip8.set_quirks(quirks);
+            writeln!(status, "Recognized {}: using the {} quirks profile", rom.title, rom.profile)?;
+        }
+        _ => {
+            chip8.set_quirks(chip8::QuirksConfig::default());
+            writeln!(status, "Unknown ROM, using the default quirks")?;
+        }
+    }
+    Ok(())
+}
+
+// Replace the running ROM with the one at `path`, loaded the way main loads the first one.
+// The size is checked before anything is reset, so a ROM that can't fit leaves the current
+// one running. Returns the title for the window.
+fn load_dropped_rom(chip8: &mut Chip8, path: &Path, load_addr: Option<u16>,
+    quirks: Option<chip8::QuirksConfig>) -> Result<String> {
+    let rom_filename = path.to_str()
+        .ok_or_else(|| anyhow::anyhow!("path is not valid UTF-8"))?;
+    let size = std::fs::read(path)?.len();
+    let addr = load_addr.unwrap_or(Chip8::START_ADDRESS);
+    let space = Chip8::MEMORY_SIZE - addr as usize;
+    if size > space {
+        return Err(anyhow::anyhow!("ROM is {} bytes, only {} fit at 0x{:03X}", size, space, addr));
+    }
+
+    println!("Loading ROM: {}", rom_filename);
+    chip8.reset_and_reload();
+    load_rom_with_quirks(chip8, rom_filename, load_addr, None, quirks, &mut std::io::stdout())?;
+    Ok(rom_title(rom_filename))
+}
+
+// The clipboard as text, read through the system's paste command since there is no
+// clipboard crate to link against. None if none of them is installed or it's empty.
+fn clipboard_text() -> Option<String> {
+    const COMMANDS: &[&[&str]] = &[
+        &["pbpaste"],
+        &["wl-paste", "--no-newline"],
+        &["xclip", "-selection", "clipboard", "-o"],
+        &["xsel", "--clipboard", "--output"],
+    ];
+    COMMANDS.iter().find_map(|command| {
+        let output = std::process::Command::new(command[0]).args(&command[1..]).output().ok()?;
+        let text = String::from_utf8(output.stdout).ok()?;
+        (output.status.success() && !text.trim().is_empty()).then_some(text)
+    })
+}
+
+// A whole frame when running, a single cycle when stepping
+fn step_or_run_frame(chip8: &mut Chip8, keys: [bool; 16], control: RunControl) {
+    if control == RunControl::Step {
+        chip8.set_keys(&keys);
+        let _ = chip8.cycle(); // Errors are reported from last_error once the frame is drawn
+    } else {
+        run_frame(chip8, keys);
+    }
+}
+
+// Two emulators fed the same keypad, drawn left (A) and right (B) of a divider
+fn run_ab_compare(cycles_per_frame: u32, rom_a: &str, rom_b: &str, palette: Option<DisplayMode>,
+    quirks: Option<chip8::QuirksConfig>) -> Result<()> {
+    let mut emulators = [Chip8::new(), Chip8::new()];
+    for (chip8, rom) in emulators.iter_mut().zip([rom_a, rom_b]) {
+        chip8.enable_debug(false);
+        chip8.set_cycles_per_frame(cycles_per_frame);
+        println!("Loading ROM: {}", rom);
+        load_rom_with_quirks(chip8, rom, None, None, quirks, &mut std::io::stdout())?;
+    }
+
+    let title = format!("{} vs {}", rom_title(rom_a), rom_title(rom_b));
+    let mut platform = Platform::new(Some(title), 0, 0, None)?.with_display_size(SPLIT_DISPLAY_WIDTH, Chip8::VIDEO_HEIGHT as u32);
+    if let Some(mode) = palette {
+        platform.set_display_mode(mode);
+    }
+
+    platform.run(move |keys: &mut [bool; 16], display_buffer: &mut DisplayBuffer, control| {
+        // A's instructions, so the title reads the same as with one ROM
+        let cycles_before = emulators[0].get_cycle_count();
+        if control != RunControl::Paused {
+            for chip8 in emulators.iter_mut() {
+                step_or_run_frame(chip8, *keys, control);
+            }
+        }
+
+        // Both halves share one resolution, so a low-res ROM next to a high-res one is doubled
+        let (width, height) = if emulators.iter().any(Chip8::is_hires) {
+            (Chip8::HIRES_VIDEO_WIDTH, Chip8::HIRES_VIDEO_HEIGHT)
+        } else {
+            (Chip8::VIDEO_WIDTH, Chip8::VIDEO_HEIGHT)
+        };
+        let [a, b] = emulators.each_ref().map(|chip8| scaled_display(chip8, width));
+        let out = display_buffer.resize(2 * width as u32 + 1, height as u32);
+        platform::split_screen(&a, &b, width, out);
+        let beeping = control == RunControl::Run && emulators.iter().any(Chip8::is_beeping);
+        let instructions = emulators[0].get_cycle_count() - cycles_before;
+        (emulators.iter().all(Chip8::is_halted), beeping, instructions)
+    })
+}
+
+// Final machine state after --headless, stable across runs so it can be diffed against a
+// known-good output
+fn print_headless_summary(chip8: &Chip8) {
+    let state = chip8.save_state();
+    println!("Cycles: {}", chip8.get_cycle_count());
+    println!("PC: 0x{:03X}  I: 0x{:03X}", state.pc, state.index);
+    let registers: Vec<String> = state.registers.iter()
+        .enumerate()
+        .map(|(i, value)| format!("V{:X}={:02X}", i, value))
+        .collect();
+    println!("Registers: {}", registers.join(" "));
+    println!("Video: {}x{} {:016x}", chip8.video_width(), chip8.video_height(), chip8.display_hash());
+    print!("{}", chip8.display_ascii());
+
+    let breakdown = chip8.opcode_stats().breakdown();
+    let total: u64 = breakdown.iter().map(|(_, count)| count).sum();
+    println!("Opcodes:");
+    for (label, count) in &breakdown {
+        println!("  {}  {:>10}  {:5.1}%", label, count, *count as f64 * 100.0 / total as f64);
+    }
+}
+
+// The display scaled up by whole pixels to `width` across
+fn scaled_display(chip8: &Chip8, width: usize) -> Vec<u32> {
+    let scale = width / chip8.video_width();
+    chip8.video_iter_rows()
+        .flat_map(|row| {
+            let wide: Vec<u32> = row.iter().flat_map(|&pixel| [pixel].repeat(scale)).collect();
+            wide.repeat(scale)
+        })
+        .collect()
+}
+
+// Title from a sidecar `<rom>.yaml` with a top-level `title:` field, else the ROM file stem
+fn rom_title(rom_filename: &str) -> String {
+    let rom_path = Path::new(rom_filename);
+    let sidecar_title = std::fs::read_to_string(rom_path.with_extension("yaml"))
+        .ok()
+        .and_then(|yaml| {
+            yaml.lines()
+                .find_map(|line| line.strip_prefix("title:"))
+                .map(|title| title.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+        })
+        .filter(|title| !title.is_empty());
+
+    sidecar_title.unwrap_or_else(|| {
+        rom_path.file_stem().map_or_else(
+            || rom_filename.to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+    })
+}
+
+fn print_hotspots(chip8: &Chip8) {
+    let counts = chip8.get_hotspot_counts();
+    let mut hotspots: Vec<(u16, u64)> = chip8.get_hotspots().iter()
+        .map(|&addr| (addr, counts[&addr]))
+        .collect();
+    hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    println!("Hotspots ({} addresses):", hotspots.len());
+    for (addr, count) in hotspots.iter().take(10) {
+        println!("  0x{:03X}: {} executions", addr, count);
+    }
+}
+
+// Run each bundled ROM headless for one second and check the achieved instructions per second
+#[cfg(feature = "bench")]
+fn benchmark_roms(minimum_ips: u64) -> Result<()> {
+    use std::time::{Duration, Instant};
+
+    let rom_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("rom");
+    let mut roms: Vec<_> = std::fs::read_dir(&rom_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    roms.sort();
+
+    let mut failed = false;
+    for rom in &roms {
+        let mut chip8 = Chip8::new();
+        chip8.enable_debug(false);
+        chip8.load_rom(&rom.to_string_lossy())?;
+
+        let start = Instant::now();
+        let mut cycles: u64 = 0;
+        while start.elapsed() < Duration::from_secs(1) {
+            // Only check the clock every so often so it doesn't dominate the measurement
+            for _ in 0..1024 {
+                let _ = chip8.cycle();
+            }
+            cycles += 1024;
+        }
+        let ips = (cycles as f64 / start.elapsed().as_secs_f64()) as u64;
+
+        let name = rom.file_name().unwrap_or_default().to_string_lossy();
+        if ips < minimum_ips {
+            println!("FAIL {}: {} IPS (minimum {})", name, ips, minimum_ips);
+            failed = true;
+        } else {
+            println!("ok   {}: {} IPS", name, ips);
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -0,0 +1,157 @@
+// Command-line options, shared by the usage text and the shell completions so they can't
+// drift apart. Parsing stays in main.
+pub struct CliOption {
+    pub name: &'static str,
+    pub value: &'static str, // Usage placeholder like "<file>", empty for a switch
+    pub files: bool,         // The value is a path, so shells should complete file names
+    pub help: &'static [&'static str],
+}
+
+impl CliOption {
+    // The help text on one line, for completion descriptions
+    pub fn summary(&self) -> String {
+        self.help.join(" ")
+    }
+
+    // How many values follow the option, e.g. 2 for --ab-compare <A> <B>
+    pub fn value_count(&self) -> usize {
+        self.value.matches('<').count()
+    }
+}
+
+pub const OPTIONS: &[CliOption] = &[
+    CliOption {
+        name: "--palette",
+        value: "<RRGGBB x8>",
+        files: false,
+        help: &["8 concatenated hex colors; off pixels use the first,", "on pixels the last"],
+    },
+    CliOption {
+        name: "--colors",
+        value: "<preset>",
+        files: false,
+        help: &["Color preset: classic, amber or green"],
+    },
+    CliOption {
+        name: "--inspector",
+        value: "",
+        files: false,
+        help: &["Open a second window showing CPU state"],
+    },
+    CliOption {
+        name: "--phosphor",
+        value: "",
+        files: false,
+        help: &["Fade pixels out over a few frames to hide flicker", "(F3 toggles it while running)"],
+    },
+    CliOption {
+        name: "--background",
+        value: "",
+        files: false,
+        help: &["Keep running and beeping when the window loses focus"],
+    },
+    CliOption {
+        name: "--hotspots",
+        value: "<N>",
+        files: false,
+        help: &["On exit, print addresses executed at least N times"],
+    },
+    CliOption {
+        name: "--input-lag",
+        value: "<frames>",
+        files: false,
+        help: &["Delay keypad input by N frames (N/60 s)"],
+    },
+    CliOption {
+        name: "--ab-compare",
+        value: "<A> <B>",
+        files: true,
+        help: &["Run two ROMs side by side with the same input"],
+    },
+    CliOption {
+        name: "--ips",
+        value: "<patch>",
+        files: true,
+        help: &["Apply an IPS patch to the ROM before loading it"],
+    },
+    CliOption {
+        name: "--record-inputs",
+        value: "<file>",
+        files: true,
+        help: &["Save every keypad change with its cycle number"],
+    },
+    CliOption {
+        name: "--replay-inputs",
+        value: "<file>",
+        files: true,
+        help: &["Feed a recording instead of live input (needs --seed)"],
+    },
+    CliOption {
+        name: "--quirks",
+        value: "<names>",
+        files: false,
+        help: &[
+            "Comma-separated quirks to change from the defaults,",
+            "e.g. shift-in-place,no-vf-reset. Replaces the ROM",
+            "database's profile",
+        ],
+    },
+    CliOption {
+        name: "--seed",
+        value: "<N>",
+        files: false,
+        help: &["Seed the random number generator"],
+    },
+    CliOption {
+        name: "--ipf",
+        value: "<N>",
+        files: false,
+        help: &[
+            "Run exactly N instructions per 60Hz frame instead of",
+            "pacing by Delay, e.g. 11 for about 700Hz. A Dxyn",
+            "waiting for the display (display_wait quirk) uses up",
+            "the rest of its frame. At most 1000",
+        ],
+    },
+    CliOption {
+        name: "--headless",
+        value: "<N>",
+        files: false,
+        help: &[
+            "Run N cycles without a window and print a summary,",
+            "stopping early with an error if the ROM hits one",
+        ],
+    },
+    CliOption {
+        name: "--dump-state",
+        value: "",
+        files: false,
+        help: &["With --headless, print the final state as JSON instead"],
+    },
+    CliOption {
+        name: "--load-addr",
+        value: "<hex>",
+        files: false,
+        help: &["Load the ROM and start running at this address instead", "of 0x200, e.g. 0x600 for ETI-660 programs"],
+    },
+    #[cfg(feature = "bench")]
+    CliOption {
+        name: "--benchmark-roms",
+        value: "<IPS>",
+        files: false,
+        help: &["Run every bundled ROM headless for 1s, fail below IPS"],
+    },
+];
+
+// The "Options:" block of the usage text, help wrapped under a 25-column gutter
+pub fn usage_lines() -> Vec<String> {
+    let mut lines = Vec::new();
+    for option in OPTIONS {
+        let usage = format!("{} {}", option.name, option.value);
+        for (i, help) in option.help.iter().enumerate() {
+            let left = if i == 0 { usage.trim_end() } else { "" };
+            lines.push(format!("  {:<22} {}", left, help));
+        }
+    }
+    lines
+}
@@ -1,55 +1,621 @@
 use anyhow::Result;
 use pixels::{Pixels, SurfaceTexture};
-use std::sync::Arc;
+use rodio::{
+    source::{Function, SignalGenerator, Source},
+    OutputStream, Sink,
+};
+use std::{
+    marker::PhantomData,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use crate::{chip8::Chip8, inspector, screenshot};
 use winit::{
     dpi::LogicalSize,
     event::{Event, WindowEvent, ElementState},
     event_loop::EventLoop,
     keyboard::{PhysicalKey, KeyCode},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 
 // CHIP-8 display constants
-const DISPLAY_WIDTH: u32 = 64;
-const DISPLAY_HEIGHT: u32 = 32;
+const DISPLAY_WIDTH: u32 = Chip8::VIDEO_WIDTH as u32;
+const DISPLAY_HEIGHT: u32 = Chip8::VIDEO_HEIGHT as u32;
+const WINDOW_SCALE: f64 = 10.0;
+
+// Buzzer tone
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_SAMPLE_RATE: u32 = 44100;
+const BEEP_VOLUME: f32 = 0.2;
+
+// Phosphor fade: how much of an off pixel's brightness is left each frame, out of 256.
+// Most of the glow is gone after three frames and all of it after ten.
+const PHOSPHOR_DECAY: u32 = 150;
+
+// Two displays side by side with a one pixel divider, see split_screen
+pub const SPLIT_DISPLAY_WIDTH: u32 = DISPLAY_WIDTH * 2 + 1;
+
+// Foreground and background, as RGBA
+pub type ColorPair = ([u8; 4], [u8; 4]);
+
+// Presets for Platform::with_colors
+pub const COLORS_CLASSIC: ColorPair = ([0xFF, 0xFF, 0xFF, 0xFF], [0x00, 0x00, 0x00, 0xFF]);
+pub const COLORS_AMBER: ColorPair = ([0xFF, 0xB0, 0x00, 0xFF], [0x1A, 0x10, 0x00, 0xFF]);
+pub const COLORS_GREEN_PHOSPHOR: ColorPair = ([0x33, 0xFF, 0x66, 0xFF], [0x00, 0x1A, 0x08, 0xFF]);
+
+// Preset names accepted on the command line
+pub const COLOR_PRESETS: &[(&str, ColorPair)] = &[
+    ("classic", COLORS_CLASSIC),
+    ("amber", COLORS_AMBER),
+    ("green", COLORS_GREEN_PHOSPHOR),
+];
+
+// How CHIP-8 video values are turned into colors
+#[derive(Clone, Copy)]
+pub enum DisplayMode {
+    // Off black, on white. XO-CHIP plane 2 alone is light grey and both planes dark grey.
+    Monochrome,
+    // Pixels lit in any plane use `fg`, off pixels `bg`. Colors are 0xRRGGBBAA.
+    Colors { fg: u32, bg: u32 },
+    // Each video value selects palette[value & 0x7], so off pixels use entry 0, on pixels
+    // (0xFFFFFFFF) entry 7, and XO-CHIP plane 2 and both planes entries 2 and 3. Colors are 0xRRGGBB.
+    Palette([u32; 8]),
+}
+
+impl DisplayMode {
+    // Parse 8 concatenated RRGGBB hex colors, e.g. "000000...FFFFFF"
+    pub fn from_hex_palette(hex: &str) -> Result<Self> {
+        if hex.len() != 8 * 6 || !hex.is_ascii() {
+            return Err(anyhow::anyhow!(
+                "Palette must be 8 RRGGBB colors (48 hex digits), got {} characters",
+                hex.len()
+            ));
+        }
+
+        let mut palette = [0u32; 8];
+        for (i, color) in palette.iter_mut().enumerate() {
+            let digits = &hex[i * 6..i * 6 + 6];
+            *color = u32::from_str_radix(digits, 16)
+                .map_err(|_| anyhow::anyhow!("Invalid palette color: {}", digits))?;
+        }
+
+        Ok(DisplayMode::Palette(palette))
+    }
+
+    pub fn from_colors(fg: [u8; 4], bg: [u8; 4]) -> Self {
+        DisplayMode::Colors { fg: u32::from_be_bytes(fg), bg: u32::from_be_bytes(bg) }
+    }
+
+    // One of COLOR_PRESETS by name, e.g. "amber"
+    pub fn from_preset(name: &str) -> Result<Self> {
+        let &(_, (fg, bg)) = COLOR_PRESETS.iter()
+            .find(|&&(preset, _)| preset == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown color preset: {}", name))?;
+        Ok(Self::from_colors(fg, bg))
+    }
+}
+
+// Where finished frames go. `display` holds one 0xRRGGBBAA word per pixel.
+pub trait RenderTarget: Sized {
+    fn attach(window: Arc<Window>, width: u32, height: u32) -> Result<Self>;
+    fn update(&mut self, display: &[u32], width: u32, height: u32) -> Result<()>;
+    fn commit(&mut self) -> Result<()>;
+    fn resize(&mut self, width: u32, height: u32) -> Result<()>;
+}
+
+// Renders to the window through `pixels`
+pub struct PixelsTarget {
+    pixels: Pixels<'static>,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTarget for PixelsTarget {
+    fn attach(window: Arc<Window>, width: u32, height: u32) -> Result<Self> {
+        let surface_texture = SurfaceTexture::new(width, height, window);
+        let pixels = Pixels::new(width, height, surface_texture)?;
+        Ok(Self { pixels, width, height })
+    }
+
+    fn update(&mut self, display: &[u32], width: u32, height: u32) -> Result<()> {
+        if (width, height) != (self.width, self.height) {
+            self.pixels.resize_buffer(width, height)?;
+            self.width = width;
+            self.height = height;
+        }
+
+        let frame = self.pixels.frame_mut();
+        for (pixel, &rgba) in frame.chunks_exact_mut(4).zip(display) {
+            pixel.copy_from_slice(&rgba.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.pixels.render()?;
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        self.pixels.resize_surface(width, height)?;
+        Ok(())
+    }
+}
+
+// Keeps the last committed frame in memory as RGBA bytes, for tests and headless capture
+#[allow(dead_code)]
+pub struct FramebufferTarget(pub Vec<u8>);
+
+impl RenderTarget for FramebufferTarget {
+    fn attach(_window: Arc<Window>, width: u32, height: u32) -> Result<Self> {
+        Ok(Self(vec![0; (width * height * 4) as usize]))
+    }
+
+    fn update(&mut self, display: &[u32], width: u32, height: u32) -> Result<()> {
+        self.0.resize((width * height * 4) as usize, 0);
+        for (pixel, &rgba) in self.0.chunks_exact_mut(4).zip(display) {
+            pixel.copy_from_slice(&rgba.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&mut self, _width: u32, _height: u32) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Square-wave buzzer. One endless tone is queued up front and only paused or resumed, so
+// a beep spanning many frames plays as one continuous sound. Silent without an audio device.
+struct Beeper {
+    _stream: OutputStream, // Audio stops when this is dropped
+    sink: Sink,
+    on: bool,
+}
+
+impl Beeper {
+    fn new() -> Result<Self> {
+        let (stream, handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&handle)?;
+        sink.pause();
+        let sample_rate = rodio::cpal::SampleRate(BEEP_SAMPLE_RATE);
+        sink.append(SignalGenerator::new(sample_rate, BEEP_FREQUENCY, Function::Square).amplify(BEEP_VOLUME));
+        Ok(Self { _stream: stream, sink, on: false })
+    }
+
+    fn set(&mut self, on: bool) {
+        if on == self.on {
+            return;
+        }
+        if on {
+            self.sink.play();
+        } else {
+            self.sink.pause();
+        }
+        self.on = on;
+    }
+}
+
+// What the emulator should do this frame. P toggles pause; N while paused asks for a
+// single instruction.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RunControl {
+    Run,
+    Paused, // Run nothing, keep showing the last frame
+    Step,   // Paused, but execute exactly one instruction
+}
+
+// This frame's RunControl, consuming a pending step request
+fn run_control(paused: bool, step_requested: &mut bool) -> RunControl {
+    match (paused, std::mem::take(step_requested)) {
+        (false, _) => RunControl::Run,
+        (true, false) => RunControl::Paused,
+        (true, true) => RunControl::Step,
+    }
+}
+
+// Which of our windows have keyboard focus. Moving from the main window to the inspector
+// loses one and gains the other, which shouldn't count as the user leaving.
+struct Focus {
+    main: bool,
+    inspector: bool,
+    run_unfocused: bool, // See Platform::with_background_running
+}
+
+impl Focus {
+    fn new(run_unfocused: bool) -> Self {
+        Self { main: true, inspector: false, run_unfocused }
+    }
+
+    // Whether emulation is paused, given whether the user paused it with P
+    fn paused(&self, paused: bool) -> bool {
+        paused || !(self.run_unfocused || self.main || self.inspector)
+    }
+}
 
-pub struct Platform;
+// Frames and instructions over the last second, for the window title
+struct PerfCounter {
+    since: Instant,
+    frames: u32,
+    instructions: u64,
+}
+
+impl PerfCounter {
+    fn new(now: Instant) -> Self {
+        Self { since: now, frames: 0, instructions: 0 }
+    }
+
+    // Count one frame. Once a second has passed, returns (FPS, IPS) and starts over.
+    fn frame(&mut self, instructions: u64, now: Instant) -> Option<(u32, u64)> {
+        self.frames += 1;
+        self.instructions += instructions;
+        let elapsed = now.duration_since(self.since);
+        if elapsed < Duration::from_secs(1) {
+            return None;
+        }
+        let secs = elapsed.as_secs_f64();
+        let fps = (self.frames as f64 / secs).round() as u32;
+        let ips = (self.instructions as f64 / secs).round() as u64;
+        *self = Self::new(now);
+        Some((fps, ips))
+    }
+}
+
+// "<title> — 60 FPS, 540 IPS (paused)", leaving out whatever isn't known or doesn't apply
+fn window_title(title: &str, rates: Option<(u32, u64)>, paused: bool) -> String {
+    let mut text = title.to_string();
+    if let Some((fps, ips)) = rates {
+        text += &format!(" — {} FPS, {} IPS", fps, ips);
+    }
+    if paused {
+        text += " (paused)";
+    }
+    text
+}
+
+// CRT-style persistence. A pixel that goes off fades to the background over a few frames
+// instead of vanishing, which hides most of the flicker from erase-and-redraw sprites.
+struct Phosphor {
+    intensity: Vec<u8>, // Per pixel: 255 while lit, decaying once off
+    lit: Vec<u32>,      // The video value each pixel last had while lit, for its fading color
+}
+
+impl Phosphor {
+    fn new() -> Self {
+        Self { intensity: Vec::new(), lit: Vec::new() }
+    }
+
+    // Fade the off pixels of `frame`, the colorized `chip8_display`, using earlier frames
+    fn apply(&mut self, chip8_display: &[u32], display_mode: &DisplayMode, frame: &mut [u32]) {
+        // Nothing to carry over across a resolution change
+        if self.intensity.len() != chip8_display.len() {
+            *self = Self { intensity: vec![0; chip8_display.len()], lit: vec![0; chip8_display.len()] };
+        }
+        for (i, &chip8_pixel) in chip8_display.iter().enumerate() {
+            if chip8_pixel != 0 {
+                self.intensity[i] = 255;
+                self.lit[i] = chip8_pixel;
+                continue;
+            }
+            self.intensity[i] = (self.intensity[i] as u32 * PHOSPHOR_DECAY / 256) as u8;
+            if self.intensity[i] > 0 {
+                let lit_color = pixel_color(self.lit[i], display_mode);
+                frame[i] = blend(frame[i], lit_color, self.intensity[i]);
+            }
+        }
+    }
+}
+
+// `from` moved `amount`/255 of the way to `to`, per 0xRRGGBBAA channel
+fn blend(from: u32, to: u32, amount: u8) -> u32 {
+    let (from, to) = (from.to_be_bytes(), to.to_be_bytes());
+    let channels: [u8; 4] = std::array::from_fn(|c| {
+        let (from, to) = (from[c] as i32, to[c] as i32);
+        (from + (to - from) * amount as i32 / 255) as u8
+    });
+    u32::from_be_bytes(channels)
+}
+
+// What the update function draws into each frame. The size may change between frames, e.g.
+// when a SUPER-CHIP ROM switches to 128x64; the window keeps its size and rescales.
+pub struct DisplayBuffer {
+    pub pixels: Vec<u32>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DisplayBuffer {
+    fn new(width: u32, height: u32) -> Self {
+        Self { pixels: vec![0; (width * height) as usize], width, height }
+    }
+
+    // Set the size for this frame and return the pixels to fill. Clears them if it changed.
+    pub fn resize(&mut self, width: u32, height: u32) -> &mut [u32] {
+        if (width, height) != (self.width, self.height) {
+            *self = Self::new(width, height);
+        }
+        &mut self.pixels
+    }
+}
+
+// Produces the lines shown in the inspector window
+type InspectorFn = Box<dyn FnMut() -> Vec<String>>;
+
+// Sees every frame: (frame number from 0, 0xRRGGBBAA pixels about to be shown)
+type FrameHook = Box<dyn Fn(u64, &[u32])>;
+
+// Called with true for faster (=), false for slower (-)
+type SpeedFn = Box<dyn FnMut(bool)>;
+
+// Called with the path of a file dropped on the window
+type DropFn = Box<dyn FnMut(&Path) -> Option<String>>;
+
+// Reads the clipboard as text, None if it's empty or unreadable
+type ClipboardFn = Box<dyn FnMut() -> Option<String>>;
+
+// Called with the clipboard text when Ctrl+V is pressed while paused
+type PasteFn = Box<dyn FnMut(&str)>;
+
+// Called once a frame for the gamepad button presses and releases since the last call
+type GamepadPollFn = Box<dyn FnMut() -> Vec<(GamepadButton, bool)>>;
+
+pub struct Platform<R: RenderTarget = PixelsTarget> {
+    title: String,
+    key_map: KeyMap,
+    display_mode: DisplayMode,
+    display_width: u32,
+    display_height: u32,
+    inspector: Option<InspectorFn>,
+    frame_hooks: Vec<FrameHook>,
+    speed_fn: Option<SpeedFn>,
+    drop_fn: Option<DropFn>,
+    gamepad_fn: Option<GamepadPollFn>,
+    paste: Option<(ClipboardFn, PasteFn)>,
+    phosphor: bool,
+    run_unfocused: bool,
+    target: PhantomData<R>,
+}
 
 impl Platform {
-    pub fn new(_title: &str, _window_width: u32, _window_height: u32) -> Result<Self> {
-        Ok(Self)
+    // The window is titled "FRIES-8 | <title_suffix>", or just "FRIES-8" without a suffix.
+    // `key_map` defaults to KeyMap::default().
+    pub fn new(
+        title_suffix: Option<String>,
+        _window_width: u32,
+        _window_height: u32,
+        key_map: Option<KeyMap>,
+    ) -> Result<Self> {
+        let title = match title_suffix {
+            Some(suffix) => format!("FRIES-8 | {}", suffix),
+            None => "FRIES-8".to_string(),
+        };
+        Ok(Self {
+            title,
+            key_map: key_map.unwrap_or_default(),
+            display_mode: DisplayMode::Monochrome,
+            display_width: DISPLAY_WIDTH,
+            display_height: DISPLAY_HEIGHT,
+            inspector: None,
+            frame_hooks: Vec::new(),
+            speed_fn: None,
+            drop_fn: None,
+            gamepad_fn: None,
+            paste: None,
+            phosphor: false,
+            run_unfocused: false,
+            target: PhantomData,
+        })
+    }
+}
+
+impl<R: RenderTarget> Platform<R> {
+    // Swap the render backend, keeping the rest of the configuration
+    #[allow(dead_code)]
+    pub fn with_target<T: RenderTarget>(self) -> Platform<T> {
+        Platform {
+            title: self.title,
+            key_map: self.key_map,
+            display_mode: self.display_mode,
+            display_width: self.display_width,
+            display_height: self.display_height,
+            inspector: self.inspector,
+            frame_hooks: self.frame_hooks,
+            speed_fn: self.speed_fn,
+            drop_fn: self.drop_fn,
+            gamepad_fn: self.gamepad_fn,
+            paste: self.paste,
+            phosphor: self.phosphor,
+            run_unfocused: self.run_unfocused,
+            target: PhantomData,
+        }
+    }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.display_mode = mode;
+    }
+
+    // Draw on pixels in `fg` and off pixels in `bg` (RGBA), e.g. the COLORS_AMBER preset
+    #[allow(dead_code)]
+    pub fn with_colors(mut self, fg: [u8; 4], bg: [u8; 4]) -> Self {
+        self.display_mode = DisplayMode::from_colors(fg, bg);
+        self
+    }
+
+    // Initial size of the buffer handed to the update function, 64x32 unless changed
+    pub fn with_display_size(mut self, width: u32, height: u32) -> Self {
+        self.display_width = width;
+        self.display_height = height;
+        self
+    }
+
+    // Open a second window showing the text from `lines_fn`, refreshed every frame.
+    // Closing it leaves the emulator running.
+    pub fn with_inspector(mut self, lines_fn: impl FnMut() -> Vec<String> + 'static) -> Self {
+        self.inspector = Some(Box::new(lines_fn));
+        self
+    }
+
+    // Observe each rendered frame, e.g. to record it. Hooks run in the order they were added,
+    // after the frame is uploaded and before it is presented.
+    #[allow(dead_code)]
+    pub fn with_frame_hook(mut self, hook: impl Fn(u64, &[u32]) + 'static) -> Self {
+        self.frame_hooks.push(Box::new(hook));
+        self
+    }
+
+    // Let the = and - keys change the emulation speed through `speed_fn`
+    pub fn with_speed_keys(mut self, speed_fn: impl FnMut(bool) + 'static) -> Self {
+        self.speed_fn = Some(Box::new(speed_fn));
+        self
     }
 
+    // Hand files dropped on the window to `drop_fn`, e.g. to load them as the new ROM. A
+    // returned title suffix retitles the window, like the one passed to new().
+    pub fn with_file_drop(mut self, drop_fn: impl FnMut(&Path) -> Option<String> + 'static) -> Self {
+        self.drop_fn = Some(Box::new(drop_fn));
+        self
+    }
+
+    // While paused, Ctrl+V hands the text from `clipboard_fn` to `paste_fn`, e.g. to write
+    // a hex patch over memory at PC
+    pub fn with_clipboard_paste(
+        mut self,
+        clipboard_fn: impl FnMut() -> Option<String> + 'static,
+        paste_fn: impl FnMut(&str) + 'static,
+    ) -> Self {
+        self.paste = Some((Box::new(clipboard_fn), Box::new(paste_fn)));
+        self
+    }
+
+    // Read gamepad buttons from `poll_fn` every frame, mapped to CHIP-8 keys through the
+    // KeyMap's gamepad layout. Either the keyboard or the pad can hold a key down.
+    #[allow(dead_code)]
+    pub fn with_gamepad(mut self, poll_fn: impl FnMut() -> Vec<(GamepadButton, bool)> + 'static) -> Self {
+        self.gamepad_fn = Some(Box::new(poll_fn));
+        self
+    }
+
+    // Start with the phosphor fade on or off. F3 toggles it either way.
+    pub fn with_phosphor(mut self, on: bool) -> Self {
+        self.phosphor = on;
+        self
+    }
+
+    // Keep running, and beeping, while another application has focus. By default the
+    // emulator pauses as if P were pressed until one of its windows is focused again.
+    pub fn with_background_running(mut self, on: bool) -> Self {
+        self.run_unfocused = on;
+        self
+    }
+
+    // Drive the window. Each frame `update_fn` fills the display buffer from the keypad,
+    // following the RunControl, and returns (should_quit, should_beep, instructions run),
+    // the last for the FPS and IPS shown in the title.
     pub fn run<F>(self, mut update_fn: F) -> Result<()>
     where
-        F: FnMut(&mut [bool; 16]) -> (Vec<u32>, bool) + 'static,
+        F: FnMut(&mut [bool; 16], &mut DisplayBuffer, RunControl) -> (bool, bool, u64) + 'static,
     {
         let event_loop = EventLoop::new()?;
 
         let window = {
-            let size = LogicalSize::new(640.0, 320.0);
+            let size = LogicalSize::new(
+                self.display_width as f64 * WINDOW_SCALE,
+                self.display_height as f64 * WINDOW_SCALE,
+            );
             Arc::new(
                 WindowBuilder::new()
-                    .with_title("FRIES-8")
+                    .with_title(&self.title)
                     .with_inner_size(size)
                     .with_min_inner_size(size)
                     .build(&event_loop)?
             )
         };
 
-        let mut pixels = {
-            let surface_texture = SurfaceTexture::new(
-                DISPLAY_WIDTH,
-                DISPLAY_HEIGHT,
-                window.clone()
-            );
-            Pixels::new(DISPLAY_WIDTH, DISPLAY_HEIGHT, surface_texture)?
+        let (display_width, display_height) = (self.display_width, self.display_height);
+        let mut target = R::attach(window.clone(), display_width, display_height)?;
+
+        let inspector_width = (inspector::COLUMNS * inspector::GLYPH_WIDTH) as u32;
+        let inspector_height = (inspector::ROWS * inspector::GLYPH_HEIGHT) as u32;
+        let mut inspector = match self.inspector {
+            Some(lines_fn) => {
+                let window = Arc::new(
+                    WindowBuilder::new()
+                        .with_title("FRIES-8 Inspector")
+                        .with_inner_size(LogicalSize::new(
+                            inspector_width as f64 * 4.0,
+                            inspector_height as f64 * 4.0,
+                        ))
+                        .build(&event_loop)?
+                );
+                let target = R::attach(window.clone(), inspector_width, inspector_height)?;
+                Some((window, target, lines_fn))
+            }
+            None => None,
         };
 
+        let mut beeper = Beeper::new()
+            .inspect_err(|err| eprintln!("No audio, running without sound: {}", err))
+            .ok();
+
         let mut keys = [false; 16];
+        let mut pad_keys = [false; 16];
+        let mut paused = false;
+        let mut step_requested = false;
+        let mut screenshot_requested = false;
+        let mut title = self.title;
+        let key_map = self.key_map;
+        let display_mode = self.display_mode;
+        let frame_hooks = self.frame_hooks;
+        let mut speed_fn = self.speed_fn;
+        let mut drop_fn = self.drop_fn;
+        let mut gamepad_fn = self.gamepad_fn;
+        let mut paste = self.paste;
+        let mut ctrl_down = false;
+        let mut phosphor = self.phosphor.then(Phosphor::new);
+        let mut focus = Focus::new(self.run_unfocused);
+        let mut frame_number: u64 = 0;
+        let mut perf = PerfCounter::new(Instant::now());
+        let mut rates = None;
+
+        // Reused every frame: the emulator writes into display_buffer, colorize into frame
+        let mut display_buffer = DisplayBuffer::new(display_width, display_height);
+        let mut frame = vec![0u32; (display_width * display_height) as usize];
 
         event_loop.run(move |event, control_flow| {
             match event {
+                // Events for the inspector window
+                Event::WindowEvent { window_id, event }
+                    if inspector.as_ref().is_some_and(|(w, _, _)| w.id() == window_id) =>
+                {
+                    let Some((_, target, lines_fn)) = inspector.as_mut() else { return };
+                    match event {
+                        WindowEvent::CloseRequested => {
+                            inspector = None;
+                            focus.inspector = false;
+                        }
+                        WindowEvent::Focused(focused) => focus.inspector = focused,
+                        WindowEvent::Resized(size) => {
+                            if let Err(err) = target.resize(size.width, size.height) {
+                                eprintln!("Failed to resize inspector: {}", err);
+                                inspector = None;
+                            }
+                        }
+                        WindowEvent::RedrawRequested => {
+                            let frame = inspector::render_text(&lines_fn());
+                            let result = target.update(&frame, inspector_width, inspector_height)
+                                .and_then(|_| target.commit());
+                            if let Err(err) = result {
+                                eprintln!("Failed to render inspector: {}", err);
+                                inspector = None;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     ..
@@ -65,14 +631,63 @@ impl Platform {
                 } => {
                     if let PhysicalKey::Code(key_code) = key_event.physical_key {
                         let pressed = key_event.state == ElementState::Pressed;
-                        handle_key_input(&mut keys, key_code, pressed);
+                        match key_code {
+                            KeyCode::KeyP if pressed && !key_event.repeat => {
+                                paused = !paused;
+                                window.set_title(&window_title(&title, rates, focus.paused(paused)));
+                            }
+                            KeyCode::KeyN if pressed && paused => step_requested = true,
+                            KeyCode::KeyV if pressed && ctrl_down && paused && !key_event.repeat => {
+                                if let Some((clipboard_fn, paste_fn)) = paste.as_mut() {
+                                    match clipboard_fn() {
+                                        Some(text) => paste_fn(&text),
+                                        None => eprintln!("Nothing to paste: the clipboard has no text"),
+                                    }
+                                }
+                            }
+                            KeyCode::F2 if pressed && !key_event.repeat => screenshot_requested = true,
+                            KeyCode::F3 if pressed && !key_event.repeat => {
+                                phosphor = if phosphor.is_some() { None } else { Some(Phosphor::new()) };
+                            }
+                            KeyCode::Equal | KeyCode::Minus if pressed => {
+                                if let Some(speed_fn) = speed_fn.as_mut() {
+                                    speed_fn(key_code == KeyCode::Equal);
+                                }
+                            }
+                            _ => handle_key_input(&mut keys, &key_map, key_code, pressed),
+                        }
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ModifiersChanged(modifiers),
+                    ..
+                } => ctrl_down = modifiers.state().control_key(),
+                Event::WindowEvent {
+                    event: WindowEvent::Focused(focused),
+                    ..
+                } => {
+                    focus.main = focused;
+                    // Releases go to whichever window has focus now, so forget held keys
+                    // rather than leave them stuck down
+                    if !focused {
+                        keys = [false; 16];
+                    }
+                    window.set_title(&window_title(&title, rates, focus.paused(paused)));
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::DroppedFile(path),
+                    ..
+                } => {
+                    if let Some(suffix) = drop_fn.as_mut().and_then(|drop_fn| drop_fn(&path)) {
+                        title = format!("FRIES-8 | {}", suffix);
+                        window.set_title(&window_title(&title, rates, focus.paused(paused)));
                     }
                 }
                 Event::WindowEvent {
                     event: WindowEvent::Resized(size),
                     ..
                 } => {
-                    if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                    if let Err(err) = target.resize(size.width, size.height) {
                         eprintln!("Failed to resize surface: {}", err);
                         control_flow.exit();
                     }
@@ -82,18 +697,58 @@ impl Platform {
                     ..
                 } => {
                     // Get updated display buffer from emulator
-                    let (display_buffer, should_quit) = update_fn(&mut keys);
+                    // Nothing runs while unfocused, so the sound timer keeps its value and the
+                    // beep stops until it can count down again
+                    let control = run_control(focus.paused(paused), &mut step_requested);
+                    if let Some(gamepad_fn) = gamepad_fn.as_mut() {
+                        for (button, pressed) in gamepad_fn() {
+                            handle_gamepad_input(&mut pad_keys, &key_map.gamepad, button, pressed);
+                        }
+                    }
+                    let mut input = merge_keys(&keys, &pad_keys);
+                    let (should_quit, should_beep, instructions) =
+                        update_fn(&mut input, &mut display_buffer, control);
 
                     if should_quit {
                         control_flow.exit();
                         return;
                     }
+                    if let Some(beeper) = beeper.as_mut() {
+                        beeper.set(should_beep);
+                    }
 
                     // Update the pixel buffer
-                    update_pixels(&mut pixels, &display_buffer);
+                    frame.resize(display_buffer.pixels.len(), 0);
+                    colorize(&display_buffer.pixels, &display_mode, &mut frame);
+                    if let Some(phosphor) = phosphor.as_mut() {
+                        phosphor.apply(&display_buffer.pixels, &display_mode, &mut frame);
+                    }
+                    let (width, height) = (display_buffer.width, display_buffer.height);
+                    if let Err(err) = target.update(&frame, width, height) {
+                        eprintln!("Failed to update frame: {}", err);
+                        control_flow.exit();
+                        return;
+                    }
+
+                    // Native resolution, in the colors on screen
+                    if std::mem::take(&mut screenshot_requested) {
+                        match screenshot::save(&frame, width, height) {
+                            Ok(path) => println!("Saved screenshot {}", path.display()),
+                            Err(err) => eprintln!("Failed to save screenshot: {}", err),
+                        }
+                    }
+
+                    for hook in &frame_hooks {
+                        hook(frame_number, &frame);
+                    }
+                    frame_number += 1;
+                    if let Some(new_rates) = perf.frame(instructions, Instant::now()) {
+                        rates = Some(new_rates);
+                        window.set_title(&window_title(&title, rates, focus.paused(paused)));
+                    }
 
                     // Render to screen
-                    if let Err(err) = pixels.render() {
+                    if let Err(err) = target.commit() {
                         eprintln!("Failed to render: {}", err);
                         control_flow.exit();
                     }
@@ -101,6 +756,9 @@ impl Platform {
                 Event::AboutToWait => {
                     // Request a redraw
                     window.request_redraw();
+                    if let Some((inspector_window, _, _)) = &inspector {
+                        inspector_window.request_redraw();
+                    }
                 }
                 _ => {}
             }
@@ -110,61 +768,359 @@ impl Platform {
     }
 }
 
-fn update_pixels(pixels: &mut Pixels, chip8_display: &[u32]) {
-    let frame = pixels.frame_mut();
-
-    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-        let chip8_pixel = chip8_display[i];
+// Map CHIP-8 video values to 0xRRGGBBAA colors
+fn colorize(chip8_display: &[u32], display_mode: &DisplayMode, frame: &mut [u32]) {
+    for (color, &chip8_pixel) in frame.iter_mut().zip(chip8_display) {
+        *color = pixel_color(chip8_pixel, display_mode);
+    }
+}
 
-        // Convert CHIP-8 pixel (0x00000000 or 0xFFFFFFFF) to RGBA
-        let rgba = if chip8_pixel == 0xFFFFFFFF {
-            [0xFF, 0xFF, 0xFF, 0xFF] // White
-        } else {
-            [0x00, 0x00, 0x00, 0xFF] // Black
-        };
+fn pixel_color(chip8_pixel: u32, display_mode: &DisplayMode) -> u32 {
+    match display_mode {
+        // Convert CHIP-8 pixel (0x00000000 or 0xFFFFFFFF, or a plane value) to RGBA
+        DisplayMode::Monochrome => match chip8_pixel {
+            0xFFFFFFFF => 0xFFFFFFFF, // White
+            Chip8::PLANE2_PIXEL => 0xAAAAAAFF,
+            Chip8::BOTH_PLANES_PIXEL => 0x555555FF,
+            _ => 0x000000FF, // Black
+        },
+        DisplayMode::Colors { fg, bg } => if chip8_pixel != 0 { *fg } else { *bg },
+        DisplayMode::Palette(palette) => (palette[(chip8_pixel & 0x7) as usize] << 8) | 0xFF,
+    }
+}
 
-        pixel.copy_from_slice(&rgba);
+// Lay out two displays `width` pixels wide in a buffer 2 * width + 1 wide: `left`, a divider
+// column drawn as an "on" pixel, then `right`. SPLIT_DISPLAY_WIDTH is the low-res case.
+pub fn split_screen(left: &[u32], right: &[u32], width: usize, out: &mut [u32]) {
+    for (y, row) in out.chunks_exact_mut(2 * width + 1).enumerate() {
+        row[..width].copy_from_slice(&left[y * width..(y + 1) * width]);
+        row[width] = 0xFFFFFFFF;
+        row[width + 1..].copy_from_slice(&right[y * width..(y + 1) * width]);
     }
 }
 
-fn handle_key_input(keys: &mut [bool; 16], key_code: KeyCode, pressed: bool) {
-    // Map keyboard keys to CHIP-8 keys following the tutorial's layout:
-    // Keypad       Keyboard
-    // +-+-+-+-+    +-+-+-+-+
-    // |1|2|3|C|    |1|2|3|4|
-    // +-+-+-+-+    +-+-+-+-+
-    // |4|5|6|D| => |Q|W|E|R|
-    // +-+-+-+-+    +-+-+-+-+
-    // |7|8|9|E|    |A|S|D|F|
-    // +-+-+-+-+    +-+-+-+-+
-    // |A|0|B|F|    |Z|X|C|V|
-    // +-+-+-+-+    +-+-+-+-+
+// Which physical key presses each CHIP-8 key: keys[k] is the key for CHIP-8 key k.
+// The default follows the tutorial's layout:
+// Keypad       Keyboard
+// +-+-+-+-+    +-+-+-+-+
+// |1|2|3|C|    |1|2|3|4|
+// +-+-+-+-+    +-+-+-+-+
+// |4|5|6|D| => |Q|W|E|R|
+// +-+-+-+-+    +-+-+-+-+
+// |7|8|9|E|    |A|S|D|F|
+// +-+-+-+-+    +-+-+-+-+
+// |A|0|B|F|    |Z|X|C|V|
+// +-+-+-+-+    +-+-+-+-+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyMap {
+    pub keys: [KeyCode; 16],
+    pub gamepad: GamepadMap,
+}
 
-    let chip8_key = match key_code {
-        KeyCode::Digit1 => Some(0x1),
-        KeyCode::Digit2 => Some(0x2),
-        KeyCode::Digit3 => Some(0x3),
-        KeyCode::Digit4 => Some(0xC),
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            keys: [
+                KeyCode::KeyX,   // 0
+                KeyCode::Digit1, // 1
+                KeyCode::Digit2, // 2
+                KeyCode::Digit3, // 3
+                KeyCode::KeyQ,   // 4
+                KeyCode::KeyW,   // 5
+                KeyCode::KeyE,   // 6
+                KeyCode::KeyA,   // 7
+                KeyCode::KeyS,   // 8
+                KeyCode::KeyD,   // 9
+                KeyCode::KeyZ,   // A
+                KeyCode::KeyC,   // B
+                KeyCode::Digit4, // C
+                KeyCode::KeyR,   // D
+                KeyCode::KeyF,   // E
+                KeyCode::KeyV,   // F
+            ],
+            gamepad: GamepadMap::default(),
+        }
+    }
+}
 
-        KeyCode::KeyQ => Some(0x4),
-        KeyCode::KeyW => Some(0x5),
-        KeyCode::KeyE => Some(0x6),
-        KeyCode::KeyR => Some(0xD),
+impl KeyMap {
+    // The CHIP-8 key `key_code` is mapped to, if any
+    fn chip8_key(&self, key_code: KeyCode) -> Option<usize> {
+        self.keys.iter().position(|&code| code == key_code)
+    }
+}
 
-        KeyCode::KeyA => Some(0x7),
-        KeyCode::KeyS => Some(0x8),
-        KeyCode::KeyD => Some(0x9),
-        KeyCode::KeyF => Some(0xE),
+fn handle_key_input(keys: &mut [bool; 16], key_map: &KeyMap, key_code: KeyCode, pressed: bool) {
+    if let Some(key) = key_map.chip8_key(key_code) {
+        keys[key] = pressed;
+    }
+}
 
-        KeyCode::KeyZ => Some(0xA),
-        KeyCode::KeyX => Some(0x0),
-        KeyCode::KeyC => Some(0xB),
-        KeyCode::KeyV => Some(0xF),
+// Controller buttons, named by position as in SDL and gilrs: South is A on an Xbox pad
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GamepadButton {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    South,
+    East,
+    West,
+    North,
+    Select,
+    Start,
+}
 
-        _ => None,
-    };
+// Which gamepad button presses each CHIP-8 key, like KeyMap::keys. The D-pad covers 2/4/6/8,
+// the movement keys most ROMs use, and the face buttons the keys around them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GamepadMap {
+    pub buttons: [Option<GamepadButton>; 16],
+}
+
+impl Default for GamepadMap {
+    fn default() -> Self {
+        let mut buttons = [None; 16];
+        buttons[0x2] = Some(GamepadButton::DPadUp);
+        buttons[0x4] = Some(GamepadButton::DPadLeft);
+        buttons[0x6] = Some(GamepadButton::DPadRight);
+        buttons[0x8] = Some(GamepadButton::DPadDown);
+        buttons[0x5] = Some(GamepadButton::South);
+        buttons[0x0] = Some(GamepadButton::East);
+        buttons[0xA] = Some(GamepadButton::West);
+        buttons[0xB] = Some(GamepadButton::North);
+        buttons[0xE] = Some(GamepadButton::Select);
+        buttons[0xF] = Some(GamepadButton::Start);
+        Self { buttons }
+    }
+}
 
-    if let Some(key) = chip8_key {
+fn handle_gamepad_input(keys: &mut [bool; 16], map: &GamepadMap, button: GamepadButton, pressed: bool) {
+    if let Some(key) = map.buttons.iter().position(|&mapped| mapped == Some(button)) {
         keys[key] = pressed;
     }
 }
+
+// A key is down if either the keyboard or the gamepad holds it
+fn merge_keys(keyboard: &[bool; 16], gamepad: &[bool; 16]) -> [bool; 16] {
+    std::array::from_fn(|key| keyboard[key] || gamepad[key])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gamepad_and_keyboard_coexist() {
+        let map = GamepadMap::default();
+        let mut pad_keys = [false; 16];
+        handle_gamepad_input(&mut pad_keys, &map, GamepadButton::DPadUp, true);
+        handle_gamepad_input(&mut pad_keys, &map, GamepadButton::South, true);
+        assert!(pad_keys[0x2] && pad_keys[0x5]);
+
+        let mut keys = [false; 16];
+        handle_key_input(&mut keys, &KeyMap::default(), KeyCode::KeyQ, true); // 4
+        let merged = merge_keys(&keys, &pad_keys);
+        assert!(merged[0x2] && merged[0x4] && merged[0x5]);
+        assert_eq!(merged.iter().filter(|&&down| down).count(), 3);
+
+        handle_gamepad_input(&mut pad_keys, &map, GamepadButton::DPadUp, false);
+        assert!(!merge_keys(&keys, &pad_keys)[0x2]);
+    }
+
+    #[test]
+    fn test_colorize_palette() {
+        let mut palette = [0; 8];
+        palette[0] = 0x112233;
+        palette[7] = 0xFFB000;
+        let mode = DisplayMode::Palette(palette);
+
+        let mut frame = [0; 2];
+        colorize(&[0, 0xFFFFFFFF], &mode, &mut frame);
+        assert_eq!(frame, [0x112233FF, 0xFFB000FF]);
+    }
+
+    #[test]
+    fn test_perf_counter_resets_each_second() {
+        let start = Instant::now();
+        let mut perf = PerfCounter::new(start);
+        for frame in 1..60 {
+            assert_eq!(perf.frame(9, start + Duration::from_millis(frame * 1000 / 60)), None);
+        }
+        assert_eq!(perf.frame(9, start + Duration::from_secs(1)), Some((60, 540)));
+
+        // A fresh count: one slow frame over the next two seconds
+        assert_eq!(perf.frame(100, start + Duration::from_secs(3)), Some((1, 50)));
+    }
+
+    #[test]
+    fn test_key_map() {
+        let mut keys = [false; 16];
+        handle_key_input(&mut keys, &KeyMap::default(), KeyCode::KeyX, true);
+        assert!(keys[0x0]);
+
+        // AZERTY: A and Z swap places with Q and W
+        let mut azerty = KeyMap::default();
+        azerty.keys[0x4] = KeyCode::KeyA;
+        azerty.keys[0x5] = KeyCode::KeyZ;
+        azerty.keys[0x7] = KeyCode::KeyQ;
+        azerty.keys[0xA] = KeyCode::KeyW;
+
+        let mut keys = [false; 16];
+        handle_key_input(&mut keys, &azerty, KeyCode::KeyA, true);
+        handle_key_input(&mut keys, &azerty, KeyCode::KeyW, true);
+        handle_key_input(&mut keys, &azerty, KeyCode::KeyP, true); // Unmapped
+        assert_eq!(keys.iter().filter(|&&down| down).count(), 2);
+        assert!(keys[0x4] && keys[0xA]);
+
+        handle_key_input(&mut keys, &azerty, KeyCode::KeyA, false);
+        assert!(!keys[0x4]);
+    }
+
+    #[test]
+    fn test_focus_pauses() {
+        let mut focus = Focus::new(false);
+        assert!(!focus.paused(false));
+        assert!(focus.paused(true));
+
+        // Clicking into the inspector: the main window loses focus first
+        focus.main = false;
+        focus.inspector = true;
+        assert!(!focus.paused(false));
+
+        focus.inspector = false;
+        assert!(focus.paused(false));
+        focus.run_unfocused = true;
+        assert!(!focus.paused(false));
+    }
+
+    #[test]
+    fn test_window_title() {
+        assert_eq!(window_title("FRIES-8", None, false), "FRIES-8");
+        assert_eq!(window_title("FRIES-8", Some((60, 540)), false), "FRIES-8 — 60 FPS, 540 IPS");
+        assert_eq!(window_title("FRIES-8", Some((60, 0)), true), "FRIES-8 — 60 FPS, 0 IPS (paused)");
+    }
+
+    #[test]
+    fn test_colorize_planes() {
+        let mut frame = [0; 4];
+        let video = [0, 0xFFFFFFFF, Chip8::PLANE2_PIXEL, Chip8::BOTH_PLANES_PIXEL];
+        colorize(&video, &DisplayMode::Monochrome, &mut frame);
+        assert_eq!(frame, [0x000000FF, 0xFFFFFFFF, 0xAAAAAAFF, 0x555555FF]);
+
+        colorize(&video, &DisplayMode::from_colors([1, 1, 1, 1], [2, 2, 2, 2]), &mut frame);
+        assert_eq!(frame, [0x02020202, 0x01010101, 0x01010101, 0x01010101]);
+    }
+
+    #[test]
+    fn test_colorize_colors() {
+        let platform = Platform::new(None, 0, 0, None).unwrap().with_colors([1, 2, 3, 4], [5, 6, 7, 8]);
+
+        let mut frame = [0; 2];
+        colorize(&[0xFFFFFFFF, 0], &platform.display_mode, &mut frame);
+        assert_eq!(frame, [0x01020304, 0x05060708]);
+
+        let DisplayMode::Colors { fg, .. } = DisplayMode::from_preset("amber").unwrap() else {
+            panic!("expected colors");
+        };
+        assert_eq!(fg, 0xFFB000FF);
+        assert!(DisplayMode::from_preset("purple").is_err());
+    }
+
+    #[test]
+    fn test_from_hex_palette() {
+        let hex = "000000".repeat(7) + "FFB000";
+        let DisplayMode::Palette(palette) = DisplayMode::from_hex_palette(&hex).unwrap() else {
+            panic!("expected a palette");
+        };
+        assert_eq!(palette[7], 0xFFB000);
+
+        assert!(DisplayMode::from_hex_palette("FFFFFF").is_err());
+        assert!(DisplayMode::from_hex_palette(&"GG0000".repeat(8)).is_err());
+    }
+
+    #[test]
+    fn test_phosphor_fades_off_pixels() {
+        let mode = DisplayMode::Monochrome;
+        let mut phosphor = Phosphor::new();
+        let mut show = |video: &[u32]| {
+            let mut frame = [0; 2];
+            colorize(video, &mode, &mut frame);
+            phosphor.apply(video, &mode, &mut frame);
+            frame
+        };
+
+        assert_eq!(show(&[0xFFFFFFFF, 0]), [0xFFFFFFFF, 0x000000FF]);
+        // Off again: a grey that gets darker every frame until it's back to black
+        let mut levels = Vec::new();
+        loop {
+            let [faded, untouched] = show(&[0, 0]);
+            assert_eq!(untouched, 0x000000FF);
+            if faded == 0x000000FF {
+                break;
+            }
+            let level = faded >> 24;
+            assert_eq!(faded, (level * 0x01010100) | 0xFF);
+            levels.push(level);
+        }
+        assert_eq!(levels.len(), 9); // Black on the tenth frame
+        assert!(levels.windows(2).all(|pair| pair[1] < pair[0]));
+        // Lit pixels are always at full brightness
+        assert_eq!(show(&[0xFFFFFFFF, 0])[0], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_blend() {
+        assert_eq!(blend(0x000000FF, 0xFFFFFFFF, 0), 0x000000FF);
+        assert_eq!(blend(0x000000FF, 0xFFFFFFFF, 255), 0xFFFFFFFF);
+        assert_eq!(blend(0xFF0000FF, 0x00FF00FF, 51), 0xCC3300FF);
+    }
+
+    #[test]
+    fn test_framebuffer_target() {
+        let mut target = FramebufferTarget(Vec::new());
+        target.update(&[0x11223344, 0x55667788], 2, 1).unwrap();
+        target.commit().unwrap();
+
+        assert_eq!(target.0, vec![0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+    }
+
+    #[test]
+    fn test_run_control() {
+        let mut step = true;
+        assert_eq!(run_control(false, &mut step), RunControl::Run);
+        assert!(!step); // Steps only count while paused
+
+        assert_eq!(run_control(true, &mut step), RunControl::Paused);
+        step = true;
+        assert_eq!(run_control(true, &mut step), RunControl::Step);
+        assert_eq!(run_control(true, &mut step), RunControl::Paused);
+    }
+
+    #[test]
+    fn test_display_buffer_resize() {
+        let mut buffer = DisplayBuffer::new(64, 32);
+        buffer.resize(64, 32)[0] = 1;
+        assert_eq!(buffer.pixels[0], 1); // Same size, kept
+
+        assert_eq!(buffer.resize(128, 64).len(), 128 * 64);
+        assert_eq!((buffer.width, buffer.height), (128, 64));
+        assert_eq!(buffer.pixels[0], 0);
+    }
+
+    #[test]
+    fn test_split_screen() {
+        let size = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize;
+        let left = vec![1; size];
+        let right = vec![2; size];
+        let mut out = vec![0; (SPLIT_DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize];
+
+        split_screen(&left, &right, DISPLAY_WIDTH as usize, &mut out);
+
+        let row = &out[SPLIT_DISPLAY_WIDTH as usize..2 * SPLIT_DISPLAY_WIDTH as usize];
+        assert!(row[..64].iter().all(|&p| p == 1));
+        assert_eq!(row[64], 0xFFFFFFFF);
+        assert!(row[65..].iter().all(|&p| p == 2));
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// PNGs are written by hand: 8-bit RGBA, with the image data in uncompressed deflate blocks.
+// CHIP-8 frames are small enough that compression isn't worth a dependency.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+// Save a frame of 0xRRGGBBAA pixels as fries-<unix time in ms>.png in the working directory
+pub fn save(frame: &[u32], width: u32, height: u32) -> Result<PathBuf> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = PathBuf::from(format!("fries-{}.png", millis));
+    fs::write(&path, encode_png(frame, width, height))
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+fn encode_png(frame: &[u32], width: u32, height: u32) -> Vec<u8> {
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]); // 8 bits, RGBA, deflate, no filter, no interlace
+
+    // Each scanline starts with its filter type, 0 for none
+    let mut scanlines = Vec::with_capacity(frame.len() * 4 + height as usize);
+    for row in frame.chunks_exact(width as usize) {
+        scanlines.push(0);
+        scanlines.extend(row.iter().flat_map(|pixel| pixel.to_be_bytes()));
+    }
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// Length, type, data, then a CRC over type and data
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01]; // Deflate, 32K window, no dictionary
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]); // A single empty final block
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 }
+        })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let png = encode_png(&[0xFFB000FF, 0x000000FF], 2, 1);
+
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+
+        // IDAT: zlib header, one final stored block holding the filter byte and both pixels
+        let idat = &png[33..];
+        assert_eq!(&idat[4..8], b"IDAT");
+        assert_eq!(idat[8..15], [0x78, 0x01, 1, 9, 0, !9, 0xFF]);
+        assert_eq!(idat[15..24], [0, 0xFF, 0xB0, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_zlib_stored_splits_blocks() {
+        let data = vec![0xAB; MAX_STORED_BLOCK + 1];
+        let stream = zlib_stored(&data);
+
+        assert_eq!(stream[2], 0); // First block isn't final
+        let second = 2 + 5 + MAX_STORED_BLOCK;
+        assert_eq!(stream[second..second + 5], [1, 1, 0, 0xFE, 0xFF]);
+        assert_eq!(stream.len(), 2 + 2 * 5 + data.len() + 4);
+    }
+}
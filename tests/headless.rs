@@ -0,0 +1,48 @@
+// Runs programs through the library alone, the way --headless does, and checks where they end up
+
+use fries::chip8::Chip8;
+
+#[test]
+fn headless_sum_loop() {
+    let mut chip8 = Chip8::new();
+    chip8.enable_debug(false);
+    chip8.load_rom_bytes(&[
+        0x60, 0x00, // 200: LD V0, 0
+        0x61, 0x05, // 202: LD V1, 5
+        0x80, 0x14, // 204: ADD V0, V1
+        0x71, 0xFF, // 206: ADD V1, -1
+        0x31, 0x00, // 208: SE V1, 0
+        0x12, 0x04, // 20A: JP 0x204
+        0x12, 0x0C, // 20C: JP 0x20C
+    ], true).unwrap();
+
+    chip8.run_cycles(100);
+
+    let state = chip8.save_state();
+    assert_eq!(state.registers[0], 5 + 4 + 3 + 2 + 1);
+    assert_eq!(state.registers[1], 0);
+    assert_eq!(state.pc, 0x20C);
+    assert_eq!(chip8.get_cycle_count(), 100);
+}
+
+// What --seed is for: the same seed and input give the same run, RND included
+#[test]
+fn headless_seeded_runs_match() {
+    let program = [
+        0xC0, 0x3F, // 200: RND V0, 0x3F
+        0xC1, 0x1F, // 202: RND V1, 0x1F
+        0xA0, 0x50, // 204: LD I, font '0'
+        0xD0, 0x15, // 206: DRW V0, V1, 5
+        0x12, 0x00, // 208: JP 0x200
+    ];
+    let run = |seed| {
+        let mut chip8 = Chip8::new().with_seed(seed);
+        chip8.enable_debug(false);
+        chip8.load_rom_bytes(&program, true).unwrap();
+        chip8.run_cycles(500);
+        (chip8.save_state(), chip8.display_hash())
+    };
+
+    assert!(run(42) == run(42));
+    assert!(run(42) != run(43));
+}
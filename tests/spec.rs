@@ -0,0 +1,335 @@
+// One test per opcode against Cowgod's CHIP-8 Technical Reference. Each test builds a known
+// state, runs the opcode with execute_opcode and checks that the whole machine ends up exactly
+// as the reference says: the changes it lists, and nothing else.
+//
+// execute_opcode doesn't fetch, so PC already points at the next instruction and only moves
+// when the opcode itself moves it (jumps, calls, skips).
+//
+// The reference describes the later interpreters rather than the VIP, so every quirk is off.
+
+use fries::chip8::{Chip8, Chip8State, QuirksConfig, StateDiff};
+
+const START: u16 = 0x200;
+
+fn machine() -> Chip8 {
+    let mut chip8 = Chip8::new().with_deterministic_sequence(&[0xA5]);
+    chip8.enable_debug(false);
+    chip8.set_quirks(QuirksConfig::from_flags(0));
+    chip8
+}
+
+// Set registers with LD Vx, byte, so the setup itself goes through the reference behavior
+fn with_registers(values: &[(usize, u8)]) -> Chip8 {
+    let mut chip8 = machine();
+    for &(x, kk) in values {
+        chip8.execute_opcode(0x6000 | (x as u16) << 8 | kk as u16);
+    }
+    chip8
+}
+
+// Run `opcode` and check the result against `before` with `expect` applied to it
+fn assert_transition(chip8: &mut Chip8, opcode: u16, expect: impl FnOnce(&mut Chip8State)) {
+    let before = chip8.save_state();
+    let mut expected = before.clone();
+    expect(&mut expected);
+
+    chip8.execute_opcode(opcode);
+    let after = chip8.save_state();
+
+    // The diff covers every field, so a failure names the one that's wrong
+    assert_eq!(Chip8State::diff(&expected, &after), StateDiff::default(), "opcode {:04X}", opcode);
+}
+
+#[test]
+fn spec_00e0_cls() {
+    let mut chip8 = with_registers(&[(0, 10), (1, 10)]);
+    chip8.execute_opcode(0xA050); // LD I, font '0'
+    chip8.execute_opcode(0xD015); // DRW V0, V1, 5
+    chip8.execute_opcode(0x6F00); // Drawing set VF; clear it so only the display differs
+
+    assert_transition(&mut chip8, 0x00E0, |s| s.video.fill(0));
+}
+
+#[test]
+fn spec_00ee_ret() {
+    let mut chip8 = machine();
+    chip8.execute_opcode(0x2400); // CALL 0x400, pushing 0x200
+
+    assert_transition(&mut chip8, 0x00EE, |s| {
+        s.sp = 0;
+        s.pc = START;
+    });
+}
+
+#[test]
+fn spec_1nnn_jp() {
+    assert_transition(&mut machine(), 0x1345, |s| s.pc = 0x345);
+}
+
+#[test]
+fn spec_2nnn_call() {
+    assert_transition(&mut machine(), 0x2345, |s| {
+        s.stack[0] = START;
+        s.sp = 1;
+        s.pc = 0x345;
+    });
+}
+
+#[test]
+fn spec_3xkk_se_byte() {
+    let mut chip8 = with_registers(&[(3, 0x42)]);
+    assert_transition(&mut chip8, 0x3342, |s| s.pc += 2);
+    assert_transition(&mut chip8, 0x3343, |_| {});
+}
+
+#[test]
+fn spec_4xkk_sne_byte() {
+    let mut chip8 = with_registers(&[(3, 0x42)]);
+    assert_transition(&mut chip8, 0x4342, |_| {});
+    assert_transition(&mut chip8, 0x4343, |s| s.pc += 2);
+}
+
+#[test]
+fn spec_5xy0_se_register() {
+    let mut chip8 = with_registers(&[(1, 7), (2, 7), (3, 8)]);
+    assert_transition(&mut chip8, 0x5120, |s| s.pc += 2);
+    assert_transition(&mut chip8, 0x5130, |_| {});
+}
+
+#[test]
+fn spec_6xkk_ld_byte() {
+    assert_transition(&mut machine(), 0x6A55, |s| s.registers[0xA] = 0x55);
+}
+
+#[test]
+fn spec_7xkk_add_byte() {
+    // Wraps without touching VF
+    let mut chip8 = with_registers(&[(2, 0xFF), (0xF, 0x33)]);
+    assert_transition(&mut chip8, 0x7202, |s| s.registers[2] = 0x01);
+}
+
+#[test]
+fn spec_8xy0_ld_register() {
+    let mut chip8 = with_registers(&[(1, 0x12), (2, 0x34)]);
+    assert_transition(&mut chip8, 0x8120, |s| s.registers[1] = 0x34);
+}
+
+#[test]
+fn spec_8xy1_or() {
+    let mut chip8 = with_registers(&[(1, 0b1100), (2, 0b1010)]);
+    assert_transition(&mut chip8, 0x8121, |s| s.registers[1] = 0b1110);
+}
+
+#[test]
+fn spec_8xy2_and() {
+    let mut chip8 = with_registers(&[(1, 0b1100), (2, 0b1010)]);
+    assert_transition(&mut chip8, 0x8122, |s| s.registers[1] = 0b1000);
+}
+
+#[test]
+fn spec_8xy3_xor() {
+    let mut chip8 = with_registers(&[(1, 0b1100), (2, 0b1010)]);
+    assert_transition(&mut chip8, 0x8123, |s| s.registers[1] = 0b0110);
+}
+
+#[test]
+fn spec_8xy4_add_carry() {
+    let mut chip8 = with_registers(&[(1, 0xF0), (2, 0x20)]);
+    assert_transition(&mut chip8, 0x8124, |s| {
+        s.registers[1] = 0x10;
+        s.registers[0xF] = 1;
+    });
+    assert_transition(&mut chip8, 0x8124, |s| {
+        s.registers[1] = 0x30;
+        s.registers[0xF] = 0;
+    });
+}
+
+#[test]
+fn spec_8xy5_sub_not_borrow() {
+    let mut chip8 = with_registers(&[(1, 0x30), (2, 0x10)]);
+    assert_transition(&mut chip8, 0x8125, |s| {
+        s.registers[1] = 0x20;
+        s.registers[0xF] = 1;
+    });
+
+    let mut chip8 = with_registers(&[(1, 0x10), (2, 0x30), (0xF, 1)]);
+    assert_transition(&mut chip8, 0x8125, |s| {
+        s.registers[1] = 0xE0;
+        s.registers[0xF] = 0;
+    });
+}
+
+#[test]
+fn spec_8xy6_shr() {
+    let mut chip8 = with_registers(&[(1, 0b0000_0101), (2, 0xFF)]);
+    assert_transition(&mut chip8, 0x8126, |s| {
+        s.registers[1] = 0b0000_0010;
+        s.registers[0xF] = 1;
+    });
+}
+
+#[test]
+fn spec_8xy7_subn_not_borrow() {
+    let mut chip8 = with_registers(&[(1, 0x10), (2, 0x30)]);
+    assert_transition(&mut chip8, 0x8127, |s| {
+        s.registers[1] = 0x20;
+        s.registers[0xF] = 1;
+    });
+}
+
+#[test]
+fn spec_8xye_shl() {
+    let mut chip8 = with_registers(&[(1, 0b1000_0001), (2, 0x00)]);
+    assert_transition(&mut chip8, 0x812E, |s| {
+        s.registers[1] = 0b0000_0010;
+        s.registers[0xF] = 1;
+    });
+}
+
+#[test]
+fn spec_9xy0_sne_register() {
+    let mut chip8 = with_registers(&[(1, 7), (2, 7), (3, 8)]);
+    assert_transition(&mut chip8, 0x9120, |_| {});
+    assert_transition(&mut chip8, 0x9130, |s| s.pc += 2);
+}
+
+#[test]
+fn spec_annn_ld_i() {
+    assert_transition(&mut machine(), 0xA123, |s| s.index = 0x123);
+}
+
+#[test]
+fn spec_bnnn_jp_v0() {
+    let mut chip8 = with_registers(&[(0, 0x10), (1, 0x20)]);
+    assert_transition(&mut chip8, 0xB300, |s| s.pc = 0x310);
+}
+
+#[test]
+fn spec_cxkk_rnd() {
+    // The RNG always yields 0xA5
+    assert_transition(&mut machine(), 0xC40F, |s| s.registers[4] = 0x05);
+}
+
+#[test]
+fn spec_dxyn_drw() {
+    let mut chip8 = with_registers(&[(0, 64 + 62), (1, 0)]);
+    chip8.execute_opcode(0xA050); // LD I, font '0', whose top row is 0xF0
+
+    // The reference wraps the whole sprite; like the VIP, only the start position wraps
+    // here and the sprite is clipped at the right edge
+    assert_transition(&mut chip8, 0xD011, |s| {
+        s.video[62] = 0xFFFFFFFF;
+        s.video[63] = 0xFFFFFFFF;
+        s.registers[0xF] = 0;
+    });
+    // Drawing the same row again erases it and reports the collision
+    assert_transition(&mut chip8, 0xD011, |s| {
+        s.video.fill(0);
+        s.registers[0xF] = 1;
+    });
+}
+
+#[test]
+fn spec_ex9e_skp() {
+    let mut keys = [false; 16];
+    keys[0xB] = true;
+    let mut chip8 = with_registers(&[(1, 0xB), (2, 0xC)]);
+    chip8.set_keys(&keys);
+
+    assert_transition(&mut chip8, 0xE19E, |s| s.pc += 2);
+    assert_transition(&mut chip8, 0xE29E, |_| {});
+}
+
+#[test]
+fn spec_exa1_sknp() {
+    let mut keys = [false; 16];
+    keys[0xB] = true;
+    let mut chip8 = with_registers(&[(1, 0xB), (2, 0xC)]);
+    chip8.set_keys(&keys);
+
+    assert_transition(&mut chip8, 0xE1A1, |_| {});
+    assert_transition(&mut chip8, 0xE2A1, |s| s.pc += 2);
+}
+
+#[test]
+fn spec_fx07_ld_from_dt() {
+    let mut chip8 = with_registers(&[(0, 0x3C)]);
+    chip8.execute_opcode(0xF015); // LD DT, V0
+
+    assert_transition(&mut chip8, 0xF307, |s| s.registers[3] = 0x3C);
+}
+
+#[test]
+fn spec_fx0a_ld_key() {
+    let mut chip8 = machine();
+    // No key: PC backs up so the instruction runs again
+    assert_transition(&mut chip8, 0xF50A, |s| s.pc -= 2);
+
+    // The reference only mentions the press; like the VIP, the key also has to be released
+    let mut keys = [false; 16];
+    keys[9] = true;
+    chip8.set_keys(&keys);
+    assert_transition(&mut chip8, 0xF50A, |s| s.pc -= 2);
+
+    chip8.set_keys(&[false; 16]);
+    assert_transition(&mut chip8, 0xF50A, |s| s.registers[5] = 9);
+}
+
+#[test]
+fn spec_fx15_ld_dt() {
+    let mut chip8 = with_registers(&[(2, 0x20)]);
+    assert_transition(&mut chip8, 0xF215, |s| s.delay_timer = 0x20);
+}
+
+#[test]
+fn spec_fx18_ld_st() {
+    let mut chip8 = with_registers(&[(2, 0x20)]);
+    assert_transition(&mut chip8, 0xF218, |s| s.sound_timer = 0x20);
+}
+
+#[test]
+fn spec_fx1e_add_i() {
+    let mut chip8 = with_registers(&[(2, 0x20)]);
+    chip8.execute_opcode(0xA300);
+
+    assert_transition(&mut chip8, 0xF21E, |s| s.index = 0x320);
+}
+
+#[test]
+fn spec_fx29_ld_font() {
+    let mut chip8 = with_registers(&[(2, 0xA)]);
+    assert_transition(&mut chip8, 0xF229, |s| s.index = 0x050 + 5 * 0xA);
+}
+
+#[test]
+fn spec_fx33_ld_bcd() {
+    let mut chip8 = with_registers(&[(2, 254)]);
+    chip8.execute_opcode(0xA300);
+
+    assert_transition(&mut chip8, 0xF233, |s| {
+        s.memory[0x300..0x303].copy_from_slice(&[2, 5, 4]);
+    });
+}
+
+#[test]
+fn spec_fx55_ld_store() {
+    let mut chip8 = with_registers(&[(0, 1), (1, 2), (2, 3), (3, 4)]);
+    chip8.execute_opcode(0xA300);
+
+    // V3 isn't stored, and I stays put
+    assert_transition(&mut chip8, 0xF255, |s| {
+        s.memory[0x300..0x303].copy_from_slice(&[1, 2, 3]);
+    });
+}
+
+#[test]
+fn spec_fx65_ld_load() {
+    let mut chip8 = machine();
+    chip8.load_rom_bytes(&[9, 8, 7, 6], true).unwrap();
+    chip8.execute_opcode(0xA200);
+
+    assert_transition(&mut chip8, 0xF265, |s| {
+        s.registers[..3].copy_from_slice(&[9, 8, 7]);
+    });
+}